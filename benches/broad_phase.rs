@@ -0,0 +1,175 @@
+//! Compares the persistent broad phase against rebuilding it every frame,
+//! with 10k static and 500 semikinematic shapes that move every frame.
+//!
+//! Both run the broad phase and iterate over all the potentially colliding
+//! pairs, like the narrow phase does.
+
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use physme::dim2::{broad_phase_system, fixed_timestep_system};
+use physme::prelude2d::*;
+use rstar::{RTree, RTreeObject, AABB};
+
+const STATICS: usize = 10_000;
+const DYNAMICS: usize = 500;
+
+fn spawn_world() -> (World, Resources) {
+    let mut world = World::new();
+    let mut resources = Resources::default();
+    resources.insert(BroadPhase::default());
+    // deterministic mode takes a step every frame, no matter the frame time
+    resources.insert(FixedTimestep::default().with_deterministic(true));
+    resources.insert(Time::default());
+
+    for i in 0..STATICS {
+        let position = Vec2::new((i % 100) as f32 * 16.0, (i / 100) as f32 * 16.0);
+        let shape = world.spawn((Shape::from(Size2::new(16.0, 16.0)),));
+        world.spawn((
+            RigidBody::new(Mass::Infinite)
+                .with_status(Status::Static)
+                .with_position(position),
+            Children::with(&[shape]),
+        ));
+    }
+
+    for i in 0..DYNAMICS {
+        let position = Vec2::new((i % 25) as f32 * 64.0 + 8.0, (i / 25) as f32 * 64.0 + 8.0);
+        let shape = world.spawn((Shape::from(Size2::new(12.0, 12.0)),));
+        world.spawn((
+            RigidBody::new(Mass::Real(1.0))
+                .with_status(Status::Semikinematic)
+                .with_position(position),
+            Children::with(&[shape]),
+        ));
+    }
+
+    (world, resources)
+}
+
+/// A collider as the broad phase used to store it, before it was persistent.
+struct Baseline {
+    status: Status,
+    body: Entity,
+    envelope: AABB<[f32; 2]>,
+}
+
+impl RTreeObject for Baseline {
+    type Envelope = AABB<[f32; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+/// The broad phase as it used to be, a single tree of every collider.
+#[derive(Default)]
+struct Rebuilt(RTree<Baseline>);
+
+/// Bulk loads the tree from every collider, every frame, like the broad phase used to.
+fn rebuild_system(
+    mut rebuilt: ResMut<Rebuilt>,
+    mut query: Query<(Entity, &RigidBody, &Children)>,
+    shapes: Query<&Shape>,
+) {
+    let mut colliders = Vec::new();
+    for (entity, body, children) in &mut query.iter() {
+        for &e in children.iter() {
+            if let Ok(shape) = shapes.get::<Shape>(e) {
+                // all shapes of this benchmark are rectangles centered on their body
+                if let ShapeKind::Rectangle(size) = *shape.kind() {
+                    let half = Vec2::new(size.width, size.height) * 0.5;
+                    let min = body.position - half;
+                    let max = body.position + half;
+                    colliders.push(Baseline {
+                        status: body.status,
+                        body: entity,
+                        envelope: AABB::from_corners([min.x(), min.y()], [max.x(), max.y()]),
+                    });
+                }
+            }
+        }
+    }
+    rebuilt.0 = RTree::bulk_load(colliders);
+}
+
+/// Iterates over the pairs of the rebuilt tree like `BroadPhase::iter` used to,
+/// static colliders first, each against the whole tree.
+fn rebuilt_pairs_system(rebuilt: Res<Rebuilt>) {
+    let tree = &rebuilt.0;
+    let statics = tree
+        .iter()
+        .filter(|collider| collider.status == Status::Static);
+    let semiks = tree
+        .iter()
+        .filter(|collider| collider.status == Status::Semikinematic);
+    let pairs = statics
+        .chain(semiks)
+        .flat_map(move |collider1| {
+            tree.locate_in_envelope_intersecting(&collider1.envelope)
+                .map(move |collider2| (collider1.body, collider2.body))
+        })
+        .count();
+    black_box(pairs);
+}
+
+fn pairs_system(broad: Res<BroadPhase>) {
+    black_box(broad.iter().count());
+}
+
+/// Moves the semikinematic bodies a little, like the physics step would.
+fn move_system(mut query: Query<Mut<RigidBody>>) {
+    for mut body in &mut query.iter() {
+        if body.status == Status::Semikinematic {
+            *body.position.x_mut() += 1.0;
+        }
+    }
+}
+
+fn broad_phase(c: &mut Criterion) {
+    let mut group = c.benchmark_group("broad_phase");
+
+    let (mut world, mut resources) = spawn_world();
+    let mut schedule = Schedule::default();
+    schedule.add_stage("fixed_timestep");
+    schedule.add_system_to_stage("fixed_timestep", fixed_timestep_system.system());
+    schedule.add_stage_after("fixed_timestep", "move");
+    schedule.add_system_to_stage("move", move_system.system());
+    schedule.add_stage_after("move", "broad_phase");
+    schedule.add_system_to_stage("broad_phase", rebuild_system.system());
+    schedule.add_stage_after("broad_phase", "pairs");
+    schedule.add_system_to_stage("pairs", rebuilt_pairs_system.system());
+    resources.insert(Rebuilt::default());
+    schedule.initialize(&mut world, &mut resources);
+    group.bench_function("rebuild", |b| {
+        b.iter(|| {
+            schedule.run(&mut world, &mut resources);
+            world.clear_trackers();
+        })
+    });
+
+    let (mut world, mut resources) = spawn_world();
+    let mut schedule = Schedule::default();
+    schedule.add_stage("fixed_timestep");
+    schedule.add_system_to_stage("fixed_timestep", fixed_timestep_system.system());
+    schedule.add_stage_after("fixed_timestep", "move");
+    schedule.add_system_to_stage("move", move_system.system());
+    schedule.add_stage_after("move", "broad_phase");
+    schedule.add_system_to_stage("broad_phase", broad_phase_system.system());
+    schedule.add_stage_after("broad_phase", "pairs");
+    schedule.add_system_to_stage("pairs", pairs_system.system());
+    schedule.initialize(&mut world, &mut resources);
+    schedule.run(&mut world, &mut resources);
+    // only the bodies changed since the last frame are refreshed, like in an `App`
+    world.clear_trackers();
+    group.bench_function("persistent", |b| {
+        b.iter(|| {
+            schedule.run(&mut world, &mut resources);
+            world.clear_trackers();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, broad_phase);
+criterion_main!(benches);
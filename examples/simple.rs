@@ -3,7 +3,6 @@ use physme::prelude2d::*;
 
 #[derive(Default)]
 pub struct CharacterController {
-    on_ground: bool,
     jump: bool,
 }
 
@@ -17,8 +16,7 @@ fn main() {
         .add_resource(GlobalStep(15.0))
         .add_resource(GlobalUp(Vec2::new(0.0, 1.0)))
         .add_startup_system(setup.system());
-    let character_system = CharacterControllerSystem::default().system(builder.resources_mut());
-    builder.add_system(character_system);
+    builder.add_system(character_system.system());
     builder.run();
 }
 
@@ -46,6 +44,7 @@ fn setup(
                 .with_angular_terminal(7.8),
         )
         .with(CharacterController::default())
+        .with(Grounded::default().with_coyote_time(0.1))
         .with_children(|parent| {
             parent.spawn((Shape::from(Size2::new(28.0, 28.0)),));
         })
@@ -120,6 +119,7 @@ fn setup(
                 .with_status(Status::Static)
                 .with_position(Vec2::new(-120.0, 20.0)),
         )
+        .with(OneWay::default())
         .with_children(|parent| {
             parent.spawn((Shape::from(Size2::new(120.0, 20.0)),));
         })
@@ -153,44 +153,20 @@ fn setup(
         ));
 }
 
-#[derive(Default)]
-pub struct CharacterControllerSystem {
-    reader: EventReader<Manifold>,
-}
-
-impl CharacterControllerSystem {
-    pub fn system(self, res: &mut Resources) -> Box<dyn System> {
-        let system = character_system.system();
-        res.insert_local(system.id(), self);
-        system
-    }
-}
-
 fn character_system(
-    mut state: Local<CharacterControllerSystem>,
     input: Res<Input<KeyCode>>,
-    manifolds: Res<Events<Manifold>>,
-    mut query: Query<(Mut<CharacterController>, Mut<RigidBody>)>,
+    mut query: Query<(Mut<CharacterController>, Mut<Grounded>, Mut<RigidBody>)>,
 ) {
-    for manifold in state.reader.iter(&manifolds) {
-        if manifold.normal.y() < 0.0 {
-            if let Ok(mut controller) = query.get_mut::<CharacterController>(manifold.body1) {
-                controller.on_ground = true;
-            }
-        } else if manifold.normal.y() > 0.0 {
-            if let Ok(mut controller) = query.get_mut::<CharacterController>(manifold.body2) {
-                controller.on_ground = true;
-            }
-        }
-    }
-
-    for (mut controller, mut body) in &mut query.iter() {
-        if input.just_pressed(KeyCode::Space) || input.just_pressed(KeyCode::W) {
+    for (mut controller, mut grounded, mut body) in &mut query.iter() {
+        if input.pressed(KeyCode::S) && input.just_pressed(KeyCode::Space) {
+            body.drop_through(0.25);
+        } else if input.just_pressed(KeyCode::Space) || input.just_pressed(KeyCode::W) {
             controller.jump = true;
         }
-        if controller.on_ground {
+        if grounded.can_jump() {
             if controller.jump {
                 body.apply_force(Vec2::new(0.0, 12000.0));
+                grounded.jump();
                 controller.jump = false;
             }
         }
@@ -202,6 +178,5 @@ fn character_system(
             body.apply_linear_impulse(Vec2::new(5.0, 0.0));
             body.apply_angular_impulse(-1.0);
         }
-        controller.on_ground = false;
     }
 }
@@ -2,7 +2,6 @@ use bevy::prelude::*;
 use physme::prelude3d::*;
 
 pub struct CharacterController {
-    on_ground: bool,
     jump: bool,
     camera: Entity,
 }
@@ -10,7 +9,6 @@ pub struct CharacterController {
 impl CharacterController {
     pub fn new(camera: Entity) -> Self {
         Self {
-            on_ground: false,
             jump: false,
             camera,
         }
@@ -25,8 +23,7 @@ fn main() {
         .add_resource(GlobalFriction(0.90))
         .add_resource(GlobalStep(0.5))
         .add_startup_system(setup.system());
-    let character_system = CharacterControllerSystem::default().system(builder.resources_mut());
-    builder.add_system(character_system);
+    builder.add_system(character_system.system());
     builder.run();
 }
 
@@ -69,6 +66,7 @@ fn setup(
         .with(Up::default())
         .with(UpRotation::default())
         .with(CharacterController::new(camera.unwrap()))
+        .with(Grounded::default().with_coyote_time(0.1))
         .with_children(|parent| {
             parent.spawn((Shape::from(Size3::new(1.0, 1.0, 1.0)),));
         })
@@ -145,52 +143,26 @@ fn setup(
         });
 }
 
-#[derive(Default)]
-pub struct CharacterControllerSystem {
-    reader: EventReader<Manifold>,
-}
-
-impl CharacterControllerSystem {
-    pub fn system(self, res: &mut Resources) -> Box<dyn System> {
-        let system = character_system.system();
-        res.insert_local(system.id(), self);
-        system
-    }
-}
-
 fn character_system(
-    mut state: Local<CharacterControllerSystem>,
     time: Res<Time>,
     input: Res<Input<KeyCode>>,
-    manifolds: Res<Events<Manifold>>,
-    up: Res<GlobalUp>,
-    ang_tol: Res<AngularTolerance>,
-    mut query: Query<(Mut<CharacterController>, Mut<RigidBody>, Mut<UpRotation>)>,
+    mut query: Query<(
+        Mut<CharacterController>,
+        Mut<Grounded>,
+        Mut<RigidBody>,
+        Mut<UpRotation>,
+    )>,
     camera: Query<Mut<Transform>>,
 ) {
     let delta_time = time.delta.as_secs_f32();
-    for manifold in state.reader.iter(&manifolds) {
-        let dot = up.0.dot(manifold.normal);
-        let angle = (-dot).acos();
-        let angle2 = dot.acos();
-        if angle >= 0.0 && angle < ang_tol.0 {
-            if let Ok(mut controller) = query.get_mut::<CharacterController>(manifold.body1) {
-                controller.on_ground = true;
-            }
-        } else if angle2 >= 0.0 && angle2 < ang_tol.0 {
-            if let Ok(mut controller) = query.get_mut::<CharacterController>(manifold.body2) {
-                controller.on_ground = true;
-            }
-        }
-    }
-
-    for (mut controller, mut body, mut rotation) in &mut query.iter() {
+    for (mut controller, mut grounded, mut body, mut rotation) in &mut query.iter() {
         if input.just_pressed(KeyCode::Space) {
             controller.jump = true;
         }
-        if controller.on_ground {
+        if grounded.can_jump() {
             if controller.jump {
                 body.apply_force(Vec3::new(0.0, 500.0, 0.0));
+                grounded.jump();
                 controller.jump = false;
             }
         }
@@ -216,7 +188,6 @@ fn character_system(
             let impulse = body.rotation * Vec3::new(0.5, 0.0, 0.0);
             body.apply_linear_impulse(impulse);
         }
-        controller.on_ground = false;
 
         let pitch = rotation.0;
         if let Ok(mut transform) = camera.get_mut::<Transform>(controller.camera) {
@@ -1,12 +1,16 @@
 //! This module provides the broad phase using an R*-tree.  You shouldn't
 //! have to use it directly.
 
+use std::mem;
 use std::ops::{Deref, DerefMut};
 
 use bevy::math::*;
-use rstar::{Point, RTree, RTreeObject, AABB};
+use bevy::prelude::*;
+use hashbrown::{HashMap, HashSet};
+use rstar::{Envelope, Point, RTree, RTreeObject, SelectionFunction, AABB};
+use smallvec::{smallvec, SmallVec};
 
-use crate::common::Status;
+use crate::common::{CollisionLayers, Status};
 use crate::dim2;
 use crate::dim3;
 
@@ -150,8 +154,14 @@ where
 
     /// Get the `BoundingBox` of this collider.
     fn bounding_box(&self) -> BoundingBox<Self::Point>;
-    /// Get the `Status` of this collider (Static or Semikinematic).
+    /// Get the `Status` of this collider.
     fn status(&self) -> Status;
+    /// Get the entity of the `RigidBody` this collider belongs to.
+    fn body(&self) -> Entity;
+    /// Get the entity of the `Shape` this collider was created from.
+    fn shape(&self) -> Entity;
+    /// Get the `CollisionLayers` of this collider.
+    fn layers(&self) -> CollisionLayers;
 }
 
 impl RTreeObject for dim2::Obb {
@@ -171,9 +181,60 @@ impl RTreeObject for dim3::Obb {
 }
 
 /// The broad phase, using an R*-tree.
-#[derive(Default, Debug, Clone)]
+///
+/// The broad phase is persistent.  Static and semikinematic colliders are kept
+/// in their own trees, and only the colliders of bodies and shapes that were
+/// added, changed or removed since the last step are taken out and put back in.
+#[derive(Debug, Clone)]
 pub struct BroadPhase<T: RTreeObject> {
-    rstar: RTree<T>,
+    statics: RTree<T>,
+    dynamics: RTree<T>,
+    // the envelope of the collider of every shape, and whether it's in the static tree
+    envelopes: HashMap<Entity, (T::Envelope, bool)>,
+    shapes: HashMap<Entity, SmallVec<[Entity; 4]>>,
+    changes: Changes,
+}
+
+impl<T: RTreeObject> Default for BroadPhase<T> {
+    fn default() -> Self {
+        Self {
+            statics: RTree::new(),
+            dynamics: RTree::new(),
+            envelopes: HashMap::new(),
+            shapes: HashMap::new(),
+            changes: Changes::default(),
+        }
+    }
+}
+
+/// The bodies and shapes whose colliders have to be refreshed on the next step.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Changes {
+    /// Bodies that were added or changed.
+    pub(crate) bodies: HashSet<Entity>,
+    /// Shapes that were added or changed, their bodies are in `bodies` as well.
+    pub(crate) shapes: HashSet<Entity>,
+    /// Bodies and shapes that were removed.
+    pub(crate) removed: HashSet<Entity>,
+}
+
+/// Selects the collider of a shape, whose envelope is known.
+struct SelectShape<E> {
+    shape: Entity,
+    envelope: E,
+}
+
+impl<T: RTreeObject + Collider> SelectionFunction<T> for SelectShape<T::Envelope>
+where
+    NPoint<T::Point>: Point,
+{
+    fn should_unpack_parent(&self, envelope: &T::Envelope) -> bool {
+        envelope.contains_envelope(&self.envelope)
+    }
+
+    fn should_unpack_leaf(&self, leaf: &T) -> bool {
+        leaf.shape() == self.shape
+    }
 }
 
 impl<T: RTreeObject + Collider> BroadPhase<T>
@@ -182,33 +243,346 @@ where
 {
     /// Create a new `BroadPhase` with some colliders.
     pub fn with_colliders(colliders: Vec<T>) -> Self {
-        Self {
-            rstar: RTree::bulk_load(colliders),
+        let mut broad = Self::default();
+        let (statics, dynamics): (Vec<T>, Vec<T>) = colliders
+            .into_iter()
+            .partition(|collider| collider.status() == Status::Static);
+        for collider in &statics {
+            broad.track(collider, true);
         }
+        for collider in &dynamics {
+            broad.track(collider, false);
+        }
+        broad.statics = RTree::bulk_load(statics);
+        broad.dynamics = RTree::bulk_load(dynamics);
+        broad
+    }
+
+    /// Returns true if the `BroadPhase` has no colliders.
+    pub fn is_empty(&self) -> bool {
+        self.envelopes.is_empty()
+    }
+
+    /// Insert a collider, replacing the collider of the same shape.
+    pub fn insert(&mut self, collider: T) {
+        self.remove(collider.shape());
+        let is_static = collider.status() == Status::Static;
+        self.track(&collider, is_static);
+        if is_static {
+            self.statics.insert(collider);
+        } else {
+            self.dynamics.insert(collider);
+        }
+    }
+
+    /// Remove the collider of a shape, if there is one.
+    pub fn remove(&mut self, shape: Entity) -> Option<T> {
+        let (envelope, is_static) = self.envelopes.remove(&shape)?;
+        let tree = if is_static {
+            &mut self.statics
+        } else {
+            &mut self.dynamics
+        };
+        let collider = tree.remove_with_selection_function(SelectShape { shape, envelope })?;
+        let body = collider.body();
+        if let Some(shapes) = self.shapes.get_mut(&body) {
+            shapes.retain(|other| *other != shape);
+            if shapes.is_empty() {
+                self.shapes.remove(&body);
+            }
+        }
+        Some(collider)
+    }
+
+    /// Remove all colliders of a body.
+    pub fn remove_body(&mut self, body: Entity) -> SmallVec<[T; 4]> {
+        let shapes = self.shapes.get(&body).cloned().unwrap_or_default();
+        shapes
+            .into_iter()
+            .filter_map(|shape| self.remove(shape))
+            .collect()
+    }
+
+    /// Marks a body whose colliders are refreshed on the next step.
+    pub(crate) fn mark_changed(&mut self, body: Entity) {
+        self.changes.bodies.insert(body);
+    }
+
+    /// Marks a shape of a body whose collider is rebuilt on the next step.
+    pub(crate) fn mark_reshaped(&mut self, body: Entity, shape: Entity) {
+        self.changes.bodies.insert(body);
+        self.changes.shapes.insert(shape);
+    }
+
+    /// Marks a body or a shape whose colliders are removed on the next step.
+    pub(crate) fn mark_removed(&mut self, entity: Entity) {
+        self.changes.removed.insert(entity);
+    }
+
+    /// Takes the changes marked since the last step.
+    pub(crate) fn take_changes(&mut self) -> Changes {
+        mem::take(&mut self.changes)
+    }
+
+    /// Remembers the tree and the body of the collider of a shape.
+    fn track(&mut self, collider: &T, is_static: bool) {
+        let shape = collider.shape();
+        self.envelopes
+            .insert(shape, (collider.envelope(), is_static));
+        self.shapes
+            .entry(collider.body())
+            .or_insert_with(SmallVec::new)
+            .push(shape);
     }
 
     /// Iterate through all pairs of shapes that are potentially colliding.
     ///
-    /// Collisions with static objects are iterated over before semikinematic.
+    /// Every pair is visited once.  Collisions with static objects are iterated
+    /// over before semikinematic.  Static objects never collide with each other
+    /// and shapes of the same body never collide either.  Pairs whose
+    /// `CollisionLayers` don't interact are skipped.
     pub fn iter(&self) -> impl Iterator<Item = (&T, &T)> + '_ {
-        let statics = self
-            .rstar
+        let statics = self.dynamics.iter().flat_map(move |collider2| {
+            self.statics
+                .locate_in_envelope_intersecting(&collider2.envelope())
+                .map(move |collider1| (collider1, collider2))
+        });
+        let semiks = self.dynamics.iter().flat_map(move |collider1| {
+            self.dynamics
+                .locate_in_envelope_intersecting(&collider1.envelope())
+                .filter(move |collider2| collider1.shape() < collider2.shape())
+                .map(move |collider2| (collider1, collider2))
+        });
+        statics.chain(semiks).filter(|(collider1, collider2)| {
+            collider1.body() != collider2.body() && collider1.layers().interacts(collider2.layers())
+        })
+    }
+
+    /// Returns all pairs of bodies that are potentially colliding, each with
+    /// their pairs of shapes that are potentially colliding.
+    ///
+    /// Every pair of bodies is returned once, in the same order as `iter`.
+    /// The shapes of every pair are ordered like the bodies of its first pair.
+    pub fn groups(&self) -> Vec<SmallVec<[(&T, &T); 4]>> {
+        let mut indices = HashMap::new();
+        let mut groups: Vec<SmallVec<[(&T, &T); 4]>> = Vec::new();
+        for (collider1, collider2) in self.iter() {
+            let body1 = collider1.body();
+            let body2 = collider2.body();
+            if let Some(&index) = indices.get(&(body1, body2)) {
+                groups[index].push((collider1, collider2));
+            } else if let Some(&index) = indices.get(&(body2, body1)) {
+                groups[index].push((collider2, collider1));
+            } else {
+                indices.insert((body1, body2), groups.len());
+                groups.push(smallvec![(collider1, collider2)]);
+            }
+        }
+        groups
+    }
+}
+
+impl<T: RTreeObject<Envelope = AABB<NPoint<T::Point>>> + Collider> BroadPhase<T>
+where
+    NPoint<T::Point>: Point,
+{
+    /// Iterate through all colliders whose bounding boxes intersect a `BoundingBox`.
+    ///
+    /// Static colliders are iterated over before semikinematic.
+    pub fn intersecting(&self, bounding_box: &BoundingBox<T::Point>) -> impl Iterator<Item = &T> {
+        self.statics
+            .locate_in_envelope_intersecting(&bounding_box.aabb)
+            .chain(
+                self.dynamics
+                    .locate_in_envelope_intersecting(&bounding_box.aabb),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A collider for a square of 2 by 2 units, the broad phase only needs its bounding box.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Square {
+        status: Status,
+        body: Entity,
+        shape: Entity,
+        center: Vec2,
+    }
+
+    fn square(status: Status, body: u32, shape: u32, center: Vec2) -> Square {
+        Square {
+            status,
+            body: Entity::from_id(body),
+            shape: Entity::from_id(shape),
+            center,
+        }
+    }
+
+    impl Collider for Square {
+        type Point = Vec2;
+
+        fn bounding_box(&self) -> BoundingBox<Vec2> {
+            BoundingBox::new(self.center - Vec2::one(), self.center + Vec2::one())
+        }
+
+        fn status(&self) -> Status {
+            self.status
+        }
+
+        fn body(&self) -> Entity {
+            self.body
+        }
+
+        fn shape(&self) -> Entity {
+            self.shape
+        }
+
+        fn layers(&self) -> CollisionLayers {
+            CollisionLayers::default()
+        }
+    }
+
+    impl RTreeObject for Square {
+        type Envelope = AABB<NPoint<Vec2>>;
+
+        fn envelope(&self) -> Self::Envelope {
+            self.bounding_box().aabb
+        }
+    }
+
+    /// Returns the shapes of every pair, sorted.
+    fn pairs(broad: &BroadPhase<Square>) -> Vec<(Entity, Entity)> {
+        let mut pairs: Vec<_> = broad
             .iter()
-            .filter(|collider| collider.status() == Status::Static);
-        let semiks = self
-            .rstar
+            .map(|(collider1, collider2)| (collider1.shape, collider2.shape))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    /// A row of static squares, with a row of semikinematic squares on top of them.
+    fn colliders() -> Vec<Square> {
+        let statics = (0..20).map(|i| {
+            let center = Vec2::new(i as f32 * 2.0, 0.0);
+            square(Status::Static, i, i + 100, center)
+        });
+        let semiks = (0..10).map(|i| {
+            let center = Vec2::new(i as f32 * 3.0, 1.5);
+            square(Status::Semikinematic, i + 20, i + 120, center)
+        });
+        statics.chain(semiks).collect()
+    }
+
+    #[test]
+    fn updates_match_rebuild() {
+        let mut colliders = colliders();
+        let mut broad = BroadPhase::with_colliders(colliders.clone());
+        assert!(!pairs(&broad).is_empty());
+
+        for step in 1..5 {
+            for collider in colliders.iter_mut() {
+                if collider.status == Status::Semikinematic {
+                    collider.center += Vec2::new(0.7, -0.2) * step as f32;
+                    broad.insert(*collider);
+                }
+            }
+            assert_eq!(
+                pairs(&broad),
+                pairs(&BroadPhase::with_colliders(colliders.clone()))
+            );
+        }
+
+        // a second shape on a body, which is removed with the body
+        broad.insert(square(Status::Semikinematic, 20, 150, Vec2::new(4.0, 0.0)));
+        assert_eq!(broad.remove_body(Entity::from_id(20)).len(), 2);
+        colliders.retain(|collider| collider.body != Entity::from_id(20));
+        let removed = broad.remove(Entity::from_id(105)).unwrap();
+        assert_eq!(removed.shape, Entity::from_id(105));
+        assert!(broad.remove(Entity::from_id(105)).is_none());
+        colliders.retain(|collider| collider.shape != Entity::from_id(105));
+        assert_eq!(
+            pairs(&broad),
+            pairs(&BroadPhase::with_colliders(colliders.clone()))
+        );
+    }
+
+    #[test]
+    fn keeps_changes_until_taken() {
+        let mut broad = BroadPhase::<Square>::default();
+        broad.mark_changed(Entity::from_id(1));
+        broad.mark_reshaped(Entity::from_id(2), Entity::from_id(3));
+        broad.mark_removed(Entity::from_id(4));
+        let changes = broad.take_changes();
+        assert_eq!(changes.bodies.len(), 2);
+        assert!(changes.bodies.contains(&Entity::from_id(1)));
+        assert!(changes.bodies.contains(&Entity::from_id(2)));
+        assert!(changes.shapes.contains(&Entity::from_id(3)));
+        assert!(changes.removed.contains(&Entity::from_id(4)));
+        let changes = broad.take_changes();
+        assert!(
+            changes.bodies.is_empty() && changes.shapes.is_empty() && changes.removed.is_empty()
+        );
+    }
+
+    /// Two overlapping static bodies under a semikinematic body with two
+    /// overlapping shapes, under another semikinematic body.
+    fn stack() -> BroadPhase<Square> {
+        BroadPhase::with_colliders(vec![
+            square(Status::Static, 1, 101, Vec2::new(0.0, 0.0)),
+            square(Status::Static, 2, 102, Vec2::new(1.0, 0.0)),
+            square(Status::Semikinematic, 3, 103, Vec2::new(0.0, 1.0)),
+            square(Status::Semikinematic, 3, 104, Vec2::new(1.0, 1.0)),
+            square(Status::Semikinematic, 4, 105, Vec2::new(0.5, 2.5)),
+        ])
+    }
+
+    #[test]
+    fn iter_yields_pairs_once() {
+        let broad = stack();
+        let shapes = |pairs: &[(u32, u32)]| {
+            pairs
+                .iter()
+                .map(|&(a, b)| (Entity::from_id(a), Entity::from_id(b)))
+                .collect::<Vec<_>>()
+        };
+        // neither the static bodies nor the shapes of the same body are paired
+        let expected = shapes(&[
+            (101, 103),
+            (101, 104),
+            (102, 103),
+            (102, 104),
+            (103, 105),
+            (104, 105),
+        ]);
+        assert_eq!(pairs(&broad), expected);
+        // static colliders come first
+        for (collider1, collider2) in broad.iter().take(4) {
+            assert_eq!(collider1.status, Status::Static);
+            assert_eq!(collider2.status, Status::Semikinematic);
+        }
+    }
+
+    #[test]
+    fn groups_collect_shapes_of_body_pairs() {
+        let broad = stack();
+        let groups = broad.groups();
+        let mut bodies: Vec<_> = groups
             .iter()
-            .filter(|collider| collider.status() == Status::Semikinematic);
-        statics
-            .flat_map(move |collider1| {
-                self.rstar
-                    .locate_in_envelope_intersecting(&collider1.envelope())
-                    .map(move |collider2| (collider1, collider2))
-            })
-            .chain(semiks.flat_map(move |collider1| {
-                self.rstar
-                    .locate_in_envelope_intersecting(&collider1.envelope())
-                    .map(move |collider2| (collider1, collider2))
-            }))
+            .map(|group| (group[0].0.body.id(), group[0].1.body.id()))
+            .collect();
+        bodies.sort();
+        assert_eq!(bodies, vec![(1, 3), (2, 3), (3, 4)]);
+        for group in &groups {
+            assert_eq!(group.len(), 2);
+            for (collider1, collider2) in group {
+                assert_eq!(collider1.body, group[0].0.body);
+                assert_eq!(collider2.body, group[0].1.body);
+            }
+        }
+        let count: usize = groups.iter().map(|group| group.len()).sum();
+        assert_eq!(count, broad.iter().count());
     }
 }
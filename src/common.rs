@@ -24,6 +24,9 @@ impl Vec3Ext for Vec3 {
 }
 
 /// The global friction that affects every `RigidBody`, both 2d and 3d.
+///
+/// Semikinematic bodies are slowed down by it every step.  While they touch
+/// other bodies, it is scaled by the friction of their `PhysicsMaterial`s.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct GlobalFriction(pub f32);
 
@@ -70,7 +73,7 @@ impl From<f32> for Mass {
 }
 
 /// The status of a `RigidBody`
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Property)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Property)]
 pub enum Status {
     /// Static bodies do not check for collisions.
     ///
@@ -87,6 +90,19 @@ pub enum Status {
     /// body may stand on top of another.  Semikinematic bodies can step onto small
     /// static coliders (depending on the global step value).
     Semikinematic,
+    /// Dynamic bodies are fully simulated.
+    ///
+    /// They are affected by forces, like gravity, and collisions with any other
+    /// body are resolved by an impulse solver, which makes them bounce, slide
+    /// and tumble depending on their restitution, friction and the inertia of
+    /// their shapes.  Semikinematic bodies push dynamic bodies based on their mass.
+    Dynamic,
+    /// Kinematic bodies are only moved by code.
+    ///
+    /// They are unaffected by forces and collisions, and move with their velocity
+    /// or towards a target pose set with `move_to`.  They push semikinematic and
+    /// dynamic bodies out of their way, and carry semikinematic bodies resting on top of them.
+    Kinematic,
 }
 
 impl Default for Status {
@@ -94,3 +110,174 @@ impl Default for Status {
         Status::Semikinematic
     }
 }
+
+/// How the coefficients of two `PhysicsMaterial`s are combined.
+///
+/// If two materials use different rules, the one declared last is used.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Property,
+)]
+pub enum CombineRule {
+    /// The average of both coefficients.
+    Average,
+    /// The smaller coefficient.
+    Min,
+    /// The product of both coefficients.
+    Multiply,
+    /// The larger coefficient.
+    Max,
+}
+
+impl CombineRule {
+    /// Combines two coefficients with this rule.
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            Self::Average => (a + b) * 0.5,
+            Self::Min => a.min(b),
+            Self::Multiply => a * b,
+            Self::Max => a.max(b),
+        }
+    }
+}
+
+impl Default for CombineRule {
+    fn default() -> Self {
+        CombineRule::Average
+    }
+}
+
+/// The physics material of a `RigidBody`, both 2d and 3d.
+///
+/// The coefficients of two bodies are combined for every collision between them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Property)]
+pub struct PhysicsMaterial {
+    /// The friction coefficient, from 0 (ice) upwards.
+    pub friction: f32,
+    /// The restitution, from 0 (no bounce) to 1 (perfect bounce).
+    pub restitution: f32,
+    /// How the friction is combined with the friction of another material.
+    pub friction_combine: CombineRule,
+    /// How the restitution is combined with the restitution of another material.
+    pub restitution_combine: CombineRule,
+}
+
+impl PhysicsMaterial {
+    /// Returns a new `PhysicsMaterial` with a friction and a restitution, combined by averaging.
+    pub fn new(friction: f32, restitution: f32) -> Self {
+        Self {
+            friction,
+            restitution,
+            friction_combine: CombineRule::default(),
+            restitution_combine: CombineRule::default(),
+        }
+    }
+
+    /// Returns a `PhysicsMaterial` identical to this one, but with the friction combine rule set to a new one.
+    pub fn with_friction_combine(mut self, rule: CombineRule) -> Self {
+        self.friction_combine = rule;
+        self
+    }
+
+    /// Returns a `PhysicsMaterial` identical to this one, but with the restitution combine rule set to a new one.
+    pub fn with_restitution_combine(mut self, rule: CombineRule) -> Self {
+        self.restitution_combine = rule;
+        self
+    }
+
+    /// Returns the friction of a collision between this material and another one.
+    pub fn combine_friction(&self, other: &PhysicsMaterial) -> f32 {
+        let rule = self.friction_combine.max(other.friction_combine);
+        rule.combine(self.friction, other.friction)
+    }
+
+    /// Returns the restitution of a collision between this material and another one.
+    pub fn combine_restitution(&self, other: &PhysicsMaterial) -> f32 {
+        let rule = self.restitution_combine.max(other.restitution_combine);
+        rule.combine(self.restitution, other.restitution)
+    }
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self::new(0.5, 0.0)
+    }
+}
+
+/// The collision layers of a `Shape`, both 2d and 3d.
+///
+/// Two shapes only collide if each of them is on a layer the other one collides with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Property)]
+pub struct CollisionLayers {
+    /// The layers this shape is on, as a bitmask.
+    pub layers: u32,
+    /// The layers this shape collides with, as a bitmask.
+    pub mask: u32,
+}
+
+impl CollisionLayers {
+    /// Returns new `CollisionLayers` with the layers a shape is on and the layers it collides with.
+    pub fn new(layers: u32, mask: u32) -> Self {
+        Self { layers, mask }
+    }
+
+    /// Returns true if two shapes on these layers may collide.
+    pub fn interacts(self, other: CollisionLayers) -> bool {
+        self.layers & other.mask != 0 && other.layers & self.mask != 0
+    }
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        Self {
+            layers: u32::MAX,
+            mask: u32::MAX,
+        }
+    }
+}
+
+/// Decides which shapes a spatial query, like a raycast, may hit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryFilter {
+    /// The layers that may be hit, as a bitmask.
+    pub mask: u32,
+    /// The bodies that are never hit, e.g. the body casting a ray.
+    pub excluded: Vec<Entity>,
+    /// Whether sensors may be hit, which they aren't by default.
+    pub sensors: bool,
+}
+
+impl QueryFilter {
+    /// Returns a new `QueryFilter` that hits all solid shapes on the layers of `mask`.
+    pub fn new(mask: u32) -> Self {
+        Self {
+            mask,
+            excluded: Vec::new(),
+            sensors: false,
+        }
+    }
+
+    /// Returns a `QueryFilter` identical to this one, but that never hits `body`.
+    pub fn with_excluded(mut self, body: Entity) -> Self {
+        self.excluded.push(body);
+        self
+    }
+
+    /// Returns a `QueryFilter` identical to this one, but that hits sensors if `sensors` is set.
+    pub fn with_sensors(mut self, sensors: bool) -> Self {
+        self.sensors = sensors;
+        self
+    }
+
+    /// Returns true if a shape of `body` on `layers` may be hit.
+    pub fn allows(&self, body: Entity, layers: CollisionLayers, sensor: bool) -> bool {
+        layers.layers & self.mask != 0
+            && !self.excluded.contains(&body)
+            && (self.sensors || !sensor)
+    }
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self::new(u32::MAX)
+    }
+}
@@ -0,0 +1,1104 @@
+//! # Resources
+//!
+//! Inspired by Randy Gaul's ImpulseEngine
+//! [https://github.com/RandyGaul/ImpulseEngine/blob/master/Collision.cpp](ImpulseEngine/Collision.cpp)
+//! and by Christer Ericson's Real-Time Collision Detection.
+
+use std::cmp::Ordering;
+use std::mem;
+
+use bevy::math::*;
+use smallvec::{smallvec, SmallVec};
+
+use super::tilemap::Block;
+use super::*;
+
+/// Returns a vector perpendicular to `v`, rotated clockwise.
+fn perpendicular(v: Vec2) -> Vec2 {
+    Vec2::new(v.y(), -v.x())
+}
+
+fn bias_greater_than(a: f32, b: f32) -> bool {
+    const BIAS_RELATIVE: f32 = 0.95;
+    const BIAS_ABSOLUTE: f32 = 0.01;
+    a >= b * BIAS_RELATIVE + a * BIAS_ABSOLUTE
+}
+
+fn find_axis_of_least_penetration(a: &Obb, ap: &Polygon, b: &Obb, bp: &Polygon) -> (f32, usize) {
+    let mut best_distance = f32::MIN;
+    let mut best_index = 0;
+
+    for i in 0..ap.vertices.len() {
+        let n = ap.normals[i];
+        let nw = a.rotation * n;
+
+        let brt = b.rotation.transpose();
+        let n = brt * nw;
+
+        let s = bp.get_support(-n);
+
+        let mut v = ap.vertices[i];
+        v = a.rotation * v + a.position;
+        v -= b.position;
+        v = brt * v;
+
+        let d = n.dot(s - v);
+
+        if d > best_distance {
+            best_distance = d;
+            best_index = i;
+        }
+    }
+
+    (best_distance, best_index)
+}
+
+fn find_incident_face(
+    ref_obb: &Obb,
+    ref_poly: &Polygon,
+    inc_obb: &Obb,
+    inc_poly: &Polygon,
+    idx: usize,
+) -> [Vec2; 2] {
+    let mut ref_normal = ref_poly.normals[idx];
+
+    ref_normal = ref_obb.rotation * ref_normal;
+    ref_normal = inc_obb.rotation.transpose() * ref_normal;
+
+    let mut incident_face = 0;
+    let mut min_dot = f32::MAX;
+
+    for i in 0..inc_poly.normals.len() {
+        let dot = ref_normal.dot(inc_poly.normals[i]);
+        if dot < min_dot {
+            min_dot = dot;
+            incident_face = i;
+        }
+    }
+
+    let v0 = inc_obb.rotation * inc_poly.vertices[incident_face] + inc_obb.position;
+    incident_face = (incident_face + 1) % inc_poly.vertices.len();
+    let v1 = inc_obb.rotation * inc_poly.vertices[incident_face] + inc_obb.position;
+    [v0, v1]
+}
+
+fn clip(n: Vec2, c: f32, face: &mut [Vec2]) -> usize {
+    let mut sp = 0;
+    let mut out = [face[0], face[1]];
+
+    let d1 = n.dot(face[0]) - c;
+    let d2 = n.dot(face[1]) - c;
+
+    if d1 <= 0.0 {
+        out[sp] = face[0];
+        sp += 1;
+    }
+
+    if d2 <= 0.0 {
+        out[sp] = face[1];
+        sp += 1;
+    }
+
+    if d1 * d2 < 0.0 {
+        let alpha = d1 / (d1 - d2);
+        out[sp] = face[0] + alpha * (face[1] - face[0]);
+        sp += 1;
+    }
+
+    face[0] = out[0];
+    face[1] = out[1];
+
+    debug_assert_ne!(sp, 3);
+
+    sp
+}
+
+/// Returns the closest points between the segments `p1q1` and `p2q2`.
+pub(super) fn closest_points_segments(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> (Vec2, Vec2) {
+    const EPS: f32 = 1.0e-6;
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.dot(d1);
+    let e = d2.dot(d2);
+    let f = d2.dot(r);
+
+    let (s, t) = if a <= EPS && e <= EPS {
+        (0.0, 0.0)
+    } else if a <= EPS {
+        (0.0, (f / e).max(0.0).min(1.0))
+    } else {
+        let c = d1.dot(r);
+        if e <= EPS {
+            ((-c / a).max(0.0).min(1.0), 0.0)
+        } else {
+            let b = d1.dot(d2);
+            let denom = a * e - b * b;
+            let mut s = if denom > EPS {
+                ((b * f - c * e) / denom).max(0.0).min(1.0)
+            } else {
+                0.0
+            };
+            let mut t = (b * s + f) / e;
+            if t < 0.0 {
+                t = 0.0;
+                s = (-c / a).max(0.0).min(1.0);
+            } else if t > 1.0 {
+                t = 1.0;
+                s = ((b - c) / a).max(0.0).min(1.0);
+            }
+            (s, t)
+        }
+    };
+
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+/// Collides two convex polygons using the separating axis test.
+///
+/// The normal of the resulting manifold points from `a` to `b`.
+pub fn polygon_to_polygon(a: &Obb, ap: &Polygon, b: &Obb, bp: &Polygon) -> Option<Manifold> {
+    let (penetration_a, face_a) = find_axis_of_least_penetration(a, ap, b, bp);
+    if penetration_a >= 0.0 {
+        return None;
+    }
+
+    let (penetration_b, face_b) = find_axis_of_least_penetration(b, bp, a, ap);
+    if penetration_b >= 0.0 {
+        return None;
+    }
+
+    let mut ref_index;
+    let flip;
+    let ref_obb;
+    let ref_poly;
+    let inc_obb;
+    let inc_poly;
+
+    if bias_greater_than(penetration_a, penetration_b) {
+        ref_obb = a;
+        ref_poly = ap;
+        inc_obb = b;
+        inc_poly = bp;
+        ref_index = face_a;
+        flip = false;
+    } else {
+        ref_obb = b;
+        ref_poly = bp;
+        inc_obb = a;
+        inc_poly = ap;
+        ref_index = face_b;
+        flip = true;
+    }
+
+    let mut incident_face = find_incident_face(ref_obb, ref_poly, inc_obb, inc_poly, ref_index);
+
+    let mut v1 = ref_poly.vertices[ref_index];
+    ref_index = (ref_index + 1) % ref_poly.vertices.len();
+    let mut v2 = ref_poly.vertices[ref_index];
+
+    v1 = ref_obb.rotation * v1 + ref_obb.position;
+    v2 = ref_obb.rotation * v2 + ref_obb.position;
+
+    let side_plane_normal = (v2 - v1).normalize();
+
+    let ref_face_normal = perpendicular(side_plane_normal);
+
+    let refc = ref_face_normal.dot(v1);
+    let negside = -side_plane_normal.dot(v1);
+    let posside = side_plane_normal.dot(v2);
+
+    if clip(-side_plane_normal, negside, &mut incident_face) < 2 {
+        return None;
+    }
+
+    if clip(side_plane_normal, posside, &mut incident_face) < 2 {
+        return None;
+    }
+
+    let normal = if flip {
+        -ref_face_normal
+    } else {
+        ref_face_normal
+    };
+    let mut penetration = 0.0;
+
+    let mut contacts = SmallVec::new();
+
+    let mut cp = 0;
+    let sep = ref_face_normal.dot(incident_face[0]) - refc;
+    if sep <= 0.0 {
+        contacts.push(Contact {
+            position: incident_face[0],
+            penetration: -sep,
+            impulse: 0.0,
+        });
+        penetration = -sep;
+        cp += 1;
+    }
+
+    let sep = ref_face_normal.dot(incident_face[1]) - refc;
+    if sep <= 0.0 {
+        contacts.push(Contact {
+            position: incident_face[1],
+            penetration: -sep,
+            impulse: 0.0,
+        });
+        penetration += -sep;
+        cp += 1;
+        penetration /= cp as f32;
+    }
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        penetration,
+        normal,
+        contacts,
+    })
+}
+
+/// Collides two circles given in world space.
+///
+/// The resulting normal points from the first circle to the second.
+pub fn circle_to_circle(
+    a: &Obb,
+    ca: Vec2,
+    ra: f32,
+    b: &Obb,
+    cb: Vec2,
+    rb: f32,
+) -> Option<Manifold> {
+    let d = cb - ca;
+    let radius = ra + rb;
+    let distance_squared = d.length_squared();
+    if distance_squared >= radius * radius {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    let normal = if distance > f32::EPSILON {
+        d / distance
+    } else {
+        Vec2::new(0.0, 1.0)
+    };
+    let penetration = radius - distance;
+    let contact = ca + normal * (ra - penetration * 0.5);
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        penetration,
+        normal,
+        contacts: smallvec![Contact {
+            position: contact,
+            penetration,
+            impulse: 0.0,
+        }],
+    })
+}
+
+/// Collides a convex polygon with a circle given in world space.
+///
+/// The resulting normal points from the polygon to the circle.
+pub fn circle_to_polygon(
+    a: &Obb,
+    ap: &Polygon,
+    b: &Obb,
+    center: Vec2,
+    radius: f32,
+) -> Option<Manifold> {
+    let c = a.rotation.transpose() * (center - a.position);
+
+    let count = ap.vertices.len();
+    let mut separation = f32::MIN;
+    let mut face = 0;
+    for i in 0..count {
+        let s = ap.normals[i].dot(c - ap.vertices[i]);
+        if s > radius {
+            return None;
+        }
+        if s > separation {
+            separation = s;
+            face = i;
+        }
+    }
+
+    let v1 = ap.vertices[face];
+    let v2 = ap.vertices[(face + 1) % count];
+
+    let (normal, contact, penetration) = if separation < f32::EPSILON {
+        let normal = ap.normals[face];
+        (normal, c - normal * separation, radius - separation)
+    } else {
+        let u1 = (c - v1).dot(v2 - v1);
+        let u2 = (c - v2).dot(v1 - v2);
+        let vertex = if u1 <= 0.0 {
+            Some(v1)
+        } else if u2 <= 0.0 {
+            Some(v2)
+        } else {
+            None
+        };
+        if let Some(vertex) = vertex {
+            let d = c - vertex;
+            let distance_squared = d.length_squared();
+            if distance_squared > radius * radius {
+                return None;
+            }
+            let distance = distance_squared.sqrt();
+            (d / distance, vertex, radius - distance)
+        } else {
+            let normal = ap.normals[face];
+            (normal, c - normal * separation, radius - separation)
+        }
+    };
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        penetration,
+        normal: a.rotation * normal,
+        contacts: smallvec![Contact {
+            position: a.to_world(contact),
+            penetration,
+            impulse: 0.0,
+        }],
+    })
+}
+
+/// Collides two capsules given as world space segments with a radius.
+///
+/// The resulting normal points from the first capsule to the second.
+pub fn capsule_to_capsule(
+    a: &Obb,
+    (pa, qa, ra): (Vec2, Vec2, f32),
+    b: &Obb,
+    (pb, qb, rb): (Vec2, Vec2, f32),
+) -> Option<Manifold> {
+    let (ca, cb) = closest_points_segments(pa, qa, pb, qb);
+    let d = cb - ca;
+    let radius = ra + rb;
+    let distance_squared = d.length_squared();
+    if distance_squared >= radius * radius {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    let normal = if distance > f32::EPSILON {
+        d / distance
+    } else {
+        // the segments intersect, push apart perpendicular to the first one
+        let axis = qa - pa;
+        if axis.length_squared() > f32::EPSILON {
+            let n = perpendicular(axis.normalize());
+            if n.dot((pb + qb) * 0.5 - (pa + qa) * 0.5) < 0.0 {
+                -n
+            } else {
+                n
+            }
+        } else {
+            Vec2::new(0.0, 1.0)
+        }
+    };
+    let penetration = radius - distance;
+    let contact = ca + normal * (ra - penetration * 0.5);
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        penetration,
+        normal,
+        contacts: smallvec![Contact {
+            position: contact,
+            penetration,
+            impulse: 0.0,
+        }],
+    })
+}
+
+/// Collides a convex polygon with a capsule given as a world space segment with a radius.
+///
+/// The resulting normal points from the polygon to the capsule.
+pub fn capsule_to_polygon(
+    a: &Obb,
+    ap: &Polygon,
+    b: &Obb,
+    (p, q, radius): (Vec2, Vec2, f32),
+) -> Option<Manifold> {
+    let rt = a.rotation.transpose();
+    let p = rt * (p - a.position);
+    let q = rt * (q - a.position);
+
+    let count = ap.vertices.len();
+    let mut separation = f32::MIN;
+    let mut face = 0;
+    for i in 0..count {
+        let n = ap.normals[i];
+        let v = ap.vertices[i];
+        let s = n.dot(p - v).min(n.dot(q - v));
+        if s > radius {
+            return None;
+        }
+        if s > separation {
+            separation = s;
+            face = i;
+        }
+    }
+
+    let n = ap.normals[face];
+    let v1 = ap.vertices[face];
+    let v2 = ap.vertices[(face + 1) % count];
+
+    // the segment lies outside of the polygon, find the closest features
+    // to reject the corner regions and to tell whether the face is the
+    // right feature to generate contacts on
+    let mut closest = None;
+    if separation > 0.0 {
+        let mut best = f32::MAX;
+        for i in 0..count {
+            let e1 = ap.vertices[i];
+            let e2 = ap.vertices[(i + 1) % count];
+            let (cs, cb) = closest_points_segments(p, q, e1, e2);
+            let distance_squared = (cs - cb).length_squared();
+            if distance_squared < best {
+                best = distance_squared;
+                closest = Some((cs, cb));
+            }
+        }
+        if best > radius * radius {
+            return None;
+        }
+    }
+
+    const FACE_TOLERANCE: f32 = 0.99;
+    let use_face = match closest {
+        Some((cs, cb)) => {
+            let d = cs - cb;
+            d.length_squared() <= f32::EPSILON || d.normalize().dot(n) >= FACE_TOLERANCE
+        }
+        None => true,
+    };
+
+    let mut contacts = SmallVec::new();
+    let mut penetration = 0.0;
+    let normal;
+
+    if use_face {
+        let side = (v2 - v1).normalize();
+        let mut segment = [p, q];
+        if clip(-side, -side.dot(v1), &mut segment) < 2 {
+            return None;
+        }
+        if clip(side, side.dot(v2), &mut segment) < 2 {
+            return None;
+        }
+        for &point in &segment {
+            let sep = n.dot(point - v1);
+            if sep <= radius {
+                contacts.push(Contact {
+                    position: a.to_world(point - n * sep),
+                    penetration: radius - sep,
+                    impulse: 0.0,
+                });
+                penetration += radius - sep;
+            }
+        }
+        if contacts.is_empty() {
+            return None;
+        }
+        penetration /= contacts.len() as f32;
+        normal = n;
+    } else {
+        let (cs, cb) = closest.unwrap();
+        let d = cs - cb;
+        let distance = d.length();
+        penetration = radius - distance;
+        contacts.push(Contact {
+            position: a.to_world(cb),
+            penetration,
+            impulse: 0.0,
+        });
+        normal = d / distance;
+    }
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        penetration,
+        normal: a.rotation * normal,
+        contacts,
+    })
+}
+
+/// Returns the blocks of merged tiles of a tilemap collider that intersect the world
+/// space box between `min` and `max`.
+fn tilemap_blocks(
+    a: &Obb,
+    offset: Vec2,
+    map: &TileMap,
+    min: Vec2,
+    max: Vec2,
+) -> SmallVec<[Block; 8]> {
+    let inverse = a.rotation.transpose();
+    let to_local = |v: Vec2| inverse * (v - a.position) - offset;
+    let corners = [
+        to_local(min),
+        to_local(Vec2::new(max.x(), min.y())),
+        to_local(max),
+        to_local(Vec2::new(min.x(), max.y())),
+    ];
+    let local_min = corners[1..].iter().fold(corners[0], |m, &v| m.min(v));
+    let local_max = corners[1..].iter().fold(corners[0], |m, &v| m.max(v));
+    map.blocks(local_min, local_max)
+}
+
+/// Returns the corners of a block of merged tiles in counter-clockwise order,
+/// relative to the collider of its tilemap.
+fn block_corners(offset: Vec2, map: &TileMap, block: Block) -> SmallVec<[Vec2; 8]> {
+    let tile = map.tile();
+    let v0 = offset
+        + Vec2::new(
+            block.column as f32 * tile.width,
+            block.row as f32 * tile.height,
+        );
+    let v2 = v0
+        + Vec2::new(
+            block.columns as f32 * tile.width,
+            block.rows as f32 * tile.height,
+        );
+    smallvec![v0, Vec2::new(v2.x(), v0.y()), v2, Vec2::new(v0.x(), v2.y())]
+}
+
+/// Collides a static tilemap with a collider of any other geometry.
+///
+/// Every block of merged tiles close to `b` is collided on its own.  Normals
+/// that point into a neighbouring solid tile are removed component by component,
+/// so that edges between two blocks never push `b` sideways.  The resulting manifold
+/// has the normal and penetration of the deepest block and the contacts of all of them.
+fn tilemap_to_other(a: &Obb, offset: Vec2, map: &TileMap, b: &Obb) -> Option<Manifold> {
+    // a small tolerance for normals that are not exactly aligned with the grid
+    const AXIS_TOLERANCE: f32 = 1.0e-3;
+
+    let inverse = a.rotation.transpose();
+    let to_local = |v: Vec2| inverse * (v - a.position) - offset;
+
+    let mut result: Option<Manifold> = None;
+    for block in tilemap_blocks(a, offset, map, b.min(), b.max()) {
+        let polygon = Polygon::new(block_corners(offset, map, block));
+        let collider = Obb::new(
+            a.status,
+            a.body,
+            a.shape,
+            a.rotation,
+            a.position,
+            Geometry::Polygon(polygon),
+            a.layers,
+        );
+        let mut manifold = match collide(&collider, b) {
+            Some(manifold) => manifold,
+            None => continue,
+        };
+
+        // find the cell of the block closest to the contacts
+        let mut center = Vec2::zero();
+        for contact in &manifold.contacts {
+            center += contact.position;
+        }
+        center /= manifold.contacts.len().max(1) as f32;
+        let (column, row) = map.cell(to_local(center));
+        let first_column = block.column as isize;
+        let last_column = (block.column + block.columns) as isize - 1;
+        let first_row = block.row as isize;
+        let last_row = (block.row + block.rows) as isize - 1;
+        let column = column.max(first_column).min(last_column);
+        let row = row.max(first_row).min(last_row);
+
+        // remove the components of the normal that point into solid tiles
+        let mut normal = inverse * manifold.normal;
+        if normal.x() > AXIS_TOLERANCE && map.is_solid(last_column + 1, row)
+            || normal.x() < -AXIS_TOLERANCE && map.is_solid(first_column - 1, row)
+        {
+            *normal.x_mut() = 0.0;
+        }
+        if normal.y() > AXIS_TOLERANCE && map.is_solid(column, last_row + 1)
+            || normal.y() < -AXIS_TOLERANCE && map.is_solid(column, first_row - 1)
+        {
+            *normal.y_mut() = 0.0;
+        }
+        if normal.length_squared() <= AXIS_TOLERANCE * AXIS_TOLERANCE {
+            continue;
+        }
+        manifold.normal = a.rotation * normal.normalize();
+
+        result = Some(merge(result, manifold));
+    }
+    result
+}
+
+/// Merges a manifold into another one between the same bodies.
+///
+/// The merged manifold has the normal and penetration of the deeper one and the
+/// contacts and pairs of shapes of both, with the pairs of the deeper one first.
+pub fn merge(deepest: Option<Manifold>, manifold: Manifold) -> Manifold {
+    match deepest {
+        None => manifold,
+        Some(mut deepest) => {
+            let mut shapes = manifold.shapes;
+            if manifold.penetration > deepest.penetration {
+                deepest.normal = manifold.normal;
+                deepest.penetration = manifold.penetration;
+                // the pairs of shapes of the deeper manifold come first
+                mem::swap(&mut deepest.shapes, &mut shapes);
+            }
+            for pair in shapes {
+                if !deepest.shapes.contains(&pair) {
+                    deepest.shapes.push(pair);
+                }
+            }
+            deepest.contacts.extend(manifold.contacts);
+            deepest
+        }
+    }
+}
+
+/// Flips a manifold, so that the bodies and the normal are swapped.
+fn flip(manifold: Manifold) -> Manifold {
+    Manifold {
+        body1: manifold.body2,
+        body2: manifold.body1,
+        shapes: manifold.shapes.iter().map(|&(s1, s2)| (s2, s1)).collect(),
+        normal: -manifold.normal,
+        ..manifold
+    }
+}
+
+/// Returns the world space segment and radius of a round collider.
+fn round(obb: &Obb) -> Option<(Vec2, Vec2, f32)> {
+    match obb.geometry {
+        Geometry::Polygon(_) | Geometry::TileMap { .. } => None,
+        Geometry::Circle { center, radius } => {
+            let center = obb.to_world(center);
+            Some((center, center, radius))
+        }
+        Geometry::Capsule { a, b, radius } => Some((obb.to_world(a), obb.to_world(b), radius)),
+    }
+}
+
+/// Collides two colliders of any geometry.
+///
+/// The normal of the resulting manifold always points from `a` to `b`.
+pub fn collide(a: &Obb, b: &Obb) -> Option<Manifold> {
+    match (&a.geometry, &b.geometry) {
+        (Geometry::TileMap { .. }, Geometry::TileMap { .. }) => None,
+        (Geometry::TileMap { offset, map }, _) => tilemap_to_other(a, *offset, map, b),
+        (_, Geometry::TileMap { offset, map }) => tilemap_to_other(b, *offset, map, a).map(flip),
+        (Geometry::Polygon(ap), Geometry::Polygon(bp)) => polygon_to_polygon(a, ap, b, bp),
+        (Geometry::Polygon(ap), Geometry::Circle { center, radius }) => {
+            circle_to_polygon(a, ap, b, b.to_world(*center), *radius)
+        }
+        (Geometry::Circle { center, radius }, Geometry::Polygon(bp)) => {
+            circle_to_polygon(b, bp, a, a.to_world(*center), *radius).map(flip)
+        }
+        (Geometry::Polygon(ap), Geometry::Capsule { .. }) => {
+            capsule_to_polygon(a, ap, b, round(b)?)
+        }
+        (Geometry::Capsule { .. }, Geometry::Polygon(bp)) => {
+            capsule_to_polygon(b, bp, a, round(a)?).map(flip)
+        }
+        (
+            Geometry::Circle {
+                center: ca,
+                radius: ra,
+            },
+            Geometry::Circle {
+                center: cb,
+                radius: rb,
+            },
+        ) => circle_to_circle(a, a.to_world(*ca), *ra, b, b.to_world(*cb), *rb),
+        _ => capsule_to_capsule(a, round(a)?, b, round(b)?),
+    }
+}
+
+/// Tests whether two colliders overlap, without building a manifold.
+///
+/// Polygons only run the separating axis test and round shapes only compare
+/// the distance between their segments, all other pairs run GJK on their convex
+/// pieces, i.e. only the blocks of tilemaps that touch the other collider.
+pub fn overlaps(a: &Obb, b: &Obb) -> bool {
+    if let (Some((pa, qa, ra)), Some((pb, qb, rb))) = (round(a), round(b)) {
+        let (ca, cb) = closest_points_segments(pa, qa, pb, qb);
+        let radius = ra + rb;
+        return (cb - ca).length_squared() < radius * radius;
+    }
+    match (&a.geometry, &b.geometry) {
+        (Geometry::Polygon(ap), Geometry::Polygon(bp)) => {
+            find_axis_of_least_penetration(a, ap, b, bp).0 < 0.0
+                && find_axis_of_least_penetration(b, bp, a, ap).0 < 0.0
+        }
+        _ => distance(a, (b.min(), b.max()), b, (a.min(), a.max())).is_none(),
+    }
+}
+
+/// Returns half of the smallest side of the bounding boxes of two colliders, the relative
+/// motion up to which the discrete test keeps them from passing through each other.
+pub(super) fn sweep_step(a: &Obb, b: &Obb) -> f32 {
+    let size = (a.max() - a.min()).min(b.max() - b.min());
+    size.x().min(size.y()) * 0.5
+}
+
+/// A convex piece of a collider, the convex hull of world space points grown by a radius.
+struct Convex {
+    points: SmallVec<[Vec2; 8]>,
+    radius: f32,
+}
+
+/// Splits a collider into convex pieces.
+///
+/// Tilemaps only return the blocks that intersect the world space box between `min` and `max`.
+fn convex_pieces(obb: &Obb, min: Vec2, max: Vec2) -> SmallVec<[Convex; 4]> {
+    let convex = |points: &[Vec2], radius: f32| Convex {
+        points: points.iter().map(|&v| obb.to_world(v)).collect(),
+        radius,
+    };
+    match obb.geometry {
+        Geometry::Polygon(ref polygon) => smallvec![convex(&polygon.vertices, 0.0)],
+        Geometry::Circle { center, radius } => smallvec![convex(&[center], radius)],
+        Geometry::Capsule { a, b, radius } => smallvec![convex(&[a, b], radius)],
+        Geometry::TileMap { offset, ref map } => tilemap_blocks(obb, offset, map, min, max)
+            .into_iter()
+            .map(|block| convex(&block_corners(offset, map, block), 0.0))
+            .collect(),
+    }
+}
+
+/// Returns the point of the segment `pq` closest to the origin, along with the
+/// vertices of the segment needed to describe it.
+fn closest_to_origin_segment(p: Vec2, q: Vec2) -> (Vec2, SmallVec<[Vec2; 3]>) {
+    let d = q - p;
+    let l = d.length_squared();
+    let t = if l <= f32::EPSILON {
+        0.0
+    } else {
+        -p.dot(d) / l
+    };
+    if t <= 0.0 {
+        (p, smallvec![p])
+    } else if t >= 1.0 {
+        (q, smallvec![q])
+    } else {
+        (p + d * t, smallvec![p, q])
+    }
+}
+
+/// Returns the point of a simplex closest to the origin, along with the vertices of the
+/// simplex needed to describe it, or `None` if the simplex contains the origin.
+fn closest_to_origin(simplex: &[Vec2]) -> Option<(Vec2, SmallVec<[Vec2; 3]>)> {
+    let cross = |u: Vec2, v: Vec2| u.x() * v.y() - u.y() * v.x();
+    match *simplex {
+        [p] => Some((p, smallvec![p])),
+        [p, q] => Some(closest_to_origin_segment(p, q)),
+        [a, b, c] => {
+            let edges = [(a, b), (b, c), (c, a)];
+            let area = cross(b - a, c - a);
+            if area.abs() > f32::EPSILON
+                && edges.iter().all(|&(p, q)| cross(q - p, -p) * area >= 0.0)
+            {
+                return None;
+            }
+            edges
+                .iter()
+                .map(|&(p, q)| closest_to_origin_segment(p, q))
+                .min_by(|(u, _), (v, _)| {
+                    u.length_squared()
+                        .partial_cmp(&v.length_squared())
+                        .unwrap_or(Ordering::Equal)
+                })
+        }
+        _ => unreachable!("a simplex in 2d has at most three vertices"),
+    }
+}
+
+/// Returns a lower bound of the distance between two convex pieces and the direction
+/// from `a` to `b` it is measured along, or `None` if they overlap.
+///
+/// Runs the GJK distance algorithm on the Minkowski difference of the points.  The
+/// bound is the separation along the current direction, so it never exceeds the actual
+/// distance, even if the algorithm stops before it converges.
+fn convex_distance(a: &Convex, b: &Convex) -> Option<(f32, Vec2)> {
+    const MAX_ITERATIONS: usize = 32;
+    const TOLERANCE: f32 = 1.0e-6;
+
+    let support = |points: &[Vec2], d: Vec2| {
+        let mut best = points[0];
+        for &v in &points[1..] {
+            if v.dot(d) > best.dot(d) {
+                best = v;
+            }
+        }
+        best
+    };
+
+    let mut closest = b.points[0] - a.points[0];
+    let mut simplex: SmallVec<[Vec2; 3]> = smallvec![closest];
+    let mut separation = (0.0, Vec2::zero());
+    for _ in 0..MAX_ITERATIONS {
+        let distance = closest.length_squared();
+        if distance <= TOLERANCE * TOLERANCE {
+            return None;
+        }
+        let w = support(&b.points, -closest) - support(&a.points, closest);
+        // the difference is at least this far from the origin along `closest`
+        let normal = closest / distance.sqrt();
+        separation = (normal.dot(w), normal);
+        // no point of the difference is closer to the origin than the simplex
+        if distance - closest.dot(w) <= distance * TOLERANCE {
+            break;
+        }
+        simplex.push(w);
+        let (point, reduced) = closest_to_origin(&simplex)?;
+        if point.length_squared() >= distance {
+            break;
+        }
+        closest = point;
+        simplex = reduced;
+    }
+
+    let (separation, normal) = separation;
+    let separation = separation - a.radius - b.radius;
+    if separation <= 0.0 {
+        return None;
+    }
+    Some((separation, normal))
+}
+
+/// Returns a lower bound of the distance between two colliders and the direction from `a`
+/// to `b` it is measured along, or `None` if they overlap.
+///
+/// Tilemaps only take their blocks in `a_box` and `b_box` into account, the distance
+/// is infinite if there are none.
+fn distance(a: &Obb, a_box: (Vec2, Vec2), b: &Obb, b_box: (Vec2, Vec2)) -> Option<(f32, Vec2)> {
+    let pieces_a = convex_pieces(a, a_box.0, a_box.1);
+    let pieces_b = convex_pieces(b, b_box.0, b_box.1);
+    let mut result = (f32::INFINITY, Vec2::zero());
+    for piece_a in &pieces_a {
+        for piece_b in &pieces_b {
+            let (distance, normal) = convex_distance(piece_a, piece_b)?;
+            if distance < result.0 {
+                result = (distance, normal);
+            }
+        }
+    }
+    Some(result)
+}
+
+/// Finds the first time in `0.0..=1.0` at which `at` returns a manifold, if the
+/// colliders are apart at time zero.
+///
+/// `moving` returns the first collider at a time of the sweep, during which it translates
+/// by `linear` and none of its points moves further than `angular` because of rotation,
+/// while `other` stays in place.  `at` collides both colliders at a time.
+///
+/// Uses conservative advancement: each iteration moves to the earliest time at which
+/// the colliders could touch, given their distance and how fast they approach each other.
+/// The sweep is never sampled, so it can't step over thin colliders, however far they move.
+pub(super) fn sweep(
+    moving: impl Fn(f32) -> Obb,
+    other: &Obb,
+    linear: Vec2,
+    angular: f32,
+    at: impl Fn(f32) -> Option<Manifold>,
+) -> Option<(f32, Manifold)> {
+    // a guard against slow convergence, each iteration advances the sweep
+    const MAX_ITERATIONS: usize = 64;
+    // how close the colliders get before they are considered touching, relative to their size
+    const TOLERANCE: f32 = 0.01;
+
+    let start = moving(0.0);
+    let end = moving(1.0);
+    let tolerance = sweep_step(&start, other) * TOLERANCE;
+
+    // the tilemap blocks that can be touched lie on the way of the other collider
+    let reach = Vec2::splat(angular);
+    let swept = (
+        start.min().min(end.min()) - reach,
+        start.max().max(end.max()) + reach,
+    );
+    let (min, max) = (other.min(), other.max());
+    let passed = (min.min(min - linear) - reach, max.max(max - linear) + reach);
+
+    let mut time = 0.0;
+    for _ in 0..MAX_ITERATIONS {
+        let (distance, normal) = match distance(&moving(time), passed, other, swept) {
+            Some(separation) => separation,
+            None => break,
+        };
+        if distance <= tolerance {
+            break;
+        }
+        let approach = linear.dot(normal) + angular;
+        if approach <= 0.0 {
+            return None;
+        }
+        time += distance / approach;
+        if time > 1.0 {
+            return None;
+        }
+    }
+
+    // the colliders touch at `time`, a little later they overlap enough for a manifold
+    let speed = linear.length() + angular;
+    let time = (time + 2.0 * tolerance / speed).min(1.0);
+    at(time).map(|manifold| (time, manifold))
+}
+
+/// Finds the first time during the last step at which two swept colliders touch.
+///
+/// Both colliders are moved back along their motion, only their positions are swept.
+/// Returns the time in `0.0..=1.0` and the manifold at that time, or `None` if the
+/// colliders already touched at the start of the step, never touched, or didn't approach
+/// each other by more than half of the smallest collider, which the discrete test handles.
+pub fn time_of_impact(a: &Obb, b: &Obb) -> Option<(f32, Manifold)> {
+    let relative = a.motion - b.motion;
+    let step = sweep_step(a, b);
+    if relative.length() <= step {
+        return None;
+    }
+
+    let (start_a, start_b) = (a.rewound(0.0), b.rewound(0.0));
+    if collide(&start_a, &start_b).is_some() {
+        return None;
+    }
+
+    // relative to `b`, `a` moves along the difference of their motions
+    let moving = |time: f32| {
+        let mut obb = start_a.clone();
+        obb.position += relative * time;
+        obb
+    };
+    let at = |time: f32| collide(&a.rewound(time), &b.rewound(time));
+    let (time, manifold) = sweep(moving, &start_b, relative, 0.0, at)?;
+    if relative.dot(manifold.normal) <= step {
+        return None;
+    }
+    Some((time, manifold))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collider(id: u32, shape: Shape, position: Vec2) -> Obb {
+        Obb::new(
+            Status::Static,
+            Entity::from_id(id),
+            Entity::from_id(id + 100),
+            Mat2::identity(),
+            position,
+            shape.geometry(),
+            CollisionLayers::default(),
+        )
+    }
+
+    fn assert_manifold(a: Shape, b: Shape, offset: Vec2, normal: Vec2, penetration: f32) {
+        let a = collider(1, a, Vec2::zero());
+        let b = collider(2, b, offset);
+        let manifold = collide(&a, &b).expect("the shapes should collide");
+        assert_eq!(manifold.body1, a.body);
+        assert_eq!(manifold.body2, b.body);
+        assert_eq!(manifold.shapes[0], (a.shape, b.shape));
+        assert!(
+            (manifold.normal - normal).length() < 1.0e-4,
+            "normal {:?}, expected {:?}",
+            manifold.normal,
+            normal
+        );
+        assert!(
+            (manifold.penetration - penetration).abs() < 1.0e-4,
+            "penetration {}, expected {}",
+            manifold.penetration,
+            penetration
+        );
+        assert!(!manifold.contacts.is_empty());
+    }
+
+    fn square() -> Shape {
+        Shape::from(Size2::new(2.0, 2.0))
+    }
+
+    #[test]
+    fn polygon_to_polygon() {
+        let offset = Vec2::new(1.5, 0.0);
+        assert_manifold(square(), square(), offset, Vec2::new(1.0, 0.0), 0.5);
+        assert_manifold(square(), square(), -offset, Vec2::new(-1.0, 0.0), 0.5);
+        let a = collider(1, square(), Vec2::zero());
+        let b = collider(2, square(), Vec2::new(2.5, 0.0));
+        assert!(collide(&a, &b).is_none());
+
+        let points = [
+            Vec2::new(-1.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, -1.0),
+        ];
+        let triangle = Shape::polygon(&points).unwrap();
+        let offset = Vec2::new(0.0, 1.5);
+        assert_manifold(triangle, square(), offset, Vec2::new(0.0, 1.0), 0.5);
+    }
+
+    #[test]
+    fn polygon_rejects_collinear_points() {
+        let points = [Vec2::zero(), Vec2::new(1.0, 1.0), Vec2::new(2.0, 2.0)];
+        assert!(Shape::polygon(&points).is_none());
+    }
+
+    #[test]
+    fn polygon_to_circle() {
+        let offset = Vec2::new(1.5, 0.0);
+        let circle = Shape::circle(1.0);
+        assert_manifold(square(), circle.clone(), offset, Vec2::new(1.0, 0.0), 0.5);
+        assert_manifold(circle, square(), offset, Vec2::new(1.0, 0.0), 0.5);
+    }
+
+    #[test]
+    fn polygon_to_capsule() {
+        let offset = Vec2::new(1.5, 0.0);
+        let capsule = Shape::capsule(2.0, 1.0);
+        assert_manifold(square(), capsule.clone(), offset, Vec2::new(1.0, 0.0), 0.5);
+        assert_manifold(capsule, square(), offset, Vec2::new(1.0, 0.0), 0.5);
+    }
+
+    #[test]
+    fn round_to_round() {
+        let offset = Vec2::new(1.5, 0.0);
+        let circle = Shape::circle(1.0);
+        let capsule = Shape::capsule(2.0, 1.0);
+        let normal = Vec2::new(1.0, 0.0);
+        assert_manifold(circle.clone(), circle.clone(), offset, normal, 0.5);
+        assert_manifold(circle.clone(), capsule.clone(), offset, normal, 0.5);
+        assert_manifold(capsule.clone(), circle, offset, normal, 0.5);
+        assert_manifold(capsule.clone(), capsule, offset, normal, 0.5);
+    }
+
+    #[test]
+    fn tilemap_to_other() {
+        let map = TileMap::new(vec![true; 3], 3, Size2::new(1.0, 1.0)).unwrap();
+        let offset = Vec2::new(1.5, 1.25);
+        let normal = Vec2::new(0.0, 1.0);
+        let tilemap = Shape::tilemap(map);
+        let square = Shape::from(Size2::new(1.0, 1.0));
+        assert_manifold(tilemap.clone(), square, offset, normal, 0.25);
+        assert_manifold(tilemap.clone(), Shape::circle(0.5), offset, normal, 0.25);
+        assert_manifold(
+            tilemap,
+            Shape::capsule(0.5, 0.5),
+            offset + Vec2::new(0.0, 0.25),
+            normal,
+            0.25,
+        );
+    }
+}
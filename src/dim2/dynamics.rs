@@ -0,0 +1,274 @@
+//! An iterative impulse solver for dynamic rigid bodies.
+//!
+//! Every manifold with a dynamic body becomes a contact constraint.  The
+//! velocities of all bodies in these constraints are solved together with
+//! accumulated impulses at every contact point, including restitution and
+//! friction, and the remaining penetration is corrected afterwards.
+
+use bevy::math::*;
+use bevy::prelude::*;
+use hashbrown::HashMap;
+use smallvec::SmallVec;
+
+use super::{Manifold, RigidBody};
+use crate::common::Status;
+
+/// The number of velocity iterations per step.
+const ITERATIONS: usize = 10;
+/// The fraction of the penetration that is corrected every step.
+const CORRECTION: f32 = 0.8;
+/// The penetration that is left uncorrected to keep resting contacts stable.
+const SLOP: f32 = 0.01;
+
+struct Body {
+    entity: Entity,
+    linvel: Vec2,
+    angvel: f32,
+    inv_mass: f32,
+    inv_inertia: f32,
+    correction: Vec2,
+}
+
+impl Body {
+    fn new(entity: Entity, body: &RigidBody) -> Self {
+        // semikinematic bodies push with their mass, but never rotate
+        let (inv_mass, inv_inertia) = match body.status {
+            Status::Static | Status::Kinematic => (0.0, 0.0),
+            Status::Semikinematic => (body.inv_mass, 0.0),
+            Status::Dynamic => (body.inv_mass, body.inv_inertia),
+        };
+        Self {
+            entity,
+            linvel: body.linvel,
+            angvel: body.angvel,
+            inv_mass,
+            inv_inertia,
+            correction: Vec2::zero(),
+        }
+    }
+
+    fn velocity_at(&self, r: Vec2) -> Vec2 {
+        self.linvel + Vec2::new(-self.angvel * r.y(), self.angvel * r.x())
+    }
+
+    fn apply_impulse(&mut self, r: Vec2, impulse: Vec2) {
+        self.linvel += impulse * self.inv_mass;
+        self.angvel += cross(r, impulse) * self.inv_inertia;
+    }
+
+    fn effective_mass(&self, r: Vec2, axis: Vec2) -> f32 {
+        let rn = cross(r, axis);
+        self.inv_mass + self.inv_inertia * rn * rn
+    }
+}
+
+struct Point {
+    r1: Vec2,
+    r2: Vec2,
+    normal_mass: f32,
+    tangent_mass: f32,
+    bounce: f32,
+    normal_impulse: f32,
+    tangent_impulse: f32,
+}
+
+struct Constraint {
+    manifold: usize,
+    body1: usize,
+    body2: usize,
+    normal: Vec2,
+    penetration: f32,
+    friction: f32,
+    points: SmallVec<[Point; 4]>,
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x() * b.y() - a.y() * b.x()
+}
+
+fn pair(bodies: &mut [Body], a: usize, b: usize) -> (&mut Body, &mut Body) {
+    if a < b {
+        let (left, right) = bodies.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = bodies.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+/// Solves all manifolds that contain a dynamic body.
+///
+/// Contacts approaching slower than `resting` don't bounce.  The normal impulse
+/// applied at every point is written back into the contacts of the manifolds.
+pub(super) fn solve(manifolds: &mut [Manifold], query: &Query<Mut<RigidBody>>, resting: f32) {
+    if manifolds.is_empty() {
+        return;
+    }
+
+    let mut bodies = Vec::new();
+    let mut indices = HashMap::new();
+    let mut constraints = Vec::new();
+    for (index, manifold) in manifolds.iter().enumerate() {
+        let a = query.get::<RigidBody>(manifold.body1).unwrap();
+        let b = query.get::<RigidBody>(manifold.body2).unwrap();
+        let body1 = *indices.entry(manifold.body1).or_insert_with(|| {
+            bodies.push(Body::new(manifold.body1, &a));
+            bodies.len() - 1
+        });
+        let body2 = *indices.entry(manifold.body2).or_insert_with(|| {
+            bodies.push(Body::new(manifold.body2, &b));
+            bodies.len() - 1
+        });
+
+        let (b1, b2) = (&bodies[body1], &bodies[body2]);
+        if b1.inv_mass + b2.inv_mass == 0.0 {
+            continue;
+        }
+
+        let restitution = a.material.combine_restitution(&b.material);
+        let friction = a.material.combine_friction(&b.material);
+        let normal = manifold.normal;
+        let tangent = Vec2::new(-normal.y(), normal.x());
+        let points = manifold
+            .contacts
+            .iter()
+            .map(|contact| {
+                let r1 = contact.position - a.center_of_mass();
+                let r2 = contact.position - b.center_of_mass();
+                let normal_mass = b1.effective_mass(r1, normal) + b2.effective_mass(r2, normal);
+                let tangent_mass = b1.effective_mass(r1, tangent) + b2.effective_mass(r2, tangent);
+                let vn = (b2.velocity_at(r2) - b1.velocity_at(r1)).dot(normal);
+                let bounce = if vn < -resting {
+                    -restitution * vn
+                } else {
+                    0.0
+                };
+                Point {
+                    r1,
+                    r2,
+                    normal_mass: normal_mass.recip(),
+                    tangent_mass: tangent_mass.recip(),
+                    bounce,
+                    normal_impulse: 0.0,
+                    tangent_impulse: 0.0,
+                }
+            })
+            .collect();
+        constraints.push(Constraint {
+            manifold: index,
+            body1,
+            body2,
+            normal,
+            penetration: manifold.penetration,
+            friction,
+            points,
+        });
+    }
+
+    for _ in 0..ITERATIONS {
+        for constraint in &mut constraints {
+            let (b1, b2) = pair(&mut bodies, constraint.body1, constraint.body2);
+            let normal = constraint.normal;
+            let tangent = Vec2::new(-normal.y(), normal.x());
+            for point in &mut constraint.points {
+                let vt = (b2.velocity_at(point.r2) - b1.velocity_at(point.r1)).dot(tangent);
+                let limit = constraint.friction * point.normal_impulse;
+                let total = (point.tangent_impulse - vt * point.tangent_mass)
+                    .max(-limit)
+                    .min(limit);
+                let impulse = tangent * (total - point.tangent_impulse);
+                point.tangent_impulse = total;
+                b1.apply_impulse(point.r1, -impulse);
+                b2.apply_impulse(point.r2, impulse);
+
+                let vn = (b2.velocity_at(point.r2) - b1.velocity_at(point.r1)).dot(normal);
+                let total =
+                    (point.normal_impulse + (point.bounce - vn) * point.normal_mass).max(0.0);
+                let impulse = normal * (total - point.normal_impulse);
+                point.normal_impulse = total;
+                b1.apply_impulse(point.r1, -impulse);
+                b2.apply_impulse(point.r2, impulse);
+            }
+        }
+    }
+
+    for constraint in &constraints {
+        let contacts = &mut manifolds[constraint.manifold].contacts;
+        for (contact, point) in contacts.iter_mut().zip(&constraint.points) {
+            contact.impulse = point.normal_impulse;
+        }
+
+        let (b1, b2) = pair(&mut bodies, constraint.body1, constraint.body2);
+        let depth = (constraint.penetration - SLOP).max(0.0) * CORRECTION;
+        let correction = constraint.normal * (depth / (b1.inv_mass + b2.inv_mass));
+        b1.correction -= correction * b1.inv_mass;
+        b2.correction += correction * b2.inv_mass;
+    }
+
+    for body in bodies {
+        let mut rigid = query.get_mut::<RigidBody>(body.entity).unwrap();
+        if matches!(rigid.status, Status::Static | Status::Kinematic) {
+            continue;
+        }
+        rigid.linvel = body.linvel;
+        rigid.angvel = body.angvel;
+        rigid.position += body.correction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{app, body, floor, platformer, spawn};
+    use super::super::{Shape, Size2};
+    use super::*;
+    use crate::common::Mass;
+
+    fn crate_(position: Vec2) -> RigidBody {
+        RigidBody::new(Mass::Real(1.0))
+            .with_status(Status::Dynamic)
+            .with_position(position)
+    }
+
+    fn square() -> Shape {
+        Shape::from(Size2::new(10.0, 10.0))
+    }
+
+    #[test]
+    fn dropped_box_comes_to_rest() {
+        let mut app = platformer();
+        floor(&mut app, 1);
+        let dropped = spawn(&mut app, 2, crate_(Vec2::new(0.0, 30.0)), square());
+
+        for _ in 0..180 {
+            app.update();
+        }
+        let dropped = body(&app, dropped);
+        assert!(
+            (dropped.position.y() - 10.0).abs() < 0.5,
+            "{:?}",
+            dropped.position
+        );
+        assert!(dropped.linvel.length() < 1.0, "{:?}", dropped.linvel);
+        assert!(dropped.angvel.abs() < 0.05, "{}", dropped.angvel);
+        assert!(dropped.rotation.abs() < 0.01, "{}", dropped.rotation);
+    }
+
+    #[test]
+    fn off_center_hit_turns_box() {
+        let mut app = app();
+        let target = spawn(&mut app, 1, crate_(Vec2::zero()), square());
+        // a kinematic bat sweeps through the lower half of the box
+        let bat = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Kinematic)
+            .with_position(Vec2::new(-20.0, -6.0))
+            .with_linear_velocity(Vec2::new(300.0, 0.0));
+        spawn(&mut app, 2, bat, square());
+
+        for _ in 0..10 {
+            app.update();
+        }
+        let target = body(&app, target);
+        assert!(target.linvel.x() > 0.0, "{:?}", target.linvel);
+        assert!(target.angvel > 0.1, "{}", target.angvel);
+    }
+}
@@ -0,0 +1,3134 @@
+//! This module provides the primitives and systems for 2d physics simulation.
+//!
+//! For examples, see the root of the crate.
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::f32::consts::PI;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use bevy::ecs::{IntoThreadLocalSystem, ParallelExecutor, Schedule};
+use bevy::math::*;
+use bevy::prelude::*;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use smallvec::{smallvec, SmallVec};
+
+use crate::broad::{self, BoundingBox, Collider};
+use crate::common::*;
+
+mod collision;
+mod dynamics;
+mod query;
+mod tilemap;
+
+pub use query::{Overlap, RayHit, ShapeHit};
+pub use tilemap::TileMap;
+
+/// This is what you want to add to your `App` if you want to run 2d physics simulation.
+///
+/// For lockstep networking and replays, add `Physics2dPlugin::deterministic()` instead.
+pub struct Physics2dPlugin;
+
+impl Physics2dPlugin {
+    /// Returns a plugin that runs 2d physics simulation in deterministic mode.
+    ///
+    /// Deterministic mode takes exactly one step every frame, no matter how long
+    /// the frame took, so the simulation runs slower or faster along with the
+    /// frame rate.  Lockstep games advance their frames at a fixed rate of their
+    /// own, replays step through the recorded inputs one frame at a time.
+    pub fn deterministic() -> impl Plugin {
+        DeterministicPhysics2dPlugin
+    }
+}
+
+/// The plugin returned by `Physics2dPlugin::deterministic`.
+struct DeterministicPhysics2dPlugin;
+
+impl Plugin for DeterministicPhysics2dPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        Physics2dPlugin.build(app);
+        app.add_resource(FixedTimestep::default().with_deterministic(true));
+    }
+}
+
+pub mod stage {
+    #[doc(hidden)]
+    pub use bevy::prelude::stage::*;
+
+    pub const FIXED_TIMESTEP: &str = "fixed_timestep";
+    pub const PHYSICS: &str = "physics";
+    pub const COLLIDING_JOINT: &str = "colliding_joint";
+    pub const PHYSICS_STEP: &str = "physics_step";
+    pub const BROAD_PHASE: &str = "broad_phase";
+    pub const NARROW_PHASE: &str = "narrow_phase";
+    pub const PHYSICS_SOLVE: &str = "physics_solve";
+    pub const RIGID_JOINT: &str = "rigid_joint";
+    pub const SYNC_TRANSFORM: &str = "sync_transform";
+}
+
+impl Plugin for Physics2dPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(GlobalFriction::default())
+            .add_resource(GlobalGravity::default())
+            .add_resource(TranslationMode::default())
+            .add_resource(RotationMode::default())
+            .add_resource(GlobalStep::default())
+            .add_resource(GlobalUp::default())
+            .add_resource(AngularTolerance::default())
+            .add_resource(RestingSpeed::default())
+            .add_resource(FixedTimestep::default())
+            .add_resource(StateHash::default())
+            .add_resource(BroadPhase::default())
+            .add_resource(Triggers::default())
+            .add_resource(Solved::default())
+            .add_event::<Manifold>()
+            .add_event::<CollisionStarted>()
+            .add_event::<CollisionOngoing>()
+            .add_event::<CollisionEnded>()
+            .add_event::<TriggerEntered>()
+            .add_event::<TriggerExited>()
+            .add_stage_before(stage::UPDATE, stage::PHYSICS)
+            .add_stage_before(stage::PHYSICS, stage::FIXED_TIMESTEP)
+            .add_stage_after(stage::PHYSICS, stage::SYNC_TRANSFORM);
+        let mut physics = PhysicsSchedule::default();
+        let physics_step = PhysicsStep::default().system(app.resources_mut());
+        let solver = Solver::default().system(app.resources_mut());
+        let tracker = ContactTracker::default().system(app.resources_mut());
+        physics
+            .add_system_to_stage(stage::PHYSICS_STEP, physics_step)
+            .add_system_to_stage(stage::BROAD_PHASE, broad_phase_system.system())
+            .add_system_to_stage(stage::NARROW_PHASE, narrow_phase_system.system())
+            .add_system_to_stage(stage::PHYSICS_SOLVE, solver)
+            .add_system_to_stage(stage::PHYSICS_SOLVE, tracker)
+            .add_system_to_stage(
+                FixedJointBehaviour::STAGE,
+                joint_system::<FixedJointBehaviour>.system(),
+            )
+            .add_system_to_stage(
+                MechanicalJointBehaviour::STAGE,
+                joint_system::<MechanicalJointBehaviour>.system(),
+            )
+            .add_system_to_stage(
+                SpringJointBehaviour::STAGE,
+                joint_system::<SpringJointBehaviour>.system(),
+            );
+        app.add_resource(physics)
+            .add_system_to_stage(stage::FIXED_TIMESTEP, fixed_timestep_system.system())
+            .add_system_to_stage(stage::PHYSICS, physics_system())
+            .add_system_to_stage(stage::SYNC_TRANSFORM, sync_transform_system.system())
+            .add_system_to_stage(stage::SYNC_TRANSFORM, state_hash_system.system())
+            .add_system_to_stage(stage::LAST, broad_phase_changes_system.system());
+    }
+}
+
+pub type BroadPhase = broad::BroadPhase<Obb>;
+
+/// The stages that run once for every fixed step, from `COLLIDING_JOINT` to `RIGID_JOINT`.
+///
+/// Systems that have to run on every step, like the `joint_system` of a custom
+/// `JointBehaviour`, are added here instead of to the `App`.
+pub struct PhysicsSchedule {
+    schedule: Schedule,
+}
+
+impl Default for PhysicsSchedule {
+    fn default() -> Self {
+        let mut schedule = Schedule::default();
+        schedule.add_stage(stage::COLLIDING_JOINT);
+        schedule.add_stage_after(stage::COLLIDING_JOINT, stage::PHYSICS_STEP);
+        schedule.add_stage_after(stage::PHYSICS_STEP, stage::BROAD_PHASE);
+        schedule.add_stage_after(stage::BROAD_PHASE, stage::NARROW_PHASE);
+        schedule.add_stage_after(stage::NARROW_PHASE, stage::PHYSICS_SOLVE);
+        schedule.add_stage_after(stage::PHYSICS_SOLVE, stage::RIGID_JOINT);
+        Self { schedule }
+    }
+}
+
+impl PhysicsSchedule {
+    /// Adds a system to one of the stages that run on every step.
+    pub fn add_system_to_stage(
+        &mut self,
+        stage_name: &'static str,
+        system: Box<dyn System>,
+    ) -> &mut Self {
+        self.schedule.add_system_to_stage(stage_name, system);
+        self
+    }
+}
+
+/// Returns the system that runs the `PhysicsSchedule` once for every step of the current frame.
+fn physics_system() -> Box<dyn System> {
+    // trackers are cleared by the app at the end of the frame, not after every step
+    let mut executor = ParallelExecutor::without_tracker_clears();
+    let run = move |world: &mut World, resources: &mut Resources| {
+        let steps = resources
+            .get::<FixedTimestep>()
+            .map_or(0, |timestep| timestep.steps());
+        if steps == 0 {
+            return;
+        }
+
+        // the schedule is taken out of the resources while it runs on them
+        let mut schedule = match resources.get_mut::<PhysicsSchedule>() {
+            Some(mut physics) => mem::take(&mut physics.schedule),
+            None => return,
+        };
+        for _ in 0..steps {
+            schedule.initialize(world, resources);
+            executor.run(&mut schedule, world, resources);
+        }
+        if let Some(mut physics) = resources.get_mut::<PhysicsSchedule>() {
+            physics.schedule = schedule;
+        }
+    };
+    run.thread_local_system()
+}
+
+/// The global gravity that affects every `RigidBody` with the `Semikinematic` status.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct GlobalGravity(pub Vec2);
+
+/// The global step value, affects all semikinematic bodies.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct GlobalStep(pub f32);
+
+/// The global up vector, affects all semikinematic bodies.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct GlobalUp(pub Vec2);
+
+/// The fixed timestep of the physics simulation, affects all bodies.
+///
+/// Every frame, the `PhysicsSchedule` runs once for every step that fits into
+/// the accumulated frame time, and every step simulates exactly one step
+/// duration.  The time left over is kept for the next frame and used to
+/// interpolate transforms.  At most `max_steps` steps are taken per frame, the
+/// time beyond that is dropped so that slow frames don't fall further behind.
+///
+/// In deterministic mode, exactly one step is taken every frame and the frame
+/// time is ignored, so the speed of the simulation depends on the frame rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+    steps: usize,
+    max_steps: usize,
+    deterministic: bool,
+}
+
+impl FixedTimestep {
+    /// Returns a new `FixedTimestep` with a number of steps per second.
+    pub fn from_rate(rate: f32) -> Self {
+        Self::from_step(rate.recip())
+    }
+
+    /// Returns a new `FixedTimestep` with the duration of a single step in seconds.
+    pub fn from_step(step: f32) -> Self {
+        Self {
+            step,
+            accumulator: 0.0,
+            steps: 0,
+            max_steps: 5,
+            deterministic: false,
+        }
+    }
+
+    /// Returns a `FixedTimestep` identical to this one, but with the maximum number of steps per frame set.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Returns a `FixedTimestep` identical to this one, but with the deterministic mode set.
+    ///
+    /// In deterministic mode, every frame takes exactly one fixed step no matter
+    /// how long the frame took, colliders and manifolds are processed in the
+    /// order of their entities, and the `StateHash` is updated after every step.
+    /// The simulation runs at real time speed only at one frame per step.
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Gets the duration of a single step in seconds.
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Gets the number of steps per second.
+    pub fn rate(&self) -> f32 {
+        self.step.recip()
+    }
+
+    /// Sets the number of steps per second.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.step = rate.recip();
+    }
+
+    /// Gets the maximum number of steps per frame.
+    pub fn max_steps(&self) -> usize {
+        self.max_steps
+    }
+
+    /// Sets the maximum number of steps per frame.
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    /// Returns true if the simulation runs in deterministic mode.
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Returns true if the physics stages are taking at least one step in the current frame.
+    pub fn is_stepping(&self) -> bool {
+        self.steps > 0
+    }
+
+    /// Gets the number of steps the physics stages take in the current frame.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Returns how far the current frame is between the last step and the next one, from 0 to 1.
+    ///
+    /// This is used to interpolate the transforms of rigid bodies.
+    pub fn alpha(&self) -> f32 {
+        if self.deterministic {
+            return 1.0;
+        }
+        (self.accumulator / self.step).max(0.0).min(1.0)
+    }
+
+    fn advance(&mut self, delta_time: f32) {
+        if self.deterministic {
+            self.steps = 1;
+            return;
+        }
+
+        self.accumulator += delta_time;
+        let steps = (self.accumulator / self.step).floor().max(0.0) as usize;
+        self.accumulator -= steps as f32 * self.step;
+        // the steps over the limit are dropped, only the remainder is kept
+        self.steps = steps.min(self.max_steps);
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::from_rate(60.0)
+    }
+}
+
+/// A hash over the state of all rigid bodies, only updated in deterministic mode.
+///
+/// Two runs of the same simulation with the same inputs have the same hash
+/// after every step, so comparing hashes detects when two runs diverge.  The
+/// entities are part of the hash, so both runs must spawn their bodies with the
+/// same ids, e.g. with `World::spawn_as_entity`.  Hashes are only comparable
+/// between binaries built with the same compiler.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateHash(pub u64);
+
+/// The global angular tolerance in radians, affects all semikinematic bodies.
+///
+/// This is used for step calculation and for push dynamics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AngularTolerance(pub f32);
+
+impl Default for AngularTolerance {
+    fn default() -> Self {
+        Self(30.0_f32.to_radians())
+    }
+}
+
+/// The global resting speed, affects all bodies.
+///
+/// Bodies hitting each other slower than this don't bounce, whatever their
+/// restitution, so that resting contacts stay at rest.  The speed is raised to
+/// twice the speed that gravity adds in one step, if that is higher.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RestingSpeed(pub f32);
+
+impl Default for RestingSpeed {
+    fn default() -> Self {
+        Self(20.0)
+    }
+}
+
+/// Makes a body a one-way platform, affects all semikinematic bodies colliding with it.
+///
+/// The platform is only solid for bodies approaching it from the side its
+/// direction points to, i.e. bodies land on it from above, but jump through it
+/// from below.  A zero direction uses `GlobalUp` instead.  Bodies passing
+/// through it don't collide, so they never send collision events.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Properties)]
+pub struct OneWay(pub Vec2);
+
+/// Tracks the ground under a body, maintained by the solver for every body with this component.
+///
+/// A body is grounded while it touches another one with a normal within
+/// `AngularTolerance` of `GlobalUp`.  The coyote time keeps `can_jump` true
+/// for a short while after walking off a ledge.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Properties)]
+pub struct Grounded {
+    #[property(ignore)]
+    #[serde(skip)]
+    ground: Option<Entity>,
+    normal: Vec2,
+    velocity: Vec2,
+    airtime: f32,
+    coyote_time: f32,
+    coyote: bool,
+}
+
+impl Default for Grounded {
+    fn default() -> Self {
+        Self {
+            ground: None,
+            normal: Vec2::zero(),
+            velocity: Vec2::zero(),
+            airtime: f32::INFINITY,
+            coyote_time: 0.0,
+            coyote: false,
+        }
+    }
+}
+
+impl Grounded {
+    /// Returns a `Grounded` identical to this one, but with the coyote time set to a new one.
+    pub fn with_coyote_time(mut self, coyote_time: f32) -> Self {
+        self.coyote_time = coyote_time;
+        self
+    }
+
+    /// Gets the body this body stands on.
+    pub fn ground(&self) -> Option<Entity> {
+        self.ground
+    }
+
+    /// Returns true if this body stands on another one.
+    pub fn is_grounded(&self) -> bool {
+        self.ground.is_some()
+    }
+
+    /// Gets the normal of the current or last ground, pointing towards this body.
+    pub fn normal(&self) -> Vec2 {
+        self.normal
+    }
+
+    /// Gets the linear velocity of the current or last ground.
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity
+    }
+
+    /// Gets the time since this body left the ground, zero while grounded.
+    pub fn airtime(&self) -> f32 {
+        self.airtime
+    }
+
+    /// Gets the coyote time.
+    pub fn coyote_time(&self) -> f32 {
+        self.coyote_time
+    }
+
+    /// Sets the coyote time.
+    pub fn set_coyote_time(&mut self, coyote_time: f32) {
+        self.coyote_time = coyote_time;
+    }
+
+    /// Returns true while grounded, and for up to the coyote time after leaving
+    /// the ground unless `jump` was called since.
+    pub fn can_jump(&self) -> bool {
+        self.is_grounded() || (self.coyote && self.airtime <= self.coyote_time)
+    }
+
+    /// Uses up the coyote time, so that a body that jumped can't jump again in mid-air.
+    pub fn jump(&mut self) {
+        self.coyote = false;
+    }
+
+    fn update(&mut self, ground: Option<(Entity, Vec2, Vec2)>, delta_time: f32) {
+        match ground {
+            Some((ground, normal, velocity)) => {
+                self.ground = Some(ground);
+                self.normal = normal;
+                self.velocity = velocity;
+                self.airtime = 0.0;
+                self.coyote = true;
+            }
+            None => {
+                self.ground = None;
+                self.airtime += delta_time;
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    vertices: SmallVec<[Vec2; 8]>,
+    normals: SmallVec<[Vec2; 8]>,
+}
+
+impl Polygon {
+    /// Creates a new polygon from vertices in counter-clockwise order.
+    fn new(vertices: SmallVec<[Vec2; 8]>) -> Self {
+        let n = vertices.len();
+        let normals = (0..n)
+            .map(|i| {
+                let edge = vertices[(i + 1) % n] - vertices[i];
+                Vec2::new(edge.y(), -edge.x()).normalize()
+            })
+            .collect();
+        Self { vertices, normals }
+    }
+
+    /// Computes the convex hull of a set of points in counter-clockwise order.
+    ///
+    /// Uses Andrew's monotone chain algorithm.  Collinear points are not part of the hull.
+    fn hull(points: &[Vec2]) -> SmallVec<[Vec2; 8]> {
+        fn cross(o: Vec2, a: Vec2, b: Vec2) -> f32 {
+            let a = a - o;
+            let b = b - o;
+            a.x() * b.y() - a.y() * b.x()
+        }
+
+        let mut points: SmallVec<[Vec2; 8]> = points.iter().copied().collect();
+        points.sort_by(|a, b| {
+            a.x()
+                .partial_cmp(&b.x())
+                .unwrap_or(Ordering::Equal)
+                .then(a.y().partial_cmp(&b.y()).unwrap_or(Ordering::Equal))
+        });
+        points.dedup_by(|a, b| (*a - *b).length_squared() <= f32::EPSILON);
+
+        if points.len() < 3 {
+            return points;
+        }
+
+        let mut hull = SmallVec::<[Vec2; 8]>::new();
+        for &p in &points {
+            while hull.len() >= 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        let lower = hull.len() + 1;
+        for &p in points.iter().rev().skip(1) {
+            while hull.len() >= lower && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+        hull
+    }
+
+    pub fn get_support(&self, dir: Vec2) -> Vec2 {
+        let mut best_projection = f32::MIN;
+        let mut best_vertex = Vec2::zero();
+
+        for &v in &self.vertices {
+            let proj = v.dot(dir);
+
+            if proj > best_projection {
+                best_vertex = v;
+                best_projection = proj;
+            }
+        }
+
+        best_vertex
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Polygon(Polygon),
+    Circle { center: Vec2, radius: f32 },
+    Capsule { a: Vec2, b: Vec2, radius: f32 },
+    TileMap { offset: Vec2, map: TileMap },
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Obb {
+    status: Status,
+    body: Entity,
+    shape: Entity,
+    position: Vec2,
+    rotation: Mat2,
+    geometry: Geometry,
+    layers: CollisionLayers,
+    motion: Vec2,
+    sensor: bool,
+}
+
+impl Obb {
+    fn new(
+        status: Status,
+        body: Entity,
+        shape: Entity,
+        rotation: Mat2,
+        position: Vec2,
+        geometry: Geometry,
+        layers: CollisionLayers,
+    ) -> Self {
+        Self {
+            status,
+            body,
+            shape,
+            rotation,
+            position,
+            geometry,
+            layers,
+            motion: Vec2::zero(),
+            sensor: false,
+        }
+    }
+
+    /// Sweeps this collider backwards by `motion`, the distance it travelled this step.
+    fn with_motion(mut self, motion: Vec2) -> Self {
+        self.motion = motion;
+        self
+    }
+
+    /// Makes this collider a trigger, that is only tested for overlaps.
+    fn with_sensor(mut self, sensor: bool) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    /// Returns this collider moved back along its motion to a time during the
+    /// last step, from 0 at its start to 1 at its end, without a motion.
+    fn rewound(&self, time: f32) -> Self {
+        let mut obb = self.clone();
+        obb.position -= obb.motion * (1.0 - time);
+        obb.motion = Vec2::zero();
+        obb
+    }
+
+    /// Transforms a point from the local space of this collider into world space.
+    pub fn to_world(&self, v: Vec2) -> Vec2 {
+        self.rotation * v + self.position
+    }
+
+    pub fn min(&self) -> Vec2 {
+        match self.geometry {
+            Geometry::Polygon(ref polygon) => {
+                let mut min = self.to_world(polygon.vertices[0]);
+                for &v in &polygon.vertices[1..] {
+                    min = min.min(self.to_world(v));
+                }
+                min
+            }
+            Geometry::Circle { center, radius } => self.to_world(center) - Vec2::splat(radius),
+            Geometry::Capsule { a, b, radius } => {
+                self.to_world(a).min(self.to_world(b)) - Vec2::splat(radius)
+            }
+            Geometry::TileMap { offset, ref map } => {
+                let size = map.size();
+                let v0 = self.to_world(offset);
+                let v1 = self.to_world(offset + Vec2::new(size.width, 0.0));
+                let v2 = self.to_world(offset + Vec2::new(size.width, size.height));
+                let v3 = self.to_world(offset + Vec2::new(0.0, size.height));
+                v0.min(v1).min(v2).min(v3)
+            }
+        }
+    }
+
+    pub fn max(&self) -> Vec2 {
+        match self.geometry {
+            Geometry::Polygon(ref polygon) => {
+                let mut max = self.to_world(polygon.vertices[0]);
+                for &v in &polygon.vertices[1..] {
+                    max = max.max(self.to_world(v));
+                }
+                max
+            }
+            Geometry::Circle { center, radius } => self.to_world(center) + Vec2::splat(radius),
+            Geometry::Capsule { a, b, radius } => {
+                self.to_world(a).max(self.to_world(b)) + Vec2::splat(radius)
+            }
+            Geometry::TileMap { offset, ref map } => {
+                let size = map.size();
+                let v0 = self.to_world(offset);
+                let v1 = self.to_world(offset + Vec2::new(size.width, 0.0));
+                let v2 = self.to_world(offset + Vec2::new(size.width, size.height));
+                let v3 = self.to_world(offset + Vec2::new(0.0, size.height));
+                v0.max(v1).max(v2).max(v3)
+            }
+        }
+    }
+}
+
+impl Collider for Obb {
+    type Point = Vec2;
+
+    fn bounding_box(&self) -> BoundingBox<Self::Point> {
+        let (min, max) = (self.min(), self.max());
+        BoundingBox::new(min.min(min - self.motion), max.max(max - self.motion))
+    }
+
+    fn status(&self) -> Status {
+        self.status
+    }
+
+    fn body(&self) -> Entity {
+        self.body
+    }
+
+    fn shape(&self) -> Entity {
+        self.shape
+    }
+
+    fn layers(&self) -> CollisionLayers {
+        self.layers
+    }
+}
+
+/// The two dimensional size of a `Shape`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Property)]
+pub struct Size2 {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Size2 {
+    /// Returns a new 2d size.
+    pub fn new(width: f32, height: f32) -> Self {
+        Self { width, height }
+    }
+}
+
+/// The geometry of a `Shape`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Property)]
+pub enum ShapeKind {
+    /// A rectangle, with its bottom left corner at the offset of the shape.
+    Rectangle(Size2),
+    /// A convex polygon, with its vertices relative to the offset of the shape.
+    ///
+    /// The vertices are in counter-clockwise order.
+    Polygon(Vec<Vec2>),
+    /// A circle with a radius, centered at the offset of the shape.
+    Circle(f32),
+    /// A capsule, centered at the offset of the shape.
+    ///
+    /// The height is the distance between the centers of the two caps
+    /// along the local y axis.
+    Capsule { height: f32, radius: f32 },
+    /// A static grid of tiles, with its bottom left corner at the offset of the shape.
+    TileMap(TileMap),
+}
+
+/// The shape of a rigid body.
+///
+/// Contains a rotation/translation offset and a size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Properties)]
+pub struct Shape {
+    offset: Vec2,
+    kind: ShapeKind,
+    layers: CollisionLayers,
+    sensor: bool,
+}
+
+impl Shape {
+    /// Return a new `Shape` with a zero offset and a size.
+    pub fn new(size: Size2) -> Self {
+        let offset = Vec2::zero();
+        let kind = ShapeKind::Rectangle(size);
+        Self {
+            offset,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new convex polygon `Shape` with a zero offset, built from the convex hull of `points`.
+    ///
+    /// Returns `None` if the hull has less than three vertices, i.e. all points are collinear.
+    pub fn polygon(points: &[Vec2]) -> Option<Self> {
+        let hull = Polygon::hull(points);
+        if hull.len() < 3 {
+            return None;
+        }
+        let offset = Vec2::zero();
+        let kind = ShapeKind::Polygon(hull.into_vec());
+        Some(Self {
+            offset,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        })
+    }
+
+    /// Return a new circular `Shape` with a zero offset and a radius.
+    pub fn circle(radius: f32) -> Self {
+        let offset = Vec2::zero();
+        let kind = ShapeKind::Circle(radius);
+        Self {
+            offset,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new capsule `Shape` with a zero offset, a height and a radius.
+    pub fn capsule(height: f32, radius: f32) -> Self {
+        let offset = Vec2::zero();
+        let kind = ShapeKind::Capsule { height, radius };
+        Self {
+            offset,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new `Shape` with a zero offset, shaped like a grid of tiles.
+    ///
+    /// Tilemaps should only be attached to `Static` bodies.
+    pub fn tilemap(map: TileMap) -> Self {
+        let offset = Vec2::zero();
+        let kind = ShapeKind::TileMap(map);
+        Self {
+            offset,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new `Shape` with an offset and a size.
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Return a new `Shape` on some collision layers.
+    pub fn with_layers(mut self, layers: CollisionLayers) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Return a new `Shape` that is a trigger if `sensor` is set.
+    ///
+    /// Triggers never push other bodies and never show up in `Manifold`s, they
+    /// only send `TriggerEntered` and `TriggerExited` events.
+    pub fn with_sensor(mut self, sensor: bool) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    /// Get the geometry of this `Shape`.
+    pub fn kind(&self) -> &ShapeKind {
+        &self.kind
+    }
+
+    /// Get the collision layers of this `Shape`.
+    pub fn layers(&self) -> CollisionLayers {
+        self.layers
+    }
+
+    /// Set the collision layers of this `Shape`.
+    pub fn set_layers(&mut self, layers: CollisionLayers) {
+        self.layers = layers;
+    }
+
+    /// Get the sensor flag of this `Shape`.
+    pub fn is_sensor(&self) -> bool {
+        self.sensor
+    }
+
+    /// Set the sensor flag of this `Shape`.
+    pub fn set_sensor(&mut self, sensor: bool) {
+        self.sensor = sensor;
+    }
+
+    fn geometry(&self) -> Geometry {
+        match self.kind {
+            ShapeKind::Rectangle(size) => {
+                let v0 = self.offset;
+                let v1 = self.offset + Vec2::new(size.width, 0.0);
+                let v2 = self.offset + Vec2::new(size.width, size.height);
+                let v3 = self.offset + Vec2::new(0.0, size.height);
+                Geometry::Polygon(Polygon::new(smallvec![v0, v1, v2, v3]))
+            }
+            ShapeKind::Polygon(ref vertices) => {
+                let vertices = vertices.iter().map(|&v| v + self.offset).collect();
+                Geometry::Polygon(Polygon::new(vertices))
+            }
+            ShapeKind::Circle(radius) => Geometry::Circle {
+                center: self.offset,
+                radius,
+            },
+            ShapeKind::Capsule { height, radius } => {
+                let half = Vec2::new(0.0, height * 0.5);
+                Geometry::Capsule {
+                    a: self.offset - half,
+                    b: self.offset + half,
+                    radius,
+                }
+            }
+            ShapeKind::TileMap(ref map) => Geometry::TileMap {
+                offset: self.offset,
+                map: map.clone(),
+            },
+        }
+    }
+
+    /// Returns the lowest point of this shape along `up`, relative to the body.
+    fn lowest_point(&self, rotation: Mat2, up: Vec2) -> Vec2 {
+        let (points, radius): (SmallVec<[Vec2; 4]>, f32) = match self.geometry() {
+            Geometry::Polygon(polygon) => (polygon.vertices.iter().copied().collect(), 0.0),
+            Geometry::Circle { center, radius } => (smallvec![center], radius),
+            Geometry::Capsule { a, b, radius } => (smallvec![a, b], radius),
+            Geometry::TileMap { offset, map } => {
+                let size = map.size();
+                let points = smallvec![
+                    offset,
+                    offset + Vec2::new(size.width, 0.0),
+                    offset + Vec2::new(size.width, size.height),
+                    offset + Vec2::new(0.0, size.height),
+                ];
+                (points, 0.0)
+            }
+        };
+        let points: SmallVec<[Vec2; 4]> = points.into_iter().map(|v| rotation * v).collect();
+        let s: SmallVec<[f32; 4]> = points.iter().map(|v| v.dot(up)).collect();
+        let min = s.iter().copied().fold(f32::INFINITY, f32::min);
+        let mut lowest_point = Vec2::zero();
+        let mut count = 0;
+        for (&v, &s) in points.iter().zip(&s) {
+            // clippy "gently recommends" doing this
+            if (s - min).abs() < f32::EPSILON {
+                lowest_point += v;
+                count += 1;
+            }
+        }
+        lowest_point /= count as f32;
+        if radius > 0.0 && up.length_squared() != 0.0 {
+            lowest_point -= up.normalize() * radius;
+        }
+        lowest_point
+    }
+
+    /// Returns the area of this shape, its centroid and its second moment of area
+    /// around the origin of the body, i.e. its inertia per unit of density.
+    fn mass_properties(&self) -> (f32, Vec2, f32) {
+        match self.geometry() {
+            Geometry::Polygon(polygon) => {
+                let vertices = &polygon.vertices;
+                let mut area = 0.0;
+                let mut centroid = Vec2::zero();
+                let mut moment = 0.0;
+                for (i, &v0) in vertices.iter().enumerate() {
+                    let v1 = vertices[(i + 1) % vertices.len()];
+                    let cross = v0.x() * v1.y() - v0.y() * v1.x();
+                    area += cross * 0.5;
+                    centroid += (v0 + v1) * (cross / 6.0);
+                    moment += cross * (v0.dot(v0) + v0.dot(v1) + v1.dot(v1)) / 12.0;
+                }
+                if area > 0.0 {
+                    centroid /= area;
+                }
+                (area, centroid, moment)
+            }
+            Geometry::Circle { center, radius } => {
+                let area = PI * radius * radius;
+                let moment = area * (radius * radius * 0.5 + center.length_squared());
+                (area, center, moment)
+            }
+            Geometry::Capsule { a, b, radius } => {
+                // a rectangle between the centers of the caps, plus both caps as one circle
+                let center = (a + b) * 0.5;
+                let height = (b - a).length();
+                let rect = 2.0 * radius * height;
+                let circle = PI * radius * radius;
+                let area = rect + circle;
+                let moment = rect * (4.0 * radius * radius + height * height) / 12.0
+                    + circle * (radius * radius * 0.5 + height * height * 0.25)
+                    + area * center.length_squared();
+                (area, center, moment)
+            }
+            Geometry::TileMap { .. } => (0.0, Vec2::zero(), 0.0),
+        }
+    }
+}
+
+impl From<Size2> for Shape {
+    fn from(size: Size2) -> Self {
+        let x = size.width * 0.5;
+        let y = size.height * 0.5;
+        let offset = Vec2::new(-x, -y);
+        let kind = ShapeKind::Rectangle(size);
+        Self {
+            offset,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InnerJoint {
+    body1: Entity,
+    body2: Entity,
+    offset: Vec2,
+    angle: f32,
+}
+
+impl InnerJoint {
+    pub fn new(body1: Entity, body2: Entity) -> Self {
+        Self {
+            body1,
+            body2,
+            offset: Vec2::zero(),
+            angle: 0.0,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_angle(mut self, angle: f32) -> Self {
+        self.angle = angle;
+        self
+    }
+}
+
+/// Defines a set of behaviours on how joints should move the anchored body relative to the anchor.
+pub trait JointBehaviour: Send + Sync + 'static {
+    const STAGE: &'static str = stage::COLLIDING_JOINT;
+
+    /// Returns a new position for target based on `self` and `anchor`.
+    fn position(
+        &mut self,
+        _offset: Vec2,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<Vec2> {
+        None
+    }
+
+    /// Returns a new rotation for target based on `self` and `anchor`.
+    fn rotation(&mut self, _angle: f32, _anchor: &RigidBody, _target: &RigidBody) -> Option<f32> {
+        None
+    }
+
+    /// Returns a new linear velocity for target based on `self` and `anchor`.
+    fn linear_velocity(
+        &mut self,
+        _offset: Vec2,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<Vec2> {
+        None
+    }
+
+    /// Returns a new angular velocity for target based on `self` and `anchor`.
+    fn angular_velocity(
+        &mut self,
+        _angle: f32,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<f32> {
+        None
+    }
+
+    /// Returns a linear impulse to apply to target based on `self` and `anchor`.
+    fn linear_impulse(
+        &mut self,
+        _offset: Vec2,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<Vec2> {
+        None
+    }
+
+    /// Returns an angular impulse to apply to target based on `self` and `anchor`.
+    fn angular_impulse(
+        &mut self,
+        _angle: f32,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<f32> {
+        None
+    }
+}
+
+/// A joint behaviour that causes the anchored body to be rigidly fixed at an offset and an angle.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct FixedJointBehaviour;
+
+impl JointBehaviour for FixedJointBehaviour {
+    const STAGE: &'static str = stage::RIGID_JOINT;
+
+    fn position(&mut self, offset: Vec2, anchor: &RigidBody, _target: &RigidBody) -> Option<Vec2> {
+        Some(anchor.position + offset)
+    }
+
+    fn rotation(&mut self, angle: f32, anchor: &RigidBody, _target: &RigidBody) -> Option<f32> {
+        Some(anchor.rotation + angle)
+    }
+}
+
+/// A joint behaviour that causes the anchored body to be accurately positioned with an offset and an angle.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct MechanicalJointBehaviour;
+
+impl JointBehaviour for MechanicalJointBehaviour {
+    const STAGE: &'static str = stage::COLLIDING_JOINT;
+
+    fn position(&mut self, offset: Vec2, anchor: &RigidBody, _target: &RigidBody) -> Option<Vec2> {
+        Some(anchor.position + offset)
+    }
+
+    fn rotation(&mut self, angle: f32, anchor: &RigidBody, _target: &RigidBody) -> Option<f32> {
+        Some(anchor.rotation + angle)
+    }
+
+    fn linear_velocity(
+        &mut self,
+        _offset: Vec2,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<Vec2> {
+        Some(Vec2::zero())
+    }
+
+    fn angular_velocity(
+        &mut self,
+        _angle: f32,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<f32> {
+        Some(0.0)
+    }
+}
+
+/// A joint behaviour that will move the anchored body into a position and angle relative to the anchor over time.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct SpringJointBehaviour {
+    rigidness: f32,
+}
+
+impl SpringJointBehaviour {
+    /// Create a new SpringJointBehaviour with an exact rigidness value.
+    ///
+    /// Rigidness describes how "snappy" the spring joint is. When it's at 0.0,
+    /// the anchored body will "jump" into position softly over one second.
+    /// When it's at 1.0, the anchored body will "jump" into position almost instantaenously.
+    pub fn new(rigidness: f32) -> Option<SpringJointBehaviour> {
+        if rigidness < 0.0 || rigidness >= 1.0 {
+            None
+        } else {
+            Some(Self { rigidness })
+        }
+    }
+
+    pub fn new_lossy(rigidness: f32) -> SpringJointBehaviour {
+        Self {
+            rigidness: rigidness.max(0.0).min(1.0),
+        }
+    }
+}
+
+impl JointBehaviour for SpringJointBehaviour {
+    const STAGE: &'static str = stage::COLLIDING_JOINT;
+
+    fn linear_velocity(
+        &mut self,
+        _offset: Vec2,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<Vec2> {
+        Some(Vec2::zero())
+    }
+
+    fn angular_velocity(
+        &mut self,
+        _angle: f32,
+        _anchor: &RigidBody,
+        _target: &RigidBody,
+    ) -> Option<f32> {
+        Some(0.0)
+    }
+
+    fn linear_impulse(
+        &mut self,
+        offset: Vec2,
+        anchor: &RigidBody,
+        target: &RigidBody,
+    ) -> Option<Vec2> {
+        // the minimum time to "jump" into position
+        const EPSILON: f32 = 0.1;
+        // the maximum time to "jump" into position
+        const T: f32 = 1.0;
+        let springiness = 1.0 - self.rigidness;
+        let position = anchor.position + offset;
+        let d = position - target.position;
+        let scale = (T - EPSILON) * springiness + EPSILON;
+        let impulse = d * target.mass / scale;
+        Some(impulse)
+    }
+
+    fn angular_impulse(
+        &mut self,
+        angle: f32,
+        anchor: &RigidBody,
+        target: &RigidBody,
+    ) -> Option<f32> {
+        // the minimum time to "jump" into position
+        const EPSILON: f32 = 0.1;
+        // the maximum time to "jump" into position
+        const T: f32 = 1.0;
+        let springiness = 1.0 - self.rigidness;
+        let rotation = anchor.rotation + angle;
+        let d = rotation - target.rotation;
+        let scale = (T - EPSILON) * springiness + EPSILON;
+        let impulse = d * target.mass / scale;
+        Some(impulse)
+    }
+}
+
+/// Allows one `RigidBody` to be anchored at another one
+/// in a fixed way, along with a local offset and angle.
+pub type FixedJoint = Joint<FixedJointBehaviour>;
+
+/// Allows one `RigidBody` to be anchored at another one
+/// in a physically accurate way, along with a local offset and angle.
+pub type MechanicalJoint = Joint<MechanicalJointBehaviour>;
+
+/// Allows one `RigidBody` to be anchored at another one
+/// in a spring-y way, along with a local offset and angle.
+pub type SpringJoint = Joint<SpringJointBehaviour>;
+
+impl SpringJoint {
+    /// Add a rigidness value to an owned `Joint`.
+    pub fn with_rigidness(mut self, rigidness: f32) -> Self {
+        self.behaviour.rigidness = rigidness;
+        self
+    }
+}
+
+/// Allows one `RigidBody` to be anchored at another one
+/// in a pre-defined way, along with a local offset and angle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Joint<B: JointBehaviour> {
+    inner: InnerJoint,
+    behaviour: B,
+}
+
+impl<B: JointBehaviour + Default> Joint<B> {
+    /// Create a new joint, where the second body shall be anchored at the first body.
+    pub fn new(body1: Entity, body2: Entity) -> Self {
+        Self::with_behaviour(body1, body2, B::default())
+    }
+}
+
+impl<B: JointBehaviour> Joint<B> {
+    /// Create a new joint, where the second body shall be anchored at the first body.
+    pub fn with_behaviour(body1: Entity, body2: Entity, behaviour: B) -> Self {
+        Self {
+            inner: InnerJoint::new(body1, body2),
+            behaviour,
+        }
+    }
+
+    /// Add an offset to an owned `Joint`.
+    pub fn with_offset(self, offset: Vec2) -> Self {
+        Self {
+            inner: self.inner.with_offset(offset),
+            behaviour: self.behaviour,
+        }
+    }
+
+    /// Add an angle to an owned `Joint`.
+    pub fn with_angle(self, angle: f32) -> Self {
+        Self {
+            inner: self.inner.with_angle(angle),
+            behaviour: self.behaviour,
+        }
+    }
+}
+
+/// The rigid body.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Properties)]
+pub struct RigidBody {
+    /// Current position of this rigid body.
+    pub position: Vec2,
+    prev_position: Vec2,
+    lowest_position: Vec2,
+    /// Current rotation of this rigid body.
+    ///
+    /// NOTE: the semikinematic collision response assumes this is a multiple of 90 degrees,
+    /// dynamic bodies may rotate freely.
+    pub rotation: f32,
+    prev_rotation: f32,
+    /// Current linear velocity of this rigid body.
+    pub linvel: Vec2,
+    prev_linvel: Vec2,
+    /// The terminal linear velocity of a semikinematic body.
+    ///
+    /// Defaults to `f32::INFINITY`.
+    pub terminal: Vec2,
+    accumulator: Vec2,
+    dynamic_acc: Vec2,
+    /// Current angular velocity of this rigid body.
+    pub angvel: f32,
+    prev_angvel: f32,
+    /// The terminal angular velocity of a semikinematic body.
+    ///
+    /// Defaults to `f32::INFINITY`.
+    pub ang_term: f32,
+    /// The physics material, i.e. friction and restitution.
+    pub material: PhysicsMaterial,
+    /// The status, i.e. static, semikinematic or dynamic.
+    ///
+    /// Affects how forces and collisions affect this rigid body.
+    pub status: Status,
+    mass: f32,
+    inv_mass: f32,
+    inv_inertia: f32,
+    center: Vec2,
+    active: bool,
+    sensor: bool,
+    ccd: bool,
+    drop_through: f32,
+    #[property(ignore)]
+    #[serde(skip)]
+    target: Option<(Vec2, f32)>,
+    targeted: bool,
+    #[property(ignore)]
+    #[serde(skip)]
+    carrier: Option<Entity>,
+    #[property(ignore)]
+    #[serde(skip)]
+    surface: Option<f32>,
+}
+
+impl RigidBody {
+    /// Returns a new `RigidBody` with just a mass and all other components set to their defaults.
+    pub fn new(mass: Mass) -> Self {
+        Self {
+            position: Vec2::zero(),
+            prev_position: Vec2::zero(),
+            lowest_position: Vec2::zero(),
+            rotation: 0.0,
+            prev_rotation: 0.0,
+            linvel: Vec2::zero(),
+            prev_linvel: Vec2::zero(),
+            terminal: Vec2::new(f32::INFINITY, f32::INFINITY),
+            accumulator: Vec2::zero(),
+            dynamic_acc: Vec2::zero(),
+            angvel: 0.0,
+            prev_angvel: 0.0,
+            ang_term: f32::INFINITY,
+            material: PhysicsMaterial::default(),
+            status: Status::Semikinematic,
+            mass: mass.scalar(),
+            inv_mass: mass.inverse(),
+            inv_inertia: 0.0,
+            center: Vec2::zero(),
+            active: true,
+            sensor: false,
+            ccd: false,
+            drop_through: 0.0,
+            target: None,
+            targeted: false,
+            carrier: None,
+            surface: None,
+        }
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the position set to a new one.
+    pub fn with_position(mut self, position: Vec2) -> Self {
+        self.position = position;
+        self.prev_position = position;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the rotation set to a new one.
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self.prev_rotation = rotation;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the linear velocity set to a new one.
+    pub fn with_linear_velocity(mut self, linvel: Vec2) -> Self {
+        self.linvel = linvel;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the linear velocity set to a new one.
+    pub fn with_angular_velocity(mut self, angvel: f32) -> Self {
+        self.angvel = angvel;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the terminal linear velocity set to a new one.
+    pub fn with_terminal(mut self, terminal: Vec2) -> Self {
+        self.terminal = terminal;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the terminal linear velocity set to a new one.
+    pub fn with_angular_terminal(mut self, terminal: f32) -> Self {
+        self.ang_term = terminal;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the acceleration set to a new one.
+    pub fn with_acceleration(mut self, acceleration: Vec2) -> Self {
+        self.accumulator = acceleration;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the material set to a new one.
+    pub fn with_material(mut self, material: PhysicsMaterial) -> Self {
+        self.material = material;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the restitution of its material set to a new one.
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.material.restitution = restitution;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the friction of its material set to a new one.
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.material.friction = friction;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the status set to a new one.
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the active flag set to a new one.
+    pub fn with_active(mut self, active: bool) -> Self {
+        self.active = active;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the sensor flag set to a new one.
+    ///
+    /// All the shapes of a sensor body are triggers, see `Shape::with_sensor`.
+    pub fn with_sensor(mut self, sensor: bool) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with continuous collision detection
+    /// enabled or disabled.
+    pub fn with_ccd(mut self, ccd: bool) -> Self {
+        self.ccd = ccd;
+        self
+    }
+
+    /// Applies an impulse to the `RigidBody`s linear velocity.
+    pub fn apply_linear_impulse(&mut self, impulse: Vec2) {
+        self.linvel += impulse * self.inv_mass;
+    }
+
+    /// Applies an impulse to the `RigidBody`s linear velocity.
+    pub fn apply_angular_impulse(&mut self, impulse: f32) {
+        self.angvel += impulse * self.inv_mass;
+    }
+
+    /// Moves a kinematic `RigidBody` to a target position and rotation over the next step.
+    ///
+    /// The velocities are set to reach the target, so that pushed and carried
+    /// bodies move along smoothly, and reset afterwards unless a new target is set.
+    pub fn move_to(&mut self, position: Vec2, rotation: f32) {
+        self.target = Some((position, rotation));
+    }
+
+    /// Applies a force to the `RigidBody`s acceleration accumulator.
+    pub fn apply_force(&mut self, force: Vec2) {
+        self.accumulator += force * self.inv_mass;
+    }
+
+    /// Gets the active flag.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Gets the sensor flag.
+    pub fn is_sensor(&self) -> bool {
+        self.sensor
+    }
+
+    /// Returns true if continuous collision detection is enabled.
+    pub fn is_ccd(&self) -> bool {
+        self.ccd
+    }
+
+    /// Gets the mass
+    pub fn mass(&self) -> f32 {
+        self.mass
+    }
+
+    /// Gets the mass
+    pub fn inverse_mass(&self) -> f32 {
+        self.inv_mass
+    }
+
+    /// Gets the inverse inertia, derived from the `Shape`s of a dynamic body.
+    ///
+    /// It is zero for static and semikinematic bodies, which never rotate from collisions.
+    pub fn inverse_inertia(&self) -> f32 {
+        self.inv_inertia
+    }
+
+    /// Gets the center of mass in world space, derived from the `Shape`s of a dynamic body.
+    ///
+    /// It is the position for static and semikinematic bodies.
+    pub fn center_of_mass(&self) -> Vec2 {
+        self.position + Mat2::from_angle(self.rotation) * self.center
+    }
+
+    /// Sets the active flag.
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    /// Sets the sensor flag.
+    pub fn set_sensor(&mut self, sensor: bool) {
+        self.sensor = sensor;
+    }
+
+    /// Enables or disables continuous collision detection.
+    ///
+    /// A body with continuous collision detection is swept from its previous to its
+    /// current position, so that it stops at the first shape in its way instead of
+    /// passing through it.  The rotation of the body is not swept.
+    pub fn set_ccd(&mut self, ccd: bool) {
+        self.ccd = ccd;
+    }
+
+    /// Lets this `RigidBody` fall through all one-way platforms for a duration in seconds.
+    pub fn drop_through(&mut self, duration: f32) {
+        self.drop_through = self.drop_through.max(duration);
+    }
+
+    /// Returns true if this `RigidBody` is currently falling through one-way platforms.
+    pub fn is_dropping_through(&self) -> bool {
+        self.drop_through > 0.0
+    }
+
+    /// Sets the mass.
+    pub fn set_mass(&mut self, mass: Mass) {
+        self.mass = mass.scalar();
+        self.inv_mass = mass.inverse();
+    }
+
+    /// Returns the difference between the last known linear velocity and the current linear velocity.
+    pub fn linear_deceleration(&self) -> Vec2 {
+        self.prev_linvel.abs() - self.linvel.abs()
+    }
+
+    /// Returns the difference between the last known angular velocity and the current angular velocity.
+    pub fn angular_deceleration(&self) -> f32 {
+        self.prev_angvel.abs() - self.angvel.abs()
+    }
+
+    fn hash_state<H: Hasher>(&self, state: &mut H) {
+        let vectors = [
+            self.position,
+            self.prev_position,
+            self.lowest_position,
+            self.linvel,
+            self.prev_linvel,
+            self.terminal,
+            self.accumulator,
+            self.dynamic_acc,
+            self.center,
+        ];
+        for vector in vectors.iter() {
+            state.write_u32(vector.x().to_bits());
+            state.write_u32(vector.y().to_bits());
+        }
+        let scalars = [
+            self.rotation,
+            self.prev_rotation,
+            self.angvel,
+            self.prev_angvel,
+            self.ang_term,
+            self.material.friction,
+            self.material.restitution,
+            self.mass,
+            self.inv_mass,
+            self.inv_inertia,
+            self.drop_through,
+        ];
+        for scalar in scalars.iter() {
+            state.write_u32(scalar.to_bits());
+        }
+        self.material.friction_combine.hash(state);
+        self.material.restitution_combine.hash(state);
+        self.status.hash(state);
+        self.active.hash(state);
+        self.sensor.hash(state);
+        self.ccd.hash(state);
+        self.target
+            .map(|(position, rotation)| {
+                [
+                    position.x().to_bits(),
+                    position.y().to_bits(),
+                    rotation.to_bits(),
+                ]
+            })
+            .hash(state);
+        self.targeted.hash(state);
+        self.carrier.hash(state);
+        self.surface.map(f32::to_bits).hash(state);
+    }
+}
+
+/// A contact point of a `Manifold`.
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    /// The position in world space.
+    pub position: Vec2,
+    /// How deep the shapes overlap at this point, positive like the penetration of the manifold.
+    pub penetration: f32,
+    /// The impulse the solver applied along the normal at this point.
+    ///
+    /// Only contacts with dynamic bodies are solved with impulses, semikinematic
+    /// bodies are pushed apart and report zero.  The `Manifold` events are sent before
+    /// the solver runs, so only the manifolds of the `CollisionStarted` and
+    /// `CollisionOngoing` events carry impulses.
+    pub impulse: f32,
+}
+
+/// The manifold, representing detailed data on a collision between two `RigidBody`s.
+///
+/// Usable as an event.  The `Manifold` events are sent by the narrow phase, before
+/// the solver runs, so the impulses of their contacts are always zero.
+#[derive(Debug, Clone)]
+pub struct Manifold {
+    /// The first entity.
+    pub body1: Entity,
+    /// The second entity.
+    pub body2: Entity,
+    /// Every pair of shapes in contact, as the `Shape` of the first entity and the
+    /// `Shape` of the second one, e.g. to tell which hitbox hit which hurtbox.
+    ///
+    /// The deepest pair, which the normal and the penetration belong to, comes first.
+    pub shapes: SmallVec<[(Entity, Entity); 1]>,
+    /// The penetration, relative to the second entity.
+    pub penetration: f32,
+    /// The normal, relative to the second entity.
+    pub normal: Vec2,
+    /// The contact points of this manifold.
+    pub contacts: SmallVec<[Contact; 4]>,
+}
+
+/// Builds the collider of the shape `e` of the body `entity`.
+fn shape_collider(
+    entity: Entity,
+    body: &RigidBody,
+    e: Entity,
+    shape: &Shape,
+    geometry: Geometry,
+) -> Obb {
+    let collider = Obb::new(
+        body.status,
+        entity,
+        e,
+        Mat2::from_angle(body.rotation),
+        body.position,
+        geometry,
+        shape.layers,
+    )
+    .with_sensor(shape.sensor || body.sensor);
+    if body.ccd {
+        collider.with_motion(body.position - body.prev_position)
+    } else {
+        collider
+    }
+}
+
+/// Marks the bodies and shapes that were added, changed or removed in the current frame,
+/// so that the broad phase refreshes their colliders.
+fn mark_changes(
+    broad: &mut BroadPhase,
+    bodies: &mut Query<(Entity, Changed<RigidBody>)>,
+    children: &mut Query<(Entity, &RigidBody, Changed<Children>)>,
+    shapes: &mut Query<(Entity, Changed<Shape>, &Parent)>,
+) {
+    for (entity, _) in &mut bodies.iter() {
+        broad.mark_changed(entity);
+    }
+    for (entity, _, _) in &mut children.iter() {
+        broad.mark_changed(entity);
+    }
+    for (entity, _, parent) in &mut shapes.iter() {
+        broad.mark_reshaped(parent.0, entity);
+    }
+    for &entity in bodies.removed::<RigidBody>() {
+        broad.mark_removed(entity);
+    }
+    for &entity in shapes.removed::<Shape>() {
+        broad.mark_removed(entity);
+    }
+}
+
+/// Keeps the changes that happen after the physics stages for the next step, since
+/// change detection forgets them at the end of the frame.
+fn broad_phase_changes_system(
+    mut broad: ResMut<BroadPhase>,
+    mut bodies: Query<(Entity, Changed<RigidBody>)>,
+    mut children: Query<(Entity, &RigidBody, Changed<Children>)>,
+    mut shapes: Query<(Entity, Changed<Shape>, &Parent)>,
+) {
+    mark_changes(&mut broad, &mut bodies, &mut children, &mut shapes);
+}
+
+pub fn broad_phase_system(
+    mut broad: ResMut<BroadPhase>,
+    timestep: Res<FixedTimestep>,
+    mut query: Query<(Entity, &RigidBody, &Children)>,
+    mut changed_bodies: Query<(Entity, Changed<RigidBody>)>,
+    mut changed_children: Query<(Entity, &RigidBody, Changed<Children>)>,
+    mut changed_shapes: Query<(Entity, Changed<Shape>, &Parent)>,
+    shapes: Query<&Shape>,
+) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    mark_changes(
+        &mut broad,
+        &mut changed_bodies,
+        &mut changed_children,
+        &mut changed_shapes,
+    );
+    let changes = broad.take_changes();
+
+    // an empty broad phase is filled with every collider at once
+    if broad.is_empty() {
+        let mut colliders = Vec::new();
+        for (entity, body, children) in &mut query.iter() {
+            for &e in children.iter() {
+                if let Ok(shape) = shapes.get::<Shape>(e) {
+                    colliders.push(shape_collider(entity, body, e, &shape, shape.geometry()));
+                }
+            }
+        }
+        // keep the trees independent from the order of the query
+        colliders.sort_by_key(|collider| collider.shape);
+        *broad = BroadPhase::with_colliders(colliders);
+        return;
+    }
+
+    // keep the trees independent from the order of the hash sets
+    let mut removed: Vec<Entity> = changes.removed.into_iter().collect();
+    removed.sort();
+    for entity in removed {
+        broad.remove(entity);
+        broad.remove_body(entity);
+    }
+
+    let mut bodies: Vec<Entity> = changes.bodies.into_iter().collect();
+    bodies.sort();
+    for entity in bodies {
+        let mut old = broad.remove_body(entity);
+        let (body, children) = match (
+            query.get::<RigidBody>(entity),
+            query.get::<Children>(entity),
+        ) {
+            (Ok(body), Ok(children)) => (body, children),
+            _ => continue,
+        };
+        for &e in children.iter() {
+            if let Ok(shape) = shapes.get::<Shape>(e) {
+                // the geometry is only cloned again if the shape changed
+                let geometry = match old.iter().position(|collider| collider.shape == e) {
+                    Some(index) if !changes.shapes.contains(&e) => old.swap_remove(index).geometry,
+                    _ => shape.geometry(),
+                };
+                broad.insert(shape_collider(entity, &body, e, &shape, geometry));
+            }
+        }
+    }
+}
+
+fn narrow_phase_system(
+    mut manifolds: ResMut<Events<Manifold>>,
+    timestep: Res<FixedTimestep>,
+    broad: Res<BroadPhase>,
+    mut triggers: ResMut<Triggers>,
+    mut entered: ResMut<Events<TriggerEntered>>,
+    mut exited: ResMut<Events<TriggerExited>>,
+    bodies: Query<Mut<RigidBody>>,
+    one_ways: Query<&OneWay>,
+) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    // one-way platforms never stop a swept body
+    let solid = |body: Entity| one_ways.get::<OneWay>(body).is_err();
+
+    // the first impact of every swept body, over all of its pairs
+    let groups = broad.groups();
+    let mut impacts = HashMap::new();
+    for &(collider1, collider2) in groups.iter().flatten() {
+        if collider1.sensor || collider2.sensor {
+            continue;
+        }
+        if !solid(collider1.body) || !solid(collider2.body) {
+            continue;
+        }
+        if let Some((time, _)) = collision::time_of_impact(collider1, collider2) {
+            for collider in &[collider1, collider2] {
+                if collider.motion != Vec2::zero() {
+                    let first = impacts.entry(collider.body).or_insert(time);
+                    *first = time.min(*first);
+                }
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    let mut inside = Vec::new();
+    for group in &groups {
+        let mut result = None;
+        for &(collider1, collider2) in group {
+            // triggers are only tested for overlaps and never reach the solver
+            if collider1.sensor || collider2.sensor {
+                if collision::overlaps(collider1, collider2) {
+                    for &(trigger, other) in &[(collider1, collider2), (collider2, collider1)] {
+                        if trigger.sensor {
+                            inside.push(TriggerContact {
+                                trigger: trigger.shape,
+                                trigger_body: trigger.body,
+                                body: other.body,
+                            });
+                        }
+                    }
+                }
+                continue;
+            }
+            // swept bodies are moved back to their first impact, so every pair of
+            // them collides where they end up, not where they were swept to
+            let manifold = match (impacts.get(&collider1.body), impacts.get(&collider2.body)) {
+                (None, None) => collision::collide(collider1, collider2),
+                (time1, time2) => collision::collide(
+                    &collider1.rewound(time1.copied().unwrap_or(1.0)),
+                    &collider2.rewound(time2.copied().unwrap_or(1.0)),
+                ),
+            };
+            if let Some(manifold) = manifold {
+                result = Some(collision::merge(result, manifold));
+            }
+        }
+        if let Some(manifold) = result {
+            results.push(manifold);
+        }
+    }
+    for (body, time) in impacts {
+        if let Ok(mut body) = bodies.get_mut::<RigidBody>(body) {
+            let rewind = (body.position - body.prev_position) * (1.0 - time);
+            body.position -= rewind;
+            body.lowest_position -= rewind;
+        }
+    }
+
+    if timestep.is_deterministic() {
+        results.sort_by_key(|manifold| (manifold.body1, manifold.body2));
+    }
+    for manifold in results {
+        manifolds.send(manifold);
+    }
+
+    let (started, stopped) = triggers.update(inside);
+    for contact in started {
+        entered.send(TriggerEntered(contact));
+    }
+    for contact in stopped {
+        exited.send(TriggerExited(contact));
+    }
+}
+
+/// A `RigidBody` inside a trigger, i.e. a `Shape` with the sensor flag set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TriggerContact {
+    /// The `Shape` of the trigger.
+    pub trigger: Entity,
+    /// The `RigidBody` the trigger belongs to.
+    pub trigger_body: Entity,
+    /// The `RigidBody` inside the trigger.
+    pub body: Entity,
+}
+
+/// Sent when a `RigidBody` enters a trigger.
+#[derive(Debug, Clone, Copy)]
+pub struct TriggerEntered(pub TriggerContact);
+
+/// Sent when a `RigidBody` leaves a trigger.
+#[derive(Debug, Clone, Copy)]
+pub struct TriggerExited(pub TriggerContact);
+
+/// The triggers every `RigidBody` is currently inside, as of the last narrow phase.
+#[derive(Debug, Default)]
+pub struct Triggers {
+    /// Sorted, to look up and compare contacts between steps.
+    contacts: Vec<TriggerContact>,
+    inside: HashMap<Entity, SmallVec<[Entity; 4]>>,
+}
+
+impl Triggers {
+    /// Returns the triggers `body` is inside.
+    pub fn inside(&self, body: Entity) -> &[Entity] {
+        self.inside
+            .get(&body)
+            .map_or(&[][..], |triggers| &triggers[..])
+    }
+
+    /// Returns whether `body` is inside `trigger`.
+    pub fn is_inside(&self, body: Entity, trigger: Entity) -> bool {
+        self.inside(body).contains(&trigger)
+    }
+
+    /// Iterates over all the bodies inside a trigger.
+    pub fn iter(&self) -> impl Iterator<Item = &TriggerContact> {
+        self.contacts.iter()
+    }
+
+    /// Replaces the contacts of the last step, returning the ones that started and stopped.
+    fn update(
+        &mut self,
+        mut contacts: Vec<TriggerContact>,
+    ) -> (Vec<TriggerContact>, Vec<TriggerContact>) {
+        // a body can overlap a trigger with more than one of its shapes
+        contacts.sort();
+        contacts.dedup();
+        let started = contacts
+            .iter()
+            .filter(|contact| self.contacts.binary_search(contact).is_err())
+            .copied()
+            .collect();
+        let stopped = self
+            .contacts
+            .iter()
+            .filter(|contact| contacts.binary_search(contact).is_err())
+            .copied()
+            .collect();
+
+        self.inside.clear();
+        for contact in &contacts {
+            self.inside
+                .entry(contact.body)
+                .or_insert_with(SmallVec::new)
+                .push(contact.trigger);
+        }
+        self.contacts = contacts;
+        (started, stopped)
+    }
+}
+
+/// Sent when two `RigidBody`s start touching, with their first manifold.
+#[derive(Debug, Clone)]
+pub struct CollisionStarted(pub Manifold);
+
+/// Sent for every step in which two `RigidBody`s keep touching, with their current manifold.
+#[derive(Debug, Clone)]
+pub struct CollisionOngoing(pub Manifold);
+
+/// Sent when two `RigidBody`s stop touching, with their last manifold.
+#[derive(Debug, Clone)]
+pub struct CollisionEnded(pub Manifold);
+
+/// Keeps track of the pairs of `RigidBody`s in contact across steps, to turn the
+/// manifolds of every step into `CollisionStarted`, `CollisionOngoing` and
+/// `CollisionEnded` events.
+#[derive(Default)]
+pub struct ContactTracker {
+    contacts: HashMap<(Entity, Entity), Manifold>,
+}
+
+impl ContactTracker {
+    pub fn system(self, res: &mut Resources) -> Box<dyn System> {
+        let system = contact_tracker_system.system();
+        res.insert_local(system.id(), self);
+        system
+    }
+}
+
+fn contact_tracker_system(
+    mut tracker: Local<ContactTracker>,
+    timestep: Res<FixedTimestep>,
+    solved: Res<Solved>,
+    mut started: ResMut<Events<CollisionStarted>>,
+    mut ongoing: ResMut<Events<CollisionOngoing>>,
+    mut ended: ResMut<Events<CollisionEnded>>,
+) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    let tracker = &mut *tracker;
+    let mut contacts = HashMap::new();
+    for manifold in &solved.0 {
+        let pair = if manifold.body1 < manifold.body2 {
+            (manifold.body1, manifold.body2)
+        } else {
+            (manifold.body2, manifold.body1)
+        };
+        if tracker.contacts.remove(&pair).is_some() {
+            ongoing.send(CollisionOngoing(manifold.clone()));
+        } else {
+            started.send(CollisionStarted(manifold.clone()));
+        }
+        contacts.insert(pair, manifold.clone());
+    }
+
+    // the pairs left over didn't touch in this step, sorted to not depend on the hash map
+    let mut left: Vec<_> = tracker.contacts.drain().collect();
+    left.sort_by_key(|(pair, _)| *pair);
+    for (_, manifold) in left {
+        ended.send(CollisionEnded(manifold));
+    }
+    tracker.contacts = contacts;
+}
+
+/// The manifolds of the last step, with the impulses applied by the solver.
+#[derive(Default)]
+struct Solved(Vec<Manifold>);
+
+#[derive(Default)]
+pub struct Solver {
+    reader: EventReader<Manifold>,
+}
+
+impl Solver {
+    pub fn system(self, res: &mut Resources) -> Box<dyn System> {
+        let system = solve_system.system();
+        res.insert_local(system.id(), self);
+        system
+    }
+}
+
+fn solve_system(
+    mut solver: Local<Solver>,
+    timestep: Res<FixedTimestep>,
+    manifolds: Res<Events<Manifold>>,
+    mut solved: ResMut<Solved>,
+    gravity: Res<GlobalGravity>,
+    step: Res<GlobalStep>,
+    up: Res<GlobalUp>,
+    ang_tol: Res<AngularTolerance>,
+    resting: Res<RestingSpeed>,
+    query: Query<Mut<RigidBody>>,
+    one_ways: Query<&OneWay>,
+    mut grounded: Query<(Entity, Mut<Grounded>)>,
+) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    let delta_time = timestep.step();
+    // contacts slower than this rest, bodies resting on each other gain about
+    // half of the speed from gravity every step
+    let resting = resting.0.max(gravity.0.length() * delta_time * 2.0);
+
+    let mut results = Vec::new();
+    let mut contacts = Vec::new();
+    let mut grounds = HashMap::new();
+    for manifold in solver.reader.iter(&manifolds) {
+        let a = query.get::<RigidBody>(manifold.body1).unwrap();
+        let b = query.get::<RigidBody>(manifold.body2).unwrap();
+
+        if let Ok(one_way) = one_ways.get::<OneWay>(manifold.body1) {
+            let direction = if one_way.0.length_squared() != 0.0 {
+                one_way.0
+            } else {
+                up.0
+            };
+            if passes_through(direction, manifold.normal, &a, &b, ang_tol.0) {
+                continue;
+            }
+        }
+        if let Ok(one_way) = one_ways.get::<OneWay>(manifold.body2) {
+            let direction = if one_way.0.length_squared() != 0.0 {
+                one_way.0
+            } else {
+                up.0
+            };
+            if passes_through(direction, -manifold.normal, &b, &a, ang_tol.0) {
+                continue;
+            }
+        }
+
+        // bodies resting on top of another one are grounded on it
+        if grounded.get::<Grounded>(manifold.body1).is_ok()
+            && rests_on(up.0, -manifold.normal, ang_tol.0)
+        {
+            let ground = (manifold.body2, -manifold.normal, b.linvel);
+            touch_ground(&mut grounds, manifold.body1, ground, up.0);
+        }
+        if grounded.get::<Grounded>(manifold.body2).is_ok()
+            && rests_on(up.0, manifold.normal, ang_tol.0)
+        {
+            let ground = (manifold.body1, manifold.normal, a.linvel);
+            touch_ground(&mut grounds, manifold.body2, ground, up.0);
+        }
+
+        if a.status == Status::Dynamic || b.status == Status::Dynamic {
+            contacts.push(manifold.clone());
+            continue;
+        }
+
+        let dynamics = if a.status == Status::Semikinematic && b.status == Status::Semikinematic {
+            let push_angle = up.0.abs().dot(manifold.normal.abs()).acos();
+            if push_angle > ang_tol.0 {
+                let sum_recip = (a.mass + b.mass).recip();
+                let br = b.linvel * b.mass;
+                let ar = a.linvel * a.mass;
+                let rv = br * sum_recip - ar * sum_recip;
+
+                let impulse = -rv * manifold.normal.abs();
+
+                let a = a.linvel - impulse;
+                let b = b.linvel + impulse;
+                Some((a, b))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let friction = a.material.combine_friction(&b.material);
+        let restitution = a.material.combine_restitution(&b.material);
+        let approach = (a.linvel - b.linvel).dot(manifold.normal);
+
+        // semikinematic bodies resting on top of kinematic bodies are carried along
+        let carried = (
+            b.status == Status::Kinematic && rests_on(up.0, -manifold.normal, ang_tol.0),
+            a.status == Status::Kinematic && rests_on(up.0, manifold.normal, ang_tol.0),
+        );
+        mem::drop(a);
+        mem::drop(b);
+
+        let mut a = query.get_mut::<RigidBody>(manifold.body1).unwrap();
+        match a.status {
+            Status::Static | Status::Dynamic | Status::Kinematic => {}
+            Status::Semikinematic => {
+                if carried.0 {
+                    a.carrier = Some(manifold.body2);
+                }
+                a.surface = Some(a.surface.map_or(friction, |surface| surface.max(friction)));
+
+                if let Some((impulse, _)) = dynamics {
+                    a.dynamic_acc += impulse;
+
+                    let d = -manifold.normal * manifold.penetration;
+                    let v = a.linvel * delta_time;
+                    if v.signum() == d.signum() {
+                        // nothing
+                    } else {
+                        a.linvel =
+                            bounce(a.linvel, manifold.normal, approach, restitution, resting);
+                        a.position += d;
+                    }
+                } else {
+                    let mut solve = true;
+                    let step_angle = up.0.abs().dot(manifold.normal.abs()).acos();
+                    if step_angle > ang_tol.0 {
+                        let up_vector = up.0;
+                        if up_vector.length_squared() != 0.0 {
+                            for point in &manifold.contacts {
+                                let d = point.position - a.lowest_position;
+                                let s = d.dot(up_vector);
+                                if s >= 0.0 && s < step.0 {
+                                    let diff = a.position - a.lowest_position;
+                                    a.lowest_position += up_vector * s;
+                                    a.position = a.lowest_position + diff;
+                                    solve = false;
+                                }
+                            }
+                        }
+                    }
+
+                    if solve {
+                        let d = -manifold.normal * manifold.penetration;
+                        let v = a.linvel * delta_time;
+                        if v.signum() == d.signum() {
+                            // nothing
+                        } else {
+                            a.linvel =
+                                bounce(a.linvel, manifold.normal, approach, restitution, resting);
+                            a.position += d;
+                        }
+                    }
+                }
+            }
+        }
+        mem::drop(a);
+
+        let mut b = query.get_mut::<RigidBody>(manifold.body2).unwrap();
+        match b.status {
+            Status::Static | Status::Dynamic | Status::Kinematic => {}
+            Status::Semikinematic => {
+                if carried.1 {
+                    b.carrier = Some(manifold.body1);
+                }
+                b.surface = Some(b.surface.map_or(friction, |surface| surface.max(friction)));
+
+                if let Some((_, impulse)) = dynamics {
+                    b.dynamic_acc += impulse;
+
+                    let d = manifold.normal * manifold.penetration;
+                    let v = b.linvel * delta_time;
+                    if v.signum() == d.signum() {
+                        // nothing
+                    } else {
+                        b.linvel =
+                            bounce(b.linvel, -manifold.normal, approach, restitution, resting);
+                        b.position += d;
+                    }
+                } else {
+                    let mut solve = true;
+                    let step_angle = up.0.abs().dot(manifold.normal.abs()).acos();
+                    if step_angle > ang_tol.0 {
+                        let up_vector = up.0;
+                        if up_vector.length_squared() != 0.0 {
+                            for point in &manifold.contacts {
+                                let d = point.position - b.lowest_position;
+                                let s = d.dot(up_vector);
+                                if s >= 0.0 && s < step.0 {
+                                    let diff = b.position - b.lowest_position;
+                                    b.lowest_position += up_vector * s;
+                                    b.position = b.lowest_position + diff;
+                                    solve = false;
+                                }
+                            }
+                        }
+                    }
+
+                    if solve {
+                        let d = manifold.normal * manifold.penetration;
+                        let v = b.linvel * delta_time;
+                        if v.signum() == d.signum() {
+                            // nothing
+                        } else {
+                            b.linvel =
+                                bounce(b.linvel, -manifold.normal, approach, restitution, resting);
+                            b.position += d;
+                        }
+                    }
+                }
+            }
+        }
+        mem::drop(b);
+
+        results.push(manifold.clone());
+    }
+
+    for (entity, mut ground) in &mut grounded.iter() {
+        ground.update(grounds.remove(&entity), delta_time);
+    }
+
+    dynamics::solve(&mut contacts, &query, resting);
+    results.extend(contacts);
+    solved.0 = results;
+}
+
+/// Returns true if `body` may pass through the one-way `platform`.
+///
+/// The normal points from the platform to the body.  The body passes if it's
+/// dropping through, if it's not on the solid side of the platform or if it's
+/// moving towards the solid side.
+fn passes_through(
+    direction: Vec2,
+    normal: Vec2,
+    platform: &RigidBody,
+    body: &RigidBody,
+    ang_tol: f32,
+) -> bool {
+    if body.drop_through > 0.0 {
+        return true;
+    }
+    if direction.length_squared() == 0.0 {
+        return false;
+    }
+    let direction = direction.normalize();
+    let angle = direction.dot(normal).max(-1.0).min(1.0).acos();
+    angle > ang_tol || (body.linvel - platform.linvel).dot(direction) > 0.0
+}
+
+/// Returns the velocity of a semikinematic body after hitting a surface.
+///
+/// The velocity along the normal, which points into the surface, is removed.
+/// If the body approached the surface faster than `resting`, it bounces off
+/// with its approach speed scaled by the restitution.
+fn bounce(linvel: Vec2, normal: Vec2, approach: f32, restitution: f32, resting: f32) -> Vec2 {
+    let linvel = linvel * Vec2::new(normal.y().abs(), normal.x().abs());
+    if approach > resting {
+        linvel - normal * (approach * restitution)
+    } else {
+        linvel
+    }
+}
+
+/// Keeps the ground of `body` with the normal closest to `up`.
+fn touch_ground(
+    grounds: &mut HashMap<Entity, (Entity, Vec2, Vec2)>,
+    body: Entity,
+    ground: (Entity, Vec2, Vec2),
+    up: Vec2,
+) {
+    let flatter = grounds
+        .get(&body)
+        .map_or(true, |&(_, normal, _)| ground.1.dot(up) > normal.dot(up));
+    if flatter {
+        grounds.insert(body, ground);
+    }
+}
+
+/// Returns true if a body rests on top of another one.
+///
+/// The normal points from the other body to the resting body.
+fn rests_on(up: Vec2, normal: Vec2, ang_tol: f32) -> bool {
+    if up.length_squared() == 0.0 {
+        return false;
+    }
+    let up = up.normalize();
+    let angle = up.dot(normal).max(-1.0).min(1.0).acos();
+    angle < ang_tol
+}
+
+pub struct PhysicsStep {
+    skip: usize,
+}
+
+impl PhysicsStep {
+    pub fn system(self, res: &mut Resources) -> Box<dyn System> {
+        let system = physics_step_system.system();
+        res.insert_local(system.id(), self);
+        system
+    }
+}
+
+impl Default for PhysicsStep {
+    fn default() -> Self {
+        PhysicsStep { skip: 3 }
+    }
+}
+
+fn physics_step_system(
+    mut state: Local<PhysicsStep>,
+    timestep: Res<FixedTimestep>,
+    friction: Res<GlobalFriction>,
+    gravity: Res<GlobalGravity>,
+    up: Res<GlobalUp>,
+    mut query: Query<(Entity, Mut<RigidBody>, &Children)>,
+    shapes: Query<&Shape>,
+) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    if state.skip > 0 {
+        state.skip -= 1;
+        return;
+    }
+
+    let delta_time = timestep.step();
+
+    let mut carriers = HashMap::new();
+    for (entity, mut body, children) in &mut query.iter() {
+        // resting static bodies are left untouched, so that the broad phase skips them
+        if body.status == Status::Static
+            && body.position == body.prev_position
+            && body.rotation == body.prev_rotation
+            && body.linvel == Vec2::zero()
+            && body.angvel == 0.0
+            && body.accumulator == Vec2::zero()
+            && body.dynamic_acc == Vec2::zero()
+        {
+            continue;
+        }
+
+        body.prev_position = body.position;
+        body.prev_rotation = body.rotation;
+
+        if !body.active {
+            continue;
+        }
+
+        body.drop_through = (body.drop_through - delta_time).max(0.0);
+
+        if matches!(body.status, Status::Semikinematic | Status::Dynamic) {
+            body.accumulator += gravity.0;
+        }
+
+        if body.status == Status::Kinematic {
+            if let Some((position, rotation)) = body.target.take() {
+                body.linvel = (position - body.position) / delta_time;
+                body.angvel = (rotation - body.rotation) / delta_time;
+                body.targeted = true;
+            } else if body.targeted {
+                body.linvel = Vec2::zero();
+                body.angvel = 0.0;
+                body.targeted = false;
+            }
+        }
+
+        let linvel = body.linvel + body.accumulator * delta_time;
+        let linvel = linvel + body.dynamic_acc;
+        body.linvel = linvel;
+        body.accumulator = Vec2::zero();
+        body.dynamic_acc = Vec2::zero();
+
+        if matches!(body.status, Status::Semikinematic | Status::Dynamic) {
+            let vel = body.linvel;
+            let limit = body.terminal;
+            match vel.x().partial_cmp(&0.0) {
+                Some(Ordering::Less) => *body.linvel.x_mut() = vel.x().max(-limit.x()),
+                Some(Ordering::Greater) => *body.linvel.x_mut() = vel.x().min(limit.x()),
+                Some(Ordering::Equal) => {}
+                None => *body.linvel.x_mut() = 0.0,
+            }
+            match vel.y().partial_cmp(&0.0) {
+                Some(Ordering::Less) => *body.linvel.y_mut() = vel.y().max(-limit.y()),
+                Some(Ordering::Greater) => *body.linvel.y_mut() = vel.y().min(limit.y()),
+                Some(Ordering::Equal) => {}
+                None => *body.linvel.y_mut() = 0.0,
+            }
+            let vel = body.angvel;
+            let limit = body.ang_term;
+            match vel.partial_cmp(&0.0) {
+                Some(Ordering::Less) => body.angvel = vel.max(-limit),
+                Some(Ordering::Greater) => body.angvel = vel.min(limit),
+                Some(Ordering::Equal) => {}
+                None => body.angvel = 0.0,
+            }
+        }
+
+        // the mass of dynamic bodies is spread evenly over the area of all shapes
+        body.center = Vec2::zero();
+        body.inv_inertia = 0.0;
+        if body.status == Status::Dynamic {
+            let mut area = 0.0;
+            let mut center = Vec2::zero();
+            let mut moment = 0.0;
+            for &child in children.iter() {
+                if let Ok(shape) = shapes.get::<Shape>(child) {
+                    let (a, c, m) = shape.mass_properties();
+                    area += a;
+                    center += c * a;
+                    moment += m;
+                }
+            }
+            if area > 0.0 {
+                let center = center / area;
+                // parallel axis theorem, from the origin of the body to its center of mass
+                let moment = moment - area * center.length_squared();
+                body.center = center;
+                body.inv_inertia = if moment > 0.0 {
+                    body.inv_mass * area / moment
+                } else {
+                    0.0
+                };
+            }
+        }
+
+        // dynamic bodies turn around their center of mass, which moves with the linear velocity
+        let rotation = body.rotation + body.angvel * delta_time;
+        let center = Mat2::from_angle(body.rotation) * body.center;
+        let turned = Mat2::from_angle(rotation) * body.center;
+        let position = body.position + body.linvel * delta_time + center - turned;
+        body.position = position;
+        body.rotation = rotation;
+
+        if body.status == Status::Kinematic {
+            let rotation = body.rotation - body.prev_rotation;
+            carriers.insert(entity, (body.prev_position, body.position, rotation));
+        }
+
+        // touching surfaces slow semikinematic bodies down more or less than the default material
+        let damping = match body.surface.take() {
+            Some(surface) => {
+                let scale = surface / PhysicsMaterial::default().friction;
+                friction.0.powf(scale)
+            }
+            None => friction.0,
+        };
+        match body.status {
+            Status::Semikinematic => {
+                if body.linvel.x().abs() <= body.prev_linvel.x().abs() {
+                    *body.linvel.x_mut() *= damping
+                }
+                if body.linvel.y().abs() <= body.prev_linvel.y().abs() {
+                    *body.linvel.y_mut() *= damping
+                }
+                if body.angvel.abs() <= body.prev_angvel.abs() {
+                    body.angvel *= damping
+                }
+            }
+            Status::Static | Status::Dynamic | Status::Kinematic => {}
+        }
+        body.prev_linvel = body.linvel;
+        body.prev_angvel = body.angvel;
+
+        // the lowest point along the up vector over all shapes
+        let rotation = Mat2::from_angle(body.rotation);
+        let lowest = children
+            .iter()
+            .filter_map(|&child| shapes.get::<Shape>(child).ok())
+            .map(|shape| shape.lowest_point(rotation, up.0))
+            .min_by(|a, b| {
+                let a = a.dot(up.0);
+                let b = b.dot(up.0);
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            });
+        if let Some(lowest) = lowest {
+            body.lowest_position = body.position + lowest;
+        }
+    }
+
+    for (_, mut body, _) in &mut query.iter() {
+        // only bodies on a carrier are changed
+        let carrier = match body.carrier {
+            Some(carrier) => carrier,
+            None => continue,
+        };
+        body.carrier = None;
+        if let Some(&(from, to, rotation)) = carriers.get(&carrier) {
+            let position = to + Mat2::from_angle(rotation) * (body.position - from);
+            let delta = position - body.position;
+            body.position += delta;
+            body.lowest_position += delta;
+        }
+    }
+}
+
+pub fn joint_system<B: JointBehaviour>(
+    mut commands: Commands,
+    timestep: Res<FixedTimestep>,
+    mut query: Query<(Entity, Mut<Joint<B>>)>,
+    bodies: Query<Mut<RigidBody>>,
+) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    for (e, mut joint) in &mut query.iter() {
+        let anchor = if let Ok(anchor) = bodies.get::<RigidBody>(joint.inner.body1) {
+            anchor
+        } else {
+            commands.despawn_recursive(e);
+            continue;
+        };
+        let target = if let Ok(target) = bodies.get::<RigidBody>(joint.inner.body2) {
+            target
+        } else {
+            commands.despawn_recursive(e);
+            continue;
+        };
+        let offset = joint.inner.offset;
+        let angle = joint.inner.angle;
+        let position = joint.behaviour.position(offset, &anchor, &target);
+        let rotation = joint.behaviour.rotation(angle, &anchor, &target);
+        let linvel = joint.behaviour.linear_velocity(offset, &anchor, &target);
+        let angvel = joint.behaviour.angular_velocity(angle, &anchor, &target);
+        let linimp = joint.behaviour.linear_impulse(offset, &anchor, &target);
+        let angimp = joint.behaviour.angular_impulse(angle, &anchor, &target);
+
+        mem::drop(anchor);
+        mem::drop(target);
+
+        let mut target = bodies.get_mut::<RigidBody>(joint.inner.body2).unwrap();
+
+        if let Some(position) = position {
+            target.position = position;
+        }
+
+        if let Some(rotation) = rotation {
+            target.rotation = rotation;
+        }
+
+        if let Some(linvel) = linvel {
+            target.linvel = linvel;
+        }
+
+        if let Some(angvel) = angvel {
+            target.angvel = angvel;
+        }
+
+        if let Some(linimp) = linimp {
+            target.apply_linear_impulse(linimp);
+        }
+
+        if let Some(angimp) = angimp {
+            target.apply_angular_impulse(angimp);
+        }
+    }
+}
+
+/// The plane on which to translate the 2d position into 3d coordinates.
+#[derive(Debug, Clone, Copy)]
+pub enum TranslationMode {
+    AxesXY,
+    AxesXZ,
+    AxesYZ,
+}
+
+impl Default for TranslationMode {
+    fn default() -> Self {
+        Self::AxesXY
+    }
+}
+
+/// The axis on which to rotate the 2d rotation into a 3d quaternion.
+#[derive(Debug, Clone, Copy)]
+pub enum RotationMode {
+    AxisX,
+    AxisY,
+    AxisZ,
+}
+
+impl Default for RotationMode {
+    fn default() -> Self {
+        Self::AxisZ
+    }
+}
+
+pub fn fixed_timestep_system(time: Res<Time>, mut timestep: ResMut<FixedTimestep>) {
+    let delta_time = time.delta.as_secs_f32();
+    timestep.advance(delta_time);
+}
+
+pub fn state_hash_system(
+    timestep: Res<FixedTimestep>,
+    mut hash: ResMut<StateHash>,
+    mut query: Query<(Entity, &RigidBody)>,
+) {
+    if !timestep.is_deterministic() || !timestep.is_stepping() {
+        return;
+    }
+
+    let mut bodies = Vec::new();
+    for (entity, body) in &mut query.iter() {
+        bodies.push((entity, *body));
+    }
+    bodies.sort_by_key(|(entity, _)| *entity);
+
+    // the default hasher always uses the same keys
+    let mut hasher = DefaultHasher::new();
+    for (entity, body) in bodies {
+        entity.hash(&mut hasher);
+        body.hash_state(&mut hasher);
+    }
+    hash.0 = hasher.finish();
+}
+
+pub fn sync_transform_system(
+    timestep: Res<FixedTimestep>,
+    translation_mode: Res<TranslationMode>,
+    rotation_mode: Res<RotationMode>,
+    mut query: Query<(&RigidBody, Mut<Transform>)>,
+) {
+    let alpha = timestep.alpha();
+    for (body, mut transform) in &mut query.iter() {
+        // interpolate between the last two steps
+        let position = body.prev_position + (body.position - body.prev_position) * alpha;
+        let rotation = body.prev_rotation + (body.rotation - body.prev_rotation) * alpha;
+        match *translation_mode {
+            TranslationMode::AxesXY => {
+                let x = position.x();
+                let y = position.y();
+                let z = 0.0;
+                transform.translation = Vec3::new(x, y, z);
+            }
+            TranslationMode::AxesXZ => {
+                let x = position.x();
+                let y = 0.0;
+                let z = position.y();
+                transform.translation = Vec3::new(x, y, z);
+            }
+            TranslationMode::AxesYZ => {
+                let x = 0.0;
+                let y = position.x();
+                let z = position.y();
+                transform.translation = Vec3::new(x, y, z);
+            }
+        }
+        match *rotation_mode {
+            RotationMode::AxisX => {
+                transform.rotation = Quat::from_rotation_x(rotation);
+            }
+            RotationMode::AxisY => {
+                transform.rotation = Quat::from_rotation_y(rotation);
+            }
+            RotationMode::AxisZ => {
+                transform.rotation = Quat::from_rotation_z(rotation);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_timestep_accumulates() {
+        let mut timestep = FixedTimestep::from_step(0.25);
+        timestep.advance(0.6);
+        assert_eq!(timestep.steps(), 2);
+        assert!((timestep.alpha() - 0.4).abs() < 1.0e-4);
+        timestep.advance(0.2);
+        assert_eq!(timestep.steps(), 1);
+        timestep.advance(0.1);
+        assert_eq!(timestep.steps(), 0);
+        assert!(!timestep.is_stepping());
+    }
+
+    #[test]
+    fn fixed_timestep_drops_extra_steps() {
+        let mut timestep = FixedTimestep::from_step(0.25).with_max_steps(2);
+        timestep.advance(2.1);
+        assert_eq!(timestep.steps(), 2);
+        timestep.advance(0.0);
+        assert_eq!(timestep.steps(), 0);
+    }
+
+    #[test]
+    fn fixed_timestep_deterministic() {
+        let mut timestep = FixedTimestep::from_step(0.25).with_deterministic(true);
+        timestep.advance(0.0);
+        assert_eq!(timestep.steps(), 1);
+        timestep.advance(10.0);
+        assert_eq!(timestep.steps(), 1);
+        assert_eq!(timestep.alpha(), 1.0);
+    }
+
+    /// Returns an app that runs exactly one physics step per update.
+    pub(super) fn app() -> App {
+        let mut builder = App::build();
+        builder
+            .add_plugin(Physics2dPlugin::deterministic())
+            .add_resource(Time::default());
+        builder.app
+    }
+
+    /// Returns an app with gravity pulling bodies down, as in a platformer.
+    pub(super) fn platformer() -> App {
+        let mut app = app();
+        app.resources.insert(GlobalGravity(Vec2::new(0.0, -500.0)));
+        app.resources.insert(GlobalUp(Vec2::new(0.0, 1.0)));
+        app
+    }
+
+    /// Spawns a body with a single shape.
+    ///
+    /// Entity ids are random, so bodies are spawned with fixed ones to keep runs comparable.
+    pub(super) fn spawn(app: &mut App, id: u32, body: RigidBody, shape: Shape) -> Entity {
+        let entity = Entity::from_id(id);
+        let child = Entity::from_id(id + 1000);
+        app.world.spawn_as_entity(child, (shape, Parent(entity)));
+        app.world
+            .spawn_as_entity(entity, (body, Children::with(&[child])));
+        entity
+    }
+
+    pub(super) fn body(app: &App, entity: Entity) -> RigidBody {
+        *app.world.get::<RigidBody>(entity).unwrap()
+    }
+
+    /// Spawns a wide static floor, 10 units thick, with its top at 5.
+    pub(super) fn floor(app: &mut App, id: u32) -> Entity {
+        let floor = RigidBody::new(Mass::Infinite).with_status(Status::Static);
+        spawn(app, id, floor, Shape::from(Size2::new(100.0, 10.0)))
+    }
+
+    /// Spawns a semikinematic box of 10 by 10 units.
+    pub(super) fn player(app: &mut App, id: u32, position: Vec2) -> Entity {
+        let player = RigidBody::new(Mass::Real(1.0)).with_position(position);
+        spawn(app, id, player, Shape::from(Size2::new(10.0, 10.0)))
+    }
+
+    /// Runs a small scene and returns the hash after every frame.
+    fn simulate(frames: usize) -> Vec<u64> {
+        let mut app = platformer();
+        floor(&mut app, 1);
+        let crate_ = RigidBody::new(Mass::Real(1.0))
+            .with_position(Vec2::new(0.0, 20.0))
+            .with_rotation(0.3)
+            .with_linear_velocity(Vec2::new(10.0, 0.0));
+        spawn(&mut app, 2, crate_, Shape::from(Size2::new(8.0, 8.0)));
+        let ball = RigidBody::new(Mass::Real(2.0))
+            .with_position(Vec2::new(3.0, 40.0))
+            .with_angular_velocity(1.0);
+        spawn(&mut app, 3, ball, Shape::circle(4.0));
+
+        let mut hashes = Vec::new();
+        for _ in 0..frames {
+            app.update();
+            hashes.push(app.resources.get::<StateHash>().unwrap().0);
+        }
+        hashes
+    }
+
+    #[test]
+    fn deterministic_runs_have_equal_hashes() {
+        let first = simulate(120);
+        let second = simulate(120);
+        assert_eq!(first, second);
+        assert_ne!(first[0], first[first.len() - 1]);
+    }
+
+    #[test]
+    fn broad_phase_updates_match_rebuild() {
+        let mut app = app();
+        let shape = || Shape::from(Size2::new(10.0, 10.0));
+        for i in 0..10 {
+            let wall = RigidBody::new(Mass::Infinite)
+                .with_status(Status::Static)
+                .with_position(Vec2::new(i as f32 * 8.0, 0.0));
+            spawn(&mut app, i + 1, wall, shape());
+        }
+        // kinematic bodies without velocities stay where they are put
+        for i in 0..5 {
+            let platform = RigidBody::new(Mass::Infinite)
+                .with_status(Status::Kinematic)
+                .with_position(Vec2::new(i as f32 * 16.0, 8.0));
+            spawn(&mut app, i + 20, platform, shape());
+        }
+        for _ in 0..5 {
+            app.update();
+        }
+
+        // move a body, reshape another and despawn a third
+        app.world
+            .get_mut::<RigidBody>(Entity::from_id(20))
+            .unwrap()
+            .position = Vec2::new(40.0, -5.0);
+        *app.world.get_mut::<Shape>(Entity::from_id(1021)).unwrap() =
+            Shape::from(Size2::new(30.0, 30.0));
+        app.world.despawn(Entity::from_id(3)).unwrap();
+        app.world.despawn(Entity::from_id(1003)).unwrap();
+        app.update();
+
+        let mut colliders = Vec::new();
+        for id in (1..11).chain(20..25) {
+            let entity = Entity::from_id(id);
+            let child = Entity::from_id(id + 1000);
+            if let (Ok(body), Ok(shape)) = (
+                app.world.get::<RigidBody>(entity),
+                app.world.get::<Shape>(child),
+            ) {
+                colliders.push(shape_collider(
+                    entity,
+                    &body,
+                    child,
+                    &shape,
+                    shape.geometry(),
+                ));
+            }
+        }
+        let pairs = |broad: &BroadPhase| {
+            let mut pairs: Vec<_> = broad
+                .iter()
+                .map(|(collider1, collider2)| (collider1.shape, collider2.shape))
+                .collect();
+            pairs.sort();
+            pairs
+        };
+        let broad = app.resources.get::<BroadPhase>().unwrap();
+        assert_eq!(pairs(&broad), pairs(&BroadPhase::with_colliders(colliders)));
+    }
+
+    /// Drops a bouncy box onto the floor without gravity, returns its highest upward speed.
+    fn bounce_speed(speed: f32) -> f32 {
+        let mut app = app();
+        floor(&mut app, 1);
+        let ball = RigidBody::new(Mass::Real(1.0))
+            .with_position(Vec2::new(0.0, 11.0))
+            .with_linear_velocity(Vec2::new(0.0, -speed))
+            .with_restitution(1.0);
+        let ball = spawn(&mut app, 2, ball, Shape::from(Size2::new(10.0, 10.0)));
+        let mut fastest = f32::NEG_INFINITY;
+        for _ in 0..60 {
+            app.update();
+            fastest = fastest.max(body(&app, ball).linvel.y());
+        }
+        fastest
+    }
+
+    #[test]
+    fn slow_contacts_rest_without_gravity() {
+        assert!(bounce_speed(5.0) < 1.0e-3);
+        assert!(bounce_speed(300.0) > 100.0);
+    }
+
+    /// Shoots a small box at a thin wall, returns where it ends up.
+    fn shoot(ccd: bool) -> f32 {
+        let mut app = app();
+        let wall = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Static)
+            .with_position(Vec2::new(100.0, 0.0));
+        spawn(&mut app, 1, wall, Shape::from(Size2::new(1.0, 100.0)));
+        // the box travels about 45 units per step, ten times its size
+        let bullet = RigidBody::new(Mass::Real(1.0))
+            .with_position(Vec2::new(-30.0, 0.0))
+            .with_linear_velocity(Vec2::new(3000.0, 0.0))
+            .with_ccd(ccd);
+        let bullet = spawn(&mut app, 2, bullet, Shape::from(Size2::new(4.0, 4.0)));
+        for _ in 0..30 {
+            app.update();
+        }
+        body(&app, bullet).position.x()
+    }
+
+    #[test]
+    fn ccd_stops_fast_bodies_at_thin_walls() {
+        assert!(shoot(false) > 100.5);
+        let x = shoot(true);
+        assert!(x < 100.0 && x > 90.0, "{}", x);
+    }
+
+    fn one_way(app: &mut App) -> Entity {
+        let platform = floor(app, 1);
+        app.world
+            .insert_one(platform, OneWay(Vec2::zero()))
+            .unwrap();
+        platform
+    }
+
+    #[test]
+    fn one_way_platform_blocks_from_above() {
+        let mut app = platformer();
+        one_way(&mut app);
+        let player = player(&mut app, 2, Vec2::new(0.0, 30.0));
+
+        for _ in 0..60 {
+            app.update();
+        }
+        let player = body(&app, player);
+        assert!(
+            (player.position.y() - 10.0).abs() < 0.5,
+            "{:?}",
+            player.position
+        );
+        assert!(player.linvel.y().abs() < 1.0, "{:?}", player.linvel);
+    }
+
+    #[test]
+    fn one_way_platform_lets_bodies_jump_through() {
+        let mut app = platformer();
+        one_way(&mut app);
+        let player = player(&mut app, 2, Vec2::new(0.0, -30.0));
+        app.world.get_mut::<RigidBody>(player).unwrap().linvel = Vec2::new(0.0, 600.0);
+
+        let mut highest = f32::NEG_INFINITY;
+        for _ in 0..180 {
+            app.update();
+            highest = highest.max(body(&app, player).position.y());
+        }
+        assert!(highest > 50.0, "{}", highest);
+        // after the jump, the player lands on top of the platform
+        let player = body(&app, player);
+        assert!(
+            (player.position.y() - 10.0).abs() < 0.5,
+            "{:?}",
+            player.position
+        );
+    }
+
+    #[test]
+    fn one_way_platform_lets_bodies_drop_through() {
+        let mut app = platformer();
+        one_way(&mut app);
+        let player = player(&mut app, 2, Vec2::new(0.0, 30.0));
+        for _ in 0..60 {
+            app.update();
+        }
+        assert!((body(&app, player).position.y() - 10.0).abs() < 0.5);
+
+        app.world
+            .get_mut::<RigidBody>(player)
+            .unwrap()
+            .drop_through(0.5);
+        for _ in 0..40 {
+            app.update();
+        }
+        let player = body(&app, player);
+        assert!(player.position.y() < -20.0, "{:?}", player.position);
+        assert!(!player.is_dropping_through());
+    }
+
+    fn count<T: Send + Sync + 'static>(app: &App, reader: &mut EventReader<T>) -> usize {
+        let events = app.resources.get::<Events<T>>().unwrap();
+        reader.iter(&events).count()
+    }
+
+    #[test]
+    fn collision_events_follow_contacts() {
+        let mut app = platformer();
+        floor(&mut app, 1);
+        let player = player(&mut app, 2, Vec2::new(0.0, 20.0));
+        let mut started = app
+            .resources
+            .get::<Events<CollisionStarted>>()
+            .unwrap()
+            .get_reader();
+        let mut ongoing = app
+            .resources
+            .get::<Events<CollisionOngoing>>()
+            .unwrap()
+            .get_reader();
+        let mut ended = app
+            .resources
+            .get::<Events<CollisionEnded>>()
+            .unwrap()
+            .get_reader();
+        let mut update = |app: &mut App| {
+            app.update();
+            (
+                count(app, &mut started),
+                count(app, &mut ongoing),
+                count(app, &mut ended),
+            )
+        };
+
+        let mut events = Vec::new();
+        for _ in 0..60 {
+            events.push(update(&mut app));
+        }
+        let landing = events
+            .iter()
+            .position(|&counts| counts != (0, 0, 0))
+            .expect("the body should land");
+        assert_eq!(events[landing], (1, 0, 0));
+        for &counts in &events[landing + 1..] {
+            assert_eq!(counts, (0, 1, 0));
+        }
+
+        // teleported away, the body stops touching the floor at once
+        app.world.get_mut::<RigidBody>(player).unwrap().position = Vec2::new(0.0, 200.0);
+        assert_eq!(update(&mut app), (0, 0, 1));
+        for _ in 0..5 {
+            assert_eq!(update(&mut app), (0, 0, 0));
+        }
+    }
+
+    #[test]
+    fn triggers_are_entered_and_exited_once() {
+        let mut app = app();
+        let trigger = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Static)
+            .with_position(Vec2::new(100.0, 0.0));
+        let shape = Shape::from(Size2::new(20.0, 20.0)).with_sensor(true);
+        let trigger = spawn(&mut app, 1, trigger, shape);
+        // the bullet passes through the trigger in a few steps
+        let bullet = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Kinematic)
+            .with_linear_velocity(Vec2::new(600.0, 0.0));
+        let bullet = spawn(&mut app, 2, bullet, Shape::from(Size2::new(10.0, 10.0)));
+
+        let mut entered_reader = app
+            .resources
+            .get::<Events<TriggerEntered>>()
+            .unwrap()
+            .get_reader();
+        let mut exited_reader = app
+            .resources
+            .get::<Events<TriggerExited>>()
+            .unwrap()
+            .get_reader();
+        let mut entered = Vec::new();
+        let mut exited = Vec::new();
+        let mut steps_inside = 0;
+        for _ in 0..40 {
+            app.update();
+            let events = app.resources.get::<Events<TriggerEntered>>().unwrap();
+            entered.extend(entered_reader.iter(&events).map(|event| event.0));
+            let events = app.resources.get::<Events<TriggerExited>>().unwrap();
+            exited.extend(exited_reader.iter(&events).map(|event| event.0));
+            let triggers = app.resources.get::<Triggers>().unwrap();
+            if triggers.inside(bullet) == [Entity::from_id(1001)] {
+                steps_inside += 1;
+            }
+        }
+
+        let contact = TriggerContact {
+            trigger: Entity::from_id(1001),
+            trigger_body: trigger,
+            body: bullet,
+        };
+        assert_eq!(entered, vec![contact]);
+        assert_eq!(exited, vec![contact]);
+        assert!(steps_inside > 1, "{}", steps_inside);
+        assert!(body(&app, bullet).position.x() > 115.0);
+    }
+
+    #[test]
+    fn grounded_bodies_can_jump_for_the_coyote_time() {
+        let mut app = platformer();
+        let floor = floor(&mut app, 1);
+        let body = player(&mut app, 2, Vec2::new(0.0, 10.0));
+        app.world
+            .insert_one(body, Grounded::default().with_coyote_time(0.1))
+            .unwrap();
+        let grounded = |app: &App| *app.world.get::<Grounded>(body).unwrap();
+        let teleport = |app: &mut App, position| {
+            app.world.get_mut::<RigidBody>(body).unwrap().position = position;
+        };
+
+        for _ in 0..20 {
+            app.update();
+        }
+        let standing = grounded(&app);
+        assert_eq!(standing.ground(), Some(floor));
+        assert!((standing.normal() - Vec2::unit_y()).length() < 1.0e-3);
+        assert_eq!(standing.airtime(), 0.0);
+        assert!(standing.can_jump());
+
+        // teleported into the air, the body can still jump for a while
+        teleport(&mut app, Vec2::new(0.0, 200.0));
+        app.update();
+        let falling = grounded(&app);
+        assert!(!falling.is_grounded());
+        assert!(falling.airtime() > 0.0);
+        assert!(falling.can_jump());
+        for _ in 0..6 {
+            app.update();
+        }
+        assert!(!grounded(&app).can_jump());
+
+        // back on the ground, jumping uses up the coyote time
+        teleport(&mut app, Vec2::new(0.0, 10.0));
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(grounded(&app).is_grounded());
+        teleport(&mut app, Vec2::new(0.0, 200.0));
+        app.update();
+        let mut jumping = app.world.get_mut::<Grounded>(body).unwrap();
+        assert!(jumping.can_jump());
+        jumping.jump();
+        assert!(!jumping.can_jump());
+    }
+}
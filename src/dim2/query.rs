@@ -0,0 +1,746 @@
+//! Spatial queries against the colliders of the `BroadPhase`.
+//!
+//! The queries see the colliders as they were in the last broad phase, which
+//! runs after the physics step and before the solver of every step.  Sensors
+//! are only hit by queries with a `QueryFilter` that allows them.
+use std::cmp::Ordering;
+
+use bevy::math::*;
+use bevy::prelude::*;
+
+use super::collision::closest_points_segments;
+use super::*;
+
+/// A hit of a ray with a `Shape`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// The entity of the `RigidBody` that was hit.
+    pub body: Entity,
+    /// The entity of the `Shape` that was hit.
+    pub shape: Entity,
+    /// The point where the ray entered the shape.
+    pub point: Vec2,
+    /// The normal of the shape at the point.
+    ///
+    /// Rays that start inside of a shape hit it at their origin with a normal opposite to their direction.
+    pub normal: Vec2,
+    /// The distance from the origin of the ray to the point.
+    pub distance: f32,
+}
+
+/// A hit of a swept `Shape` with another `Shape`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeHit {
+    /// The entity of the `RigidBody` that was hit.
+    pub body: Entity,
+    /// The entity of the `Shape` that was hit.
+    pub shape: Entity,
+    /// The time of impact, from `0.0` at the start to `1.0` at the end of the sweep.
+    pub time: f32,
+    /// The point where the shapes touch.
+    pub point: Vec2,
+    /// The normal of the hit shape at the point, pointing towards the swept shape.
+    pub normal: Vec2,
+}
+
+/// A `Shape` that contains a point or overlaps another shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Overlap {
+    /// The entity of the `RigidBody` of the shape.
+    pub body: Entity,
+    /// The entity of the shape.
+    pub shape: Entity,
+}
+
+impl BroadPhase {
+    /// Returns the first shape hit by a ray within `max_distance`.
+    pub fn raycast(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        self.raycast_filtered(origin, direction, max_distance, &QueryFilter::default())
+    }
+
+    /// Returns the first shape allowed by `filter` hit by a ray within `max_distance`.
+    pub fn raycast_filtered(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        self.ray_hits(origin, direction, max_distance, filter)
+            .min_by(|a, b| compare_hits(a, b))
+    }
+
+    /// Returns all shapes hit by a ray within `max_distance`, sorted by distance.
+    pub fn raycast_all(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Vec<RayHit> {
+        self.raycast_all_filtered(origin, direction, max_distance, &QueryFilter::default())
+    }
+
+    /// Returns all shapes allowed by `filter` hit by a ray within `max_distance`, sorted by distance.
+    pub fn raycast_all_filtered(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Vec<RayHit> {
+        let mut hits: Vec<_> = self
+            .ray_hits(origin, direction, max_distance, filter)
+            .collect();
+        hits.sort_by(compare_hits);
+        hits
+    }
+
+    fn ray_hits<'a>(
+        &'a self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &'a QueryFilter,
+    ) -> impl Iterator<Item = RayHit> + 'a {
+        let length = direction.length();
+        let direction = if length > f32::EPSILON {
+            direction / length
+        } else {
+            Vec2::zero()
+        };
+        let max_distance = if length > f32::EPSILON {
+            max_distance.max(0.0)
+        } else {
+            0.0
+        };
+        let end = origin + direction * max_distance;
+        let bounding_box = BoundingBox::new(origin.min(end), origin.max(end));
+        self.intersecting(&bounding_box)
+            .filter(move |collider| filter.allows(collider.body, collider.layers, collider.sensor))
+            .filter_map(move |collider| {
+                let (distance, normal) = raycast(collider, origin, direction, max_distance)?;
+                Some(RayHit {
+                    body: collider.body,
+                    shape: collider.shape,
+                    point: origin + direction * distance,
+                    normal,
+                    distance,
+                })
+            })
+    }
+
+    /// Sweeps a shape from one position and rotation to another and returns the first shape it hits.
+    ///
+    /// The rotation is interpolated linearly.  A shape that already overlaps another
+    /// shape at the start of the sweep hits it at time zero.
+    pub fn shape_cast(
+        &self,
+        shape: &Shape,
+        from: (Vec2, f32),
+        to: (Vec2, f32),
+    ) -> Option<ShapeHit> {
+        self.shape_cast_filtered(shape, from, to, &QueryFilter::default())
+    }
+
+    /// Sweeps a shape from one position and rotation to another and returns the first shape
+    /// allowed by `filter` it hits.
+    pub fn shape_cast_filtered(
+        &self,
+        shape: &Shape,
+        from: (Vec2, f32),
+        to: (Vec2, f32),
+        filter: &QueryFilter,
+    ) -> Option<ShapeHit> {
+        let geometry = shape.geometry();
+        let reach = Vec2::splat(reach(&geometry));
+        let bounding_box = BoundingBox::new(from.0.min(to.0) - reach, from.0.max(to.0) + reach);
+        let linear = to.0 - from.0;
+        let angular = reach.x() * (to.1 - from.1).abs();
+
+        let mut best: Option<ShapeHit> = None;
+        for collider in self.intersecting(&bounding_box) {
+            if !filter.allows(collider.body, collider.layers, collider.sensor) {
+                continue;
+            }
+            // the swept collider borrows the entities of the other one, they are never read
+            let swept = |time: f32| {
+                Obb::new(
+                    Status::Semikinematic,
+                    collider.body,
+                    collider.shape,
+                    Mat2::from_angle(from.1 + (to.1 - from.1) * time),
+                    from.0 + (to.0 - from.0) * time,
+                    geometry.clone(),
+                    shape.layers,
+                )
+            };
+            let at = |time: f32| collision::collide(collider, &swept(time));
+            let (time, manifold) = match at(0.0) {
+                Some(manifold) => (0.0, manifold),
+                None => match collision::sweep(&swept, collider, linear, angular, at) {
+                    Some(hit) => hit,
+                    None => continue,
+                },
+            };
+            if best.map_or(false, |best| {
+                (best.time, best.shape) <= (time, collider.shape)
+            }) {
+                continue;
+            }
+            let mut point = Vec2::zero();
+            for contact in &manifold.contacts {
+                point += contact.position;
+            }
+            best = Some(ShapeHit {
+                body: collider.body,
+                shape: collider.shape,
+                time,
+                point: point / manifold.contacts.len().max(1) as f32,
+                normal: manifold.normal,
+            });
+        }
+        best
+    }
+
+    /// Returns all shapes that contain a point.
+    pub fn point_query(&self, point: Vec2) -> Vec<Overlap> {
+        self.point_query_filtered(point, &QueryFilter::default())
+    }
+
+    /// Returns all shapes allowed by `filter` that contain a point.
+    pub fn point_query_filtered(&self, point: Vec2, filter: &QueryFilter) -> Vec<Overlap> {
+        let bounding_box = BoundingBox::new(point, point);
+        self.intersecting(&bounding_box)
+            .filter(|collider| filter.allows(collider.body, collider.layers, collider.sensor))
+            // a ray without length only hits the shapes it starts in
+            .filter(|collider| raycast(collider, point, Vec2::zero(), 0.0).is_some())
+            .map(|collider| Overlap {
+                body: collider.body,
+                shape: collider.shape,
+            })
+            .collect()
+    }
+
+    /// Returns all shapes that overlap a shape at a position and rotation.
+    pub fn overlap(&self, shape: &Shape, position: Vec2, rotation: f32) -> Vec<Overlap> {
+        self.overlap_filtered(shape, position, rotation, &QueryFilter::default())
+    }
+
+    /// Returns all shapes allowed by `filter` that overlap a shape at a position and rotation.
+    pub fn overlap_filtered(
+        &self,
+        shape: &Shape,
+        position: Vec2,
+        rotation: f32,
+        filter: &QueryFilter,
+    ) -> Vec<Overlap> {
+        let geometry = shape.geometry();
+        let reach = Vec2::splat(reach(&geometry));
+        let bounding_box = BoundingBox::new(position - reach, position + reach);
+
+        let mut overlaps = Vec::new();
+        for collider in self.intersecting(&bounding_box) {
+            if !filter.allows(collider.body, collider.layers, collider.sensor) {
+                continue;
+            }
+            // the collider borrows the entities of the other one, they are never read
+            let obb = Obb::new(
+                Status::Semikinematic,
+                collider.body,
+                collider.shape,
+                Mat2::from_angle(rotation),
+                position,
+                geometry.clone(),
+                shape.layers,
+            );
+            if collision::overlaps(collider, &obb) {
+                overlaps.push(Overlap {
+                    body: collider.body,
+                    shape: collider.shape,
+                });
+            }
+        }
+        overlaps
+    }
+}
+
+/// Orders hits by distance, and hits at the same distance by their shape.
+fn compare_hits(a: &RayHit, b: &RayHit) -> Ordering {
+    a.distance
+        .partial_cmp(&b.distance)
+        .unwrap_or(Ordering::Equal)
+        .then(a.shape.cmp(&b.shape))
+}
+
+/// Keeps the earlier of two hits.
+fn earliest(best: Option<(f32, Vec2)>, hit: Option<(f32, Vec2)>) -> Option<(f32, Vec2)> {
+    match (best, hit) {
+        (Some(best), Some(hit)) if hit.0 < best.0 => Some(hit),
+        (None, hit) => hit,
+        (best, _) => best,
+    }
+}
+
+/// Casts a ray with a normalized direction against a collider.
+///
+/// Returns the distance to the hit and the world space normal at the hit.
+fn raycast(obb: &Obb, origin: Vec2, direction: Vec2, max: f32) -> Option<(f32, Vec2)> {
+    let inverse = obb.rotation.transpose();
+    let o = inverse * (origin - obb.position);
+    let d = inverse * direction;
+    let (distance, normal) = match obb.geometry {
+        Geometry::Polygon(ref polygon) => ray_polygon(polygon, o, d, max)?,
+        Geometry::Circle { center, radius } => ray_capsule(center, center, radius, o, d, max)?,
+        Geometry::Capsule { a, b, radius } => ray_capsule(a, b, radius, o, d, max)?,
+        Geometry::TileMap { offset, ref map } => ray_tilemap(offset, map, o, d, max)?,
+    };
+    Some((distance, obb.rotation * normal))
+}
+
+/// Casts a ray against a convex polygon by clipping it against all edges.
+fn ray_polygon(polygon: &Polygon, o: Vec2, d: Vec2, max: f32) -> Option<(f32, Vec2)> {
+    let mut near = 0.0;
+    let mut far = max;
+    let mut normal = -d;
+    for (&v, &n) in polygon.vertices.iter().zip(polygon.normals.iter()) {
+        let distance = n.dot(v - o);
+        let speed = n.dot(d);
+        if speed == 0.0 {
+            if distance < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let time = distance / speed;
+        if speed < 0.0 {
+            if time > near {
+                near = time;
+                normal = n;
+            }
+        } else {
+            far = time.min(far);
+        }
+        if near > far {
+            return None;
+        }
+    }
+    Some((near, normal))
+}
+
+/// Casts a ray against a circle.
+fn ray_circle(center: Vec2, radius: f32, o: Vec2, d: Vec2, max: f32) -> Option<(f32, Vec2)> {
+    let m = o - center;
+    let b = m.dot(d);
+    let c = m.dot(m) - radius * radius;
+    if b > 0.0 && c > 0.0 {
+        return None;
+    }
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time = -b - discriminant.sqrt();
+    if time > max {
+        return None;
+    }
+    Some((time, (m + d * time) / radius))
+}
+
+/// Casts a ray against a capsule, a circle being a capsule with a single point.
+fn ray_capsule(p: Vec2, q: Vec2, radius: f32, o: Vec2, d: Vec2, max: f32) -> Option<(f32, Vec2)> {
+    let (closest, _) = closest_points_segments(p, q, o, o);
+    if (o - closest).length_squared() <= radius * radius {
+        return Some((0.0, -d));
+    }
+
+    let mut best = ray_circle(p, radius, o, d, max);
+    best = earliest(best, ray_circle(q, radius, o, d, max));
+
+    let axis = q - p;
+    let length = axis.length();
+    if length > f32::EPSILON {
+        let axis = axis / length;
+        let side = Vec2::new(axis.y(), -axis.x());
+        for &n in &[side, -side] {
+            let speed = n.dot(d);
+            if speed >= 0.0 {
+                continue;
+            }
+            let time = n.dot(p + n * radius - o) / speed;
+            let along = (o + d * time - p).dot(axis);
+            if time >= 0.0 && time <= max && along >= 0.0 && along <= length {
+                best = earliest(best, Some((time, n)));
+            }
+        }
+    }
+    best
+}
+
+/// Casts a ray against an axis aligned box.
+fn ray_box(min: Vec2, max: Vec2, o: Vec2, d: Vec2, max_distance: f32) -> Option<(f32, Vec2)> {
+    let mut near = 0.0;
+    let mut far = max_distance;
+    let mut normal = -d;
+    let axes = [Vec2::unit_x(), Vec2::unit_y()];
+    for (i, &axis) in axes.iter().enumerate() {
+        if d[i].abs() <= f32::EPSILON {
+            if o[i] < min[i] || o[i] > max[i] {
+                return None;
+            }
+            continue;
+        }
+        let inverse = d[i].recip();
+        let mut enter = (min[i] - o[i]) * inverse;
+        let mut exit = (max[i] - o[i]) * inverse;
+        let mut n = -axis;
+        if enter > exit {
+            std::mem::swap(&mut enter, &mut exit);
+            n = axis;
+        }
+        if enter > near {
+            near = enter;
+            normal = n;
+        }
+        far = exit.min(far);
+        if near > far {
+            return None;
+        }
+    }
+    Some((near, normal))
+}
+
+/// Casts a ray against the blocks of merged tiles of a tilemap.
+fn ray_tilemap(offset: Vec2, map: &TileMap, o: Vec2, d: Vec2, max: f32) -> Option<(f32, Vec2)> {
+    let o = o - offset;
+    let end = o + d * max;
+    let tile = map.tile();
+    let mut best = None;
+    for block in map.blocks(o.min(end), o.max(end)) {
+        let min = Vec2::new(
+            block.column as f32 * tile.width,
+            block.row as f32 * tile.height,
+        );
+        let size = Vec2::new(
+            block.columns as f32 * tile.width,
+            block.rows as f32 * tile.height,
+        );
+        best = earliest(best, ray_box(min, min + size, o, d, max));
+    }
+    best
+}
+
+/// Returns the distance of the furthest point of a geometry from the origin of its body.
+fn reach(geometry: &Geometry) -> f32 {
+    match geometry {
+        Geometry::Polygon(polygon) => polygon
+            .vertices
+            .iter()
+            .fold(0.0, |reach, v| v.length().max(reach)),
+        Geometry::Circle { center, radius } => center.length() + radius,
+        Geometry::Capsule { a, b, radius } => a.length().max(b.length()) + radius,
+        Geometry::TileMap { offset, map } => {
+            let size = map.size();
+            let corners = [
+                *offset,
+                *offset + Vec2::new(size.width, 0.0),
+                *offset + Vec2::new(size.width, size.height),
+                *offset + Vec2::new(0.0, size.height),
+            ];
+            corners.iter().fold(0.0, |reach, v| v.length().max(reach))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collider(id: u32, shape: Shape, position: Vec2) -> Obb {
+        Obb::new(
+            Status::Static,
+            Entity::from_id(id),
+            Entity::from_id(id + 100),
+            Mat2::identity(),
+            position,
+            shape.geometry(),
+            CollisionLayers::default(),
+        )
+    }
+
+    fn assert_hit(hit: Option<RayHit>, id: u32, distance: f32, normal: Vec2) {
+        let hit = hit.expect("the ray should hit");
+        assert_eq!(hit.body, Entity::from_id(id));
+        assert_eq!(hit.shape, Entity::from_id(id + 100));
+        assert!(
+            (hit.distance - distance).abs() < 1.0e-4,
+            "distance {}, expected {}",
+            hit.distance,
+            distance
+        );
+        assert!(
+            (hit.normal - normal).length() < 1.0e-4,
+            "normal {:?}, expected {:?}",
+            hit.normal,
+            normal
+        );
+    }
+
+    fn square() -> Shape {
+        Shape::from(Size2::new(2.0, 2.0))
+    }
+
+    #[test]
+    fn raycast_hits_every_shape() {
+        let map = TileMap::new(vec![true; 3], 3, Size2::new(1.0, 1.0)).unwrap();
+        // the tilemap grows from its origin, it is shifted to cover the same span
+        let shapes = vec![
+            (square(), Vec2::new(5.0, 0.0)),
+            (Shape::circle(1.0), Vec2::new(5.0, 0.0)),
+            (Shape::capsule(2.0, 1.0), Vec2::new(5.0, 0.0)),
+            (Shape::tilemap(map), Vec2::new(4.0, -0.5)),
+        ];
+        let left = Vec2::new(-1.0, 0.0);
+        for (shape, offset) in shapes {
+            let broad = BroadPhase::with_colliders(vec![collider(1, shape, offset)]);
+            assert_hit(
+                broad.raycast(Vec2::zero(), Vec2::unit_x(), 10.0),
+                1,
+                4.0,
+                left,
+            );
+            assert_eq!(broad.raycast(Vec2::zero(), Vec2::unit_x(), 3.0), None);
+            assert_eq!(broad.raycast(Vec2::zero(), -Vec2::unit_x(), 10.0), None);
+            assert_eq!(
+                broad.raycast(Vec2::new(0.0, 3.0), Vec2::unit_x(), 10.0),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn raycast_hits_caps_and_tops() {
+        let down = -Vec2::unit_y();
+        let up = Vec2::unit_y();
+        let capsule =
+            BroadPhase::with_colliders(vec![collider(1, Shape::capsule(2.0, 1.0), Vec2::zero())]);
+        assert_hit(
+            capsule.raycast(Vec2::new(0.0, 10.0), down, 20.0),
+            1,
+            8.0,
+            up,
+        );
+
+        let map = TileMap::new(vec![true; 3], 3, Size2::new(1.0, 1.0)).unwrap();
+        let tilemap =
+            BroadPhase::with_colliders(vec![collider(1, Shape::tilemap(map), Vec2::zero())]);
+        assert_hit(tilemap.raycast(Vec2::new(1.5, 5.0), down, 10.0), 1, 4.0, up);
+        assert_eq!(tilemap.raycast(Vec2::new(4.5, 5.0), down, 10.0), None);
+    }
+
+    #[test]
+    fn raycast_from_inside_hits_at_origin() {
+        let broad = BroadPhase::with_colliders(vec![collider(1, square(), Vec2::new(5.0, 0.0))]);
+        let hit = broad.raycast(Vec2::new(5.0, 0.0), Vec2::unit_x(), 10.0);
+        assert_hit(hit, 1, 0.0, Vec2::new(-1.0, 0.0));
+        assert_eq!(hit.unwrap().point, Vec2::new(5.0, 0.0));
+    }
+
+    #[test]
+    fn raycast_all_sorts_by_distance() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, square(), Vec2::new(10.0, 0.0)),
+            collider(2, square(), Vec2::new(5.0, 0.0)),
+        ]);
+        let hits = broad.raycast_all(Vec2::zero(), Vec2::unit_x(), 20.0);
+        assert_eq!(hits.len(), 2);
+        assert_hit(Some(hits[0]), 2, 4.0, Vec2::new(-1.0, 0.0));
+        assert_hit(Some(hits[1]), 1, 9.0, Vec2::new(-1.0, 0.0));
+        assert_eq!(hits[0].point, Vec2::new(4.0, 0.0));
+    }
+
+    #[test]
+    fn raycast_skips_filtered_bodies() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, square(), Vec2::new(10.0, 0.0)),
+            collider(2, square(), Vec2::new(5.0, 0.0)),
+        ]);
+        let excluded = QueryFilter::default().with_excluded(Entity::from_id(2));
+        let hit = broad.raycast_filtered(Vec2::zero(), Vec2::unit_x(), 20.0, &excluded);
+        assert_hit(hit, 1, 9.0, Vec2::new(-1.0, 0.0));
+        let hits = broad.raycast_all_filtered(Vec2::zero(), Vec2::unit_x(), 20.0, &excluded);
+        assert_eq!(hits.len(), 1);
+        let masked = QueryFilter::new(0);
+        assert_eq!(
+            broad.raycast_filtered(Vec2::zero(), Vec2::unit_x(), 20.0, &masked),
+            None
+        );
+    }
+
+    fn assert_cast(hit: Option<ShapeHit>, id: u32, time: f32, normal: Vec2) {
+        let hit = hit.expect("the shape should hit");
+        assert_eq!(hit.body, Entity::from_id(id));
+        assert_eq!(hit.shape, Entity::from_id(id + 100));
+        assert!(
+            (hit.time - time).abs() < 1.0e-2,
+            "time {}, expected {}",
+            hit.time,
+            time
+        );
+        assert!(
+            (hit.normal - normal).length() < 1.0e-3,
+            "normal {:?}, expected {:?}",
+            hit.normal,
+            normal
+        );
+    }
+
+    #[test]
+    fn shape_cast_finds_time_of_impact() {
+        let broad = BroadPhase::with_colliders(vec![collider(1, square(), Vec2::new(6.0, 0.0))]);
+        let from = (Vec2::zero(), 0.0);
+        let to = (Vec2::new(20.0, 0.0), 0.0);
+        let left = Vec2::new(-1.0, 0.0);
+        assert_cast(broad.shape_cast(&square(), from, to), 1, 0.2, left);
+        assert_cast(
+            broad.shape_cast(&Shape::circle(1.0), from, to),
+            1,
+            0.2,
+            left,
+        );
+        let capsule = Shape::capsule(2.0, 1.0);
+        assert_cast(broad.shape_cast(&capsule, from, to), 1, 0.2, left);
+
+        assert_eq!(
+            broad.shape_cast(&square(), to, (Vec2::new(30.0, 0.0), 0.0)),
+            None
+        );
+        assert_eq!(
+            broad.shape_cast(
+                &square(),
+                (Vec2::new(0.0, 3.0), 0.0),
+                (Vec2::new(20.0, 3.0), 0.0)
+            ),
+            None
+        );
+        let inside = (Vec2::new(5.5, 0.0), 0.0);
+        assert_eq!(broad.shape_cast(&square(), inside, to).unwrap().time, 0.0);
+    }
+
+    #[test]
+    fn shape_cast_lands_on_tilemap() {
+        let map = TileMap::new(vec![true; 3], 3, Size2::new(1.0, 1.0)).unwrap();
+        let broad =
+            BroadPhase::with_colliders(vec![collider(1, Shape::tilemap(map), Vec2::zero())]);
+        let from = (Vec2::new(1.5, 5.0), 0.0);
+        let to = (Vec2::new(1.5, -5.0), 0.0);
+        let hit = broad.shape_cast(&Shape::circle(0.5), from, to);
+        assert_cast(hit, 1, 0.35, Vec2::unit_y());
+    }
+
+    #[test]
+    fn shape_cast_skips_filtered_bodies() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, square(), Vec2::new(12.0, 0.0)),
+            collider(2, square(), Vec2::new(6.0, 0.0)),
+        ]);
+        let from = (Vec2::zero(), 0.0);
+        let to = (Vec2::new(20.0, 0.0), 0.0);
+        let left = Vec2::new(-1.0, 0.0);
+        assert_cast(broad.shape_cast(&square(), from, to), 2, 0.2, left);
+        let excluded = QueryFilter::default().with_excluded(Entity::from_id(2));
+        let hit = broad.shape_cast_filtered(&square(), from, to, &excluded);
+        assert_cast(hit, 1, 0.5, left);
+    }
+
+    fn overlapping(overlaps: Vec<Overlap>) -> Vec<u32> {
+        let mut ids: Vec<_> = overlaps.iter().map(|overlap| overlap.body.id()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn point_query_finds_containing_shapes() {
+        let map = TileMap::new(vec![true; 3], 3, Size2::new(1.0, 1.0)).unwrap();
+        let shapes = vec![
+            (square(), Vec2::new(5.0, 0.0)),
+            (Shape::circle(1.0), Vec2::new(5.0, 0.0)),
+            (Shape::capsule(2.0, 1.0), Vec2::new(5.0, 0.0)),
+            (Shape::tilemap(map), Vec2::new(4.0, -0.5)),
+        ];
+        for (shape, offset) in shapes {
+            let broad = BroadPhase::with_colliders(vec![collider(1, shape, offset)]);
+            let overlaps = broad.point_query(Vec2::new(5.0, 0.0));
+            assert_eq!(
+                overlaps,
+                vec![Overlap {
+                    body: Entity::from_id(1),
+                    shape: Entity::from_id(101),
+                }]
+            );
+            assert!(broad.point_query(Vec2::new(5.0, 3.0)).is_empty());
+            assert!(broad.point_query(Vec2::new(7.5, 0.0)).is_empty());
+        }
+    }
+
+    #[test]
+    fn overlap_finds_touched_shapes() {
+        let map = TileMap::new(vec![true; 3], 3, Size2::new(1.0, 1.0)).unwrap();
+        let shapes = vec![
+            (square(), Vec2::new(5.0, 0.0)),
+            (Shape::circle(1.0), Vec2::new(5.0, 0.0)),
+            (Shape::capsule(2.0, 1.0), Vec2::new(5.0, 0.0)),
+            (Shape::tilemap(map), Vec2::new(4.0, -0.5)),
+        ];
+        for (shape, offset) in shapes {
+            let broad = BroadPhase::with_colliders(vec![collider(1, shape, offset)]);
+            for query in &[square(), Shape::circle(1.0)] {
+                let overlaps = broad.overlap(query, Vec2::new(6.5, 0.0), 0.0);
+                assert_eq!(overlapping(overlaps), vec![1]);
+                assert!(broad.overlap(query, Vec2::new(8.5, 0.0), 0.0).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn point_and_overlap_queries_skip_filtered_bodies() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, square(), Vec2::new(5.0, 0.0)),
+            collider(2, Shape::circle(1.0), Vec2::new(5.0, 0.0)),
+        ]);
+        let point = Vec2::new(5.0, 0.0);
+        assert_eq!(overlapping(broad.point_query(point)), vec![1, 2]);
+        assert_eq!(
+            overlapping(broad.overlap(&square(), point, 0.0)),
+            vec![1, 2]
+        );
+
+        let excluded = QueryFilter::default().with_excluded(Entity::from_id(2));
+        let overlaps = broad.point_query_filtered(point, &excluded);
+        assert_eq!(overlapping(overlaps), vec![1]);
+        let overlaps = broad.overlap_filtered(&square(), point, 0.0, &excluded);
+        assert_eq!(overlapping(overlaps), vec![1]);
+    }
+
+    #[test]
+    fn queries_only_hit_sensors_when_allowed() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, square(), Vec2::new(10.0, 0.0)),
+            collider(2, square(), Vec2::new(5.0, 0.0)).with_sensor(true),
+        ]);
+        let sensors = QueryFilter::default().with_sensors(true);
+        let left = Vec2::new(-1.0, 0.0);
+        let hit = broad.raycast(Vec2::zero(), Vec2::unit_x(), 20.0);
+        assert_hit(hit, 1, 9.0, left);
+        let hit = broad.raycast_filtered(Vec2::zero(), Vec2::unit_x(), 20.0, &sensors);
+        assert_hit(hit, 2, 4.0, left);
+
+        let from = (Vec2::new(-4.0, 0.0), 0.0);
+        let to = (Vec2::new(16.0, 0.0), 0.0);
+        assert_cast(broad.shape_cast(&square(), from, to), 1, 0.6, left);
+        let hit = broad.shape_cast_filtered(&square(), from, to, &sensors);
+        assert_cast(hit, 2, 0.3, left);
+
+        let point = Vec2::new(5.0, 0.0);
+        assert!(broad.point_query(point).is_empty());
+        let overlaps = broad.point_query_filtered(point, &sensors);
+        assert_eq!(overlapping(overlaps), vec![2]);
+        assert!(broad.overlap(&square(), point, 0.0).is_empty());
+        let overlaps = broad.overlap_filtered(&square(), point, 0.0, &sensors);
+        assert_eq!(overlapping(overlaps), vec![2]);
+    }
+}
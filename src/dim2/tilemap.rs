@@ -0,0 +1,285 @@
+//! Static tilemap colliders for grid based levels.
+//!
+//! Adjacent solid cells are merged into as few rectangles as possible, and
+//! only the rectangles close to the other collider are tested.
+
+use std::fmt;
+use std::sync::Arc;
+
+use bevy::math::*;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use super::Size2;
+
+/// A rectangle of merged cells, in cell coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Block {
+    pub(crate) column: usize,
+    pub(crate) row: usize,
+    pub(crate) columns: usize,
+    pub(crate) rows: usize,
+}
+
+struct TileMapData {
+    cells: Vec<bool>,
+    blocks: Vec<Block>,
+    owners: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawTileMap {
+    cells: Vec<bool>,
+    columns: usize,
+    rows: usize,
+    tile: Size2,
+}
+
+/// A static grid of solid and empty tiles.
+///
+/// The cells are stored row by row, starting with the bottom row, and the
+/// bottom left corner of the map is at the offset of its `Shape`.  Cloning a
+/// `TileMap` is cheap, as the cells are shared.
+///
+/// Contacts with edges between two solid cells are suppressed, so that bodies
+/// slide smoothly along floors and walls made of many tiles.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawTileMap", into = "RawTileMap")]
+pub struct TileMap {
+    data: Arc<TileMapData>,
+    columns: usize,
+    rows: usize,
+    tile: Size2,
+}
+
+impl TileMap {
+    /// Returns a new `TileMap` with `columns` cells per row and a size of a single tile.
+    ///
+    /// Returns `None` if the map is empty or the number of cells isn't a multiple of `columns`.
+    pub fn new(cells: Vec<bool>, columns: usize, tile: Size2) -> Option<Self> {
+        if columns == 0 || cells.is_empty() || cells.len() % columns != 0 {
+            return None;
+        }
+        let rows = cells.len() / columns;
+
+        // greedily grow rectangles to the right first, then upwards
+        let mut owners = vec![u32::MAX; cells.len()];
+        let mut blocks = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let free = |c: usize, r: usize| {
+                    cells[r * columns + c] && owners[r * columns + c] == u32::MAX
+                };
+                if !free(column, row) {
+                    continue;
+                }
+
+                let mut width = 1;
+                while column + width < columns && free(column + width, row) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while row + height < rows && (column..column + width).all(|c| free(c, row + height))
+                {
+                    height += 1;
+                }
+
+                let index = blocks.len() as u32;
+                for r in row..row + height {
+                    for c in column..column + width {
+                        owners[r * columns + c] = index;
+                    }
+                }
+                blocks.push(Block {
+                    column,
+                    row,
+                    columns: width,
+                    rows: height,
+                });
+            }
+        }
+
+        let data = TileMapData {
+            cells,
+            blocks,
+            owners,
+        };
+        Some(Self {
+            data: Arc::new(data),
+            columns,
+            rows,
+            tile,
+        })
+    }
+
+    /// Get the number of columns.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Get the number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Get the size of a single tile.
+    pub fn tile(&self) -> Size2 {
+        self.tile
+    }
+
+    /// Returns true if the cell at a column and a row is solid.
+    ///
+    /// Cells outside of the map are empty.
+    pub fn is_solid(&self, column: isize, row: isize) -> bool {
+        if column < 0 || row < 0 || column as usize >= self.columns || row as usize >= self.rows {
+            return false;
+        }
+        self.data.cells[row as usize * self.columns + column as usize]
+    }
+
+    /// Returns the size of the whole map.
+    pub fn size(&self) -> Size2 {
+        Size2::new(
+            self.columns as f32 * self.tile.width,
+            self.rows as f32 * self.tile.height,
+        )
+    }
+
+    /// Returns the cell containing a point relative to the bottom left corner of the map.
+    pub(crate) fn cell(&self, point: Vec2) -> (isize, isize) {
+        (
+            (point.x() / self.tile.width).floor() as isize,
+            (point.y() / self.tile.height).floor() as isize,
+        )
+    }
+
+    /// Returns the blocks that intersect the box between `min` and `max`,
+    /// relative to the bottom left corner of the map.
+    pub(crate) fn blocks(&self, min: Vec2, max: Vec2) -> SmallVec<[Block; 8]> {
+        let (c0, r0) = self.cell(min);
+        let (c1, r1) = self.cell(max);
+        let c0 = c0.max(0);
+        let r0 = r0.max(0);
+        let c1 = c1.min(self.columns as isize - 1);
+        let r1 = r1.min(self.rows as isize - 1);
+
+        let mut indices = SmallVec::<[u32; 8]>::new();
+        for row in r0..=r1 {
+            for column in c0..=c1 {
+                let owner = self.data.owners[row as usize * self.columns + column as usize];
+                if owner != u32::MAX && !indices.contains(&owner) {
+                    indices.push(owner);
+                }
+            }
+        }
+        indices
+            .into_iter()
+            .map(|index| self.data.blocks[index as usize])
+            .collect()
+    }
+}
+
+impl fmt::Debug for TileMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TileMap")
+            .field("columns", &self.columns)
+            .field("rows", &self.rows)
+            .field("tile", &self.tile)
+            .field("blocks", &self.data.blocks.len())
+            .finish()
+    }
+}
+
+impl PartialEq for TileMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.columns == other.columns
+            && self.rows == other.rows
+            && self.tile == other.tile
+            && (Arc::ptr_eq(&self.data, &other.data) || self.data.cells == other.data.cells)
+    }
+}
+
+impl std::convert::TryFrom<RawTileMap> for TileMap {
+    type Error = &'static str;
+
+    fn try_from(raw: RawTileMap) -> Result<Self, Self::Error> {
+        if raw.cells.len() != raw.columns * raw.rows {
+            return Err("invalid tilemap");
+        }
+        Self::new(raw.cells, raw.columns, raw.tile).ok_or("invalid tilemap")
+    }
+}
+
+impl From<TileMap> for RawTileMap {
+    fn from(map: TileMap) -> Self {
+        Self {
+            cells: map.data.cells.clone(),
+            columns: map.columns,
+            rows: map.rows,
+            tile: map.tile,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(column: usize, row: usize, columns: usize, rows: usize) -> Block {
+        Block {
+            column,
+            row,
+            columns,
+            rows,
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_maps() {
+        let tile = Size2::new(1.0, 1.0);
+        assert!(TileMap::new(vec![true; 4], 0, tile).is_none());
+        assert!(TileMap::new(Vec::new(), 2, tile).is_none());
+        assert!(TileMap::new(vec![true; 5], 2, tile).is_none());
+    }
+
+    #[test]
+    fn merges_full_grid_into_one_block() {
+        let map = TileMap::new(vec![true; 6], 3, Size2::new(1.0, 1.0)).unwrap();
+        assert_eq!(map.rows(), 2);
+        assert_eq!(&map.data.blocks[..], &[block(0, 0, 3, 2)]);
+    }
+
+    #[test]
+    fn merges_rows_before_columns() {
+        #[rustfmt::skip]
+        let cells = vec![
+            true, true, true,
+            true, false, false,
+            true, false, true,
+        ];
+        let map = TileMap::new(cells, 3, Size2::new(1.0, 1.0)).unwrap();
+        assert_eq!(
+            &map.data.blocks[..],
+            &[block(0, 0, 3, 1), block(0, 1, 1, 2), block(2, 2, 1, 1)]
+        );
+        assert_eq!(map.data.owners[3], 1);
+        assert_eq!(map.data.owners[4], u32::MAX);
+    }
+
+    #[test]
+    fn returns_blocks_in_box() {
+        #[rustfmt::skip]
+        let cells = vec![
+            true, true, true,
+            true, false, false,
+            true, false, true,
+        ];
+        let map = TileMap::new(cells, 3, Size2::new(2.0, 2.0)).unwrap();
+        let blocks = map.blocks(Vec2::new(3.0, 3.0), Vec2::new(5.0, 5.0));
+        assert_eq!(&blocks[..], &[block(2, 2, 1, 1)]);
+        let blocks = map.blocks(Vec2::new(-10.0, -10.0), Vec2::new(1.0, 1.0));
+        assert_eq!(&blocks[..], &[block(0, 0, 3, 1)]);
+        let blocks = map.blocks(Vec2::new(2.5, 2.5), Vec2::new(3.5, 3.5));
+        assert!(blocks.is_empty());
+    }
+}
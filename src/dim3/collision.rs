@@ -2,6 +2,9 @@
 ///
 /// Inspired by Randy Gaul's qu3e engine
 /// [https://github.com/RandyGaul/qu3e/blob/master/src/collision/q3Collide.cpp](qu3e/q3Collide.cpp)
+use std::cmp::Ordering;
+use std::mem;
+
 use bevy::math::*;
 use bevy::prelude::*;
 use smallvec::{smallvec, SmallVec};
@@ -669,12 +672,14 @@ pub fn box_to_box(a: &Obb, b: &Obb) -> Option<Manifold> {
                 let contact = Contact {
                     position: v,
                     penetration: d,
+                    impulse: 0.0,
                 };
                 contacts.push(contact);
             }
             Some(Manifold {
                 body1: a.body,
                 body2: b.body,
+                shapes: smallvec![(a.shape, b.shape)],
                 normal,
                 penetration: smax,
                 contacts,
@@ -698,12 +703,1353 @@ pub fn box_to_box(a: &Obb, b: &Obb) -> Option<Manifold> {
         Some(Manifold {
             body1: a.body,
             body2: b.body,
+            shapes: smallvec![(a.shape, b.shape)],
             normal,
             penetration: smax,
             contacts: smallvec![Contact {
                 position: (ca + cb) * 0.5,
                 penetration: smax,
+                impulse: 0.0,
             }],
         })
     }
 }
+
+/// Returns the closest points between the segments `p1q1` and `p2q2`.
+fn closest_points_segments(p1: Vec3, q1: Vec3, p2: Vec3, q2: Vec3) -> (Vec3, Vec3) {
+    const EPS: f32 = 1.0e-6;
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.dot(d1);
+    let e = d2.dot(d2);
+    let f = d2.dot(r);
+
+    let (s, t) = if a <= EPS && e <= EPS {
+        (0.0, 0.0)
+    } else if a <= EPS {
+        (0.0, (f / e).max(0.0).min(1.0))
+    } else {
+        let c = d1.dot(r);
+        if e <= EPS {
+            ((-c / a).max(0.0).min(1.0), 0.0)
+        } else {
+            let b = d1.dot(d2);
+            let denom = a * e - b * b;
+            let mut s = if denom > EPS {
+                ((b * f - c * e) / denom).max(0.0).min(1.0)
+            } else {
+                0.0
+            };
+            let mut t = (b * s + f) / e;
+            if t < 0.0 {
+                t = 0.0;
+                s = (-c / a).max(0.0).min(1.0);
+            } else if t > 1.0 {
+                t = 1.0;
+                s = ((b - c) / a).max(0.0).min(1.0);
+            }
+            (s, t)
+        }
+    };
+
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+/// Returns the closest point on the segment `pq` to `x`.
+pub(super) fn closest_point_segment(p: Vec3, q: Vec3, x: Vec3) -> Vec3 {
+    let d = q - p;
+    let l = d.length_squared();
+    if l <= f32::EPSILON {
+        return p;
+    }
+    let t = ((x - p).dot(d) / l).max(0.0).min(1.0);
+    p + d * t
+}
+
+/// Returns the closest point on the triangle `abc` to `x`.
+fn closest_point_triangle(a: Vec3, b: Vec3, c: Vec3, x: Vec3) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ax = x - a;
+    let d1 = ab.dot(ax);
+    let d2 = ac.dot(ax);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bx = x - b;
+    let d3 = ab.dot(bx);
+    let d4 = ac.dot(bx);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cx = x - c;
+    let d5 = ab.dot(cx);
+    let d6 = ac.dot(cx);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = va + vb + vc;
+    if denom.abs() <= f32::EPSILON {
+        return a;
+    }
+    a + ab * (vb / denom) + ac * (vc / denom)
+}
+
+/// A round shape (a sphere or a capsule) as a world space segment with a radius.
+#[derive(Debug, Clone, Copy)]
+struct Round {
+    p: Vec3,
+    q: Vec3,
+    radius: f32,
+}
+
+/// A solid shape (a cuboid, a cylinder or a triangle of a terrain) in world space.
+#[derive(Debug, Clone, Copy)]
+enum Solid {
+    Cuboid {
+        tx: Transform,
+        e: Vec3,
+    },
+    Cylinder {
+        center: Vec3,
+        axis: Vec3,
+        half_height: f32,
+        radius: f32,
+    },
+    Triangle {
+        vertices: [Vec3; 3],
+    },
+}
+
+impl Solid {
+    fn center(&self) -> Vec3 {
+        match *self {
+            Solid::Cuboid { tx, .. } => tx.translation,
+            Solid::Cylinder { center, .. } => center,
+            Solid::Triangle {
+                vertices: [a, b, c],
+            } => (a + b + c) / 3.0,
+        }
+    }
+
+    /// Returns the face normals and the axes of symmetry of this solid.
+    ///
+    /// The edges of a triangle are included as well, so that their cross
+    /// products with the axes of other solids are tested.
+    fn axes(&self) -> SmallVec<[Vec3; 4]> {
+        match *self {
+            Solid::Cuboid { tx, .. } => smallvec![
+                tx.rotation * Vec3::unit_x(),
+                tx.rotation * Vec3::unit_y(),
+                tx.rotation * Vec3::unit_z(),
+            ],
+            Solid::Cylinder { axis, .. } => smallvec![axis],
+            Solid::Triangle {
+                vertices: [a, b, c],
+            } => {
+                smallvec![(b - a).cross(c - a), b - a, c - b, a - c]
+            }
+        }
+    }
+
+    /// Returns the furthest point of this solid in the direction `d`.
+    fn support(&self, d: Vec3) -> Vec3 {
+        match *self {
+            Solid::Cuboid { tx, e } => {
+                let l = tx.rotation.mult(d);
+                tx.rotation * (e * l.signum()) + tx.translation
+            }
+            Solid::Cylinder {
+                center,
+                axis,
+                half_height,
+                radius,
+            } => {
+                let y = d.dot(axis);
+                let radial = d - axis * y;
+                let mut s = center + axis * (half_height * y.signum());
+                if radial.length_squared() > f32::EPSILON {
+                    s += radial.normalize() * radius;
+                }
+                s
+            }
+            Solid::Triangle { vertices } => {
+                let mut best = vertices[0];
+                for &v in &vertices[1..] {
+                    if v.dot(d) > best.dot(d) {
+                        best = v;
+                    }
+                }
+                best
+            }
+        }
+    }
+
+    /// Returns the point of this solid closest to `x`, which is `x` itself if it's inside.
+    fn closest_point(&self, x: Vec3) -> Vec3 {
+        match *self {
+            Solid::Cuboid { tx, e } => {
+                let l = tx.mult(x);
+                let c = l.max(-e).min(e);
+                tx.rotation * c + tx.translation
+            }
+            Solid::Cylinder {
+                center,
+                axis,
+                half_height,
+                radius,
+            } => {
+                let d = x - center;
+                let y = d.dot(axis);
+                let mut radial = d - axis * y;
+                if radial.length_squared() > radius * radius {
+                    radial = radial.normalize() * radius;
+                }
+                center + axis * y.max(-half_height).min(half_height) + radial
+            }
+            Solid::Triangle {
+                vertices: [a, b, c],
+            } => closest_point_triangle(a, b, c, x),
+        }
+    }
+
+    /// Returns the direction and the length of the shortest way out of this solid for a point inside.
+    fn exit(&self, x: Vec3) -> (Vec3, f32) {
+        match *self {
+            Solid::Cuboid { tx, e } => {
+                let l = tx.mult(x);
+                let gap = e - l.abs();
+                let mut axis = 0;
+                for i in 1..3 {
+                    if gap[i] < gap[axis] {
+                        axis = i;
+                    }
+                }
+                let n = match axis {
+                    0 => Vec3::unit_x(),
+                    1 => Vec3::unit_y(),
+                    _ => Vec3::unit_z(),
+                };
+                (tx.rotation * (n * l[axis].signum()), gap[axis])
+            }
+            Solid::Cylinder {
+                center,
+                axis,
+                half_height,
+                radius,
+            } => {
+                let d = x - center;
+                let y = d.dot(axis);
+                let radial = d - axis * y;
+                let radial_length = radial.length();
+                let axial_gap = half_height - y.abs();
+                let radial_gap = radius - radial_length;
+                if axial_gap < radial_gap || radial_length <= f32::EPSILON {
+                    (axis * y.signum(), axial_gap)
+                } else {
+                    (radial / radial_length, radial_gap)
+                }
+            }
+            // a point can only lie on a triangle, so leave through its front face
+            Solid::Triangle {
+                vertices: [a, b, c],
+            } => {
+                let n = (b - a).cross(c - a);
+                if n.length_squared() > f32::EPSILON {
+                    (n.normalize(), 0.0)
+                } else {
+                    (Vec3::unit_y(), 0.0)
+                }
+            }
+        }
+    }
+}
+
+impl Round {
+    fn center(&self) -> Vec3 {
+        (self.p + self.q) * 0.5
+    }
+
+    /// Returns the furthest point of this round shape in the direction `d`.
+    fn support(&self, d: Vec3) -> Vec3 {
+        let s = if d.dot(self.q - self.p) > 0.0 {
+            self.q
+        } else {
+            self.p
+        };
+        if d.length_squared() > f32::EPSILON {
+            s + d.normalize() * self.radius
+        } else {
+            s
+        }
+    }
+}
+
+/// The convex hull of a set of points, along with its world space transform.
+#[derive(Debug, Clone, Copy)]
+struct Hull<'a> {
+    tx: Transform,
+    points: &'a [Vec3],
+}
+
+impl Hull<'_> {
+    /// Returns the furthest point of this hull in the direction `d`.
+    fn support(&self, d: Vec3) -> Vec3 {
+        let l = self.tx.rotation.conjugate() * d;
+        let mut best = self.points[0];
+        let mut best_projection = best.dot(l);
+        for &point in &self.points[1..] {
+            let projection = point.dot(l);
+            if projection > best_projection {
+                best = point;
+                best_projection = projection;
+            }
+        }
+        self.tx.rotation * best + self.tx.translation
+    }
+}
+
+/// The world space primitive a collider is tested as.
+enum Primitive<'a> {
+    Round(Round),
+    Solid(Solid),
+    Hull(Hull<'a>),
+}
+
+impl Primitive<'_> {
+    fn center(&self) -> Vec3 {
+        match self {
+            Primitive::Round(round) => round.center(),
+            Primitive::Solid(solid) => solid.center(),
+            Primitive::Hull(hull) => hull.tx.translation,
+        }
+    }
+
+    fn support(&self, d: Vec3) -> Vec3 {
+        match self {
+            Primitive::Round(round) => round.support(d),
+            Primitive::Solid(solid) => solid.support(d),
+            Primitive::Hull(hull) => hull.support(d),
+        }
+    }
+}
+
+fn primitive(obb: &Obb) -> Primitive<'_> {
+    let tx = obb.world();
+    let center = tx.translation;
+    let axis = tx.rotation * Vec3::unit_y();
+    match obb.geometry {
+        Geometry::Cuboid => Primitive::Solid(Solid::Cuboid { tx, e: obb.extent }),
+        Geometry::Sphere { radius } => Primitive::Round(Round {
+            p: center,
+            q: center,
+            radius,
+        }),
+        Geometry::Capsule {
+            half_height,
+            radius,
+        } => Primitive::Round(Round {
+            p: center - axis * half_height,
+            q: center + axis * half_height,
+            radius,
+        }),
+        Geometry::Cylinder {
+            half_height,
+            radius,
+        } => Primitive::Solid(Solid::Cylinder {
+            center,
+            axis,
+            half_height,
+            radius,
+        }),
+        Geometry::ConvexHull(ref hull) => Primitive::Hull(Hull {
+            tx,
+            points: hull.points(),
+        }),
+        Geometry::Terrain(_) => unreachable!("terrain is collided triangle by triangle"),
+    }
+}
+
+/// Collides two round shapes, i.e. spheres and capsules.
+fn round_to_round(a: &Obb, ra: Round, b: &Obb, rb: Round) -> Option<Manifold> {
+    let (ca, cb) = closest_points_segments(ra.p, ra.q, rb.p, rb.q);
+    let d = cb - ca;
+    let radius = ra.radius + rb.radius;
+    let distance_squared = d.length_squared();
+    if distance_squared >= radius * radius {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    let normal = if distance > f32::EPSILON {
+        d / distance
+    } else {
+        let t = (rb.p + rb.q) * 0.5 - (ra.p + ra.q) * 0.5;
+        if t.length_squared() > f32::EPSILON {
+            t.normalize()
+        } else {
+            Vec3::unit_y()
+        }
+    };
+    let penetration = distance - radius;
+    let position = ca + normal * (ra.radius + penetration * 0.5);
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        normal,
+        penetration,
+        contacts: smallvec![Contact {
+            position,
+            penetration,
+            impulse: 0.0,
+        }],
+    })
+}
+
+/// Collides a solid shape with a round shape.
+///
+/// The closest points are found by alternating projections between the
+/// segment of the round shape and the solid, which converges for convex shapes.
+fn solid_to_round(a: &Obb, solid: Solid, b: &Obb, round: Round) -> Option<Manifold> {
+    const ITERATIONS: usize = 16;
+
+    let mut x = (round.p + round.q) * 0.5;
+    let mut y = solid.closest_point(x);
+    for _ in 0..ITERATIONS {
+        x = closest_point_segment(round.p, round.q, y);
+        y = solid.closest_point(x);
+    }
+
+    let d = x - y;
+    let distance_squared = d.length_squared();
+    if distance_squared >= round.radius * round.radius {
+        return None;
+    }
+
+    let (normal, penetration, position) = if distance_squared > f32::EPSILON {
+        let distance = distance_squared.sqrt();
+        (d / distance, distance - round.radius, y)
+    } else {
+        // the segment is inside of the solid
+        let (normal, depth) = solid.exit(x);
+        (normal, -(depth + round.radius), x + normal * depth)
+    };
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        normal,
+        penetration,
+        contacts: smallvec![Contact {
+            position,
+            penetration,
+            impulse: 0.0,
+        }],
+    })
+}
+
+/// Collides two solid shapes using the separating axis test.
+///
+/// The tested axes are the face normals and axes of symmetry of both shapes,
+/// their cross products and the axis between the centers.  This is exact for
+/// flat faces and a close approximation for the curved side of a cylinder.
+fn solid_to_solid(a: &Obb, sa: Solid, b: &Obb, sb: Solid) -> Option<Manifold> {
+    let t = sb.center() - sa.center();
+
+    let axes_a = sa.axes();
+    let axes_b = sb.axes();
+    let mut axes = SmallVec::<[Vec3; 24]>::new();
+    axes.extend(axes_a.iter().copied());
+    axes.extend(axes_b.iter().copied());
+    for &u in &axes_a {
+        for &v in &axes_b {
+            axes.push(u.cross(v));
+        }
+    }
+    axes.push(t);
+
+    let mut best = f32::MAX;
+    let mut normal = Vec3::zero();
+    for axis in axes {
+        if axis.length_squared() <= f32::EPSILON {
+            continue;
+        }
+        let mut n = axis.normalize();
+        if n.dot(t) < 0.0 {
+            n = -n;
+        }
+        let overlap = sa.support(n).dot(n) - sb.support(-n).dot(n);
+        if overlap < 0.0 {
+            return None;
+        }
+        if overlap < best {
+            best = overlap;
+            normal = n;
+        }
+    }
+
+    if best == f32::MAX {
+        return None;
+    }
+
+    let penetration = -best;
+    let position = (sa.support(normal) + sb.support(-normal)) * 0.5;
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        normal,
+        penetration,
+        contacts: smallvec![Contact {
+            position,
+            penetration,
+            impulse: 0.0,
+        }],
+    })
+}
+
+/// A vertex of the Minkowski difference, along with the support points it was built from.
+#[derive(Debug, Clone, Copy)]
+struct SupportPoint {
+    v: Vec3,
+    a: Vec3,
+    b: Vec3,
+}
+
+fn minkowski_support(pa: &Primitive, pb: &Primitive, d: Vec3) -> SupportPoint {
+    let a = pa.support(d);
+    let b = pb.support(-d);
+    SupportPoint { v: a - b, a, b }
+}
+
+fn same_direction(a: Vec3, b: Vec3) -> bool {
+    a.dot(b) > 0.0
+}
+
+fn gjk_line(simplex: &mut SmallVec<[SupportPoint; 4]>, d: &mut Vec3) -> bool {
+    let a = simplex[0];
+    let b = simplex[1];
+    let ab = b.v - a.v;
+    let ao = -a.v;
+
+    if same_direction(ab, ao) {
+        *d = ab.cross(ao).cross(ab);
+    } else {
+        *simplex = smallvec![a];
+        *d = ao;
+    }
+
+    false
+}
+
+fn gjk_triangle(simplex: &mut SmallVec<[SupportPoint; 4]>, d: &mut Vec3) -> bool {
+    let a = simplex[0];
+    let b = simplex[1];
+    let c = simplex[2];
+    let ab = b.v - a.v;
+    let ac = c.v - a.v;
+    let ao = -a.v;
+    let abc = ab.cross(ac);
+
+    if same_direction(abc.cross(ac), ao) {
+        if same_direction(ac, ao) {
+            *simplex = smallvec![a, c];
+            *d = ac.cross(ao).cross(ac);
+        } else {
+            *simplex = smallvec![a, b];
+            return gjk_line(simplex, d);
+        }
+    } else if same_direction(ab.cross(abc), ao) {
+        *simplex = smallvec![a, b];
+        return gjk_line(simplex, d);
+    } else if same_direction(abc, ao) {
+        *d = abc;
+    } else {
+        *simplex = smallvec![a, c, b];
+        *d = -abc;
+    }
+
+    false
+}
+
+fn gjk_tetrahedron(simplex: &mut SmallVec<[SupportPoint; 4]>, d: &mut Vec3) -> bool {
+    let a = simplex[0];
+    let b = simplex[1];
+    let c = simplex[2];
+    let e = simplex[3];
+    let ab = b.v - a.v;
+    let ac = c.v - a.v;
+    let ae = e.v - a.v;
+    let ao = -a.v;
+
+    if same_direction(ab.cross(ac), ao) {
+        *simplex = smallvec![a, b, c];
+        return gjk_triangle(simplex, d);
+    }
+
+    if same_direction(ac.cross(ae), ao) {
+        *simplex = smallvec![a, c, e];
+        return gjk_triangle(simplex, d);
+    }
+
+    if same_direction(ae.cross(ab), ao) {
+        *simplex = smallvec![a, e, b];
+        return gjk_triangle(simplex, d);
+    }
+
+    true
+}
+
+/// Runs the GJK algorithm on the Minkowski difference of two shapes.
+///
+/// Returns a tetrahedron enclosing the origin if the shapes intersect.
+/// The newest point of the simplex is always the first one.
+fn gjk(pa: &Primitive, pb: &Primitive) -> Option<[SupportPoint; 4]> {
+    const MAX_ITERATIONS: usize = 64;
+
+    let mut d = pb.center() - pa.center();
+    if d.length_squared() <= f32::EPSILON {
+        d = Vec3::unit_x();
+    }
+
+    let mut simplex: SmallVec<[SupportPoint; 4]> = smallvec![minkowski_support(pa, pb, d)];
+    d = -simplex[0].v;
+
+    for _ in 0..MAX_ITERATIONS {
+        // the origin is on the boundary of the simplex, the shapes are only touching
+        if d.length_squared() <= f32::EPSILON {
+            return None;
+        }
+
+        let p = minkowski_support(pa, pb, d);
+        if p.v.dot(d) <= 0.0 {
+            return None;
+        }
+        simplex.insert(0, p);
+
+        let enclosed = match simplex.len() {
+            2 => gjk_line(&mut simplex, &mut d),
+            3 => gjk_triangle(&mut simplex, &mut d),
+            _ => gjk_tetrahedron(&mut simplex, &mut d),
+        };
+        if enclosed {
+            return Some([simplex[0], simplex[1], simplex[2], simplex[3]]);
+        }
+    }
+
+    None
+}
+
+/// Returns the outward normal of a face of the polytope and its distance to the origin.
+fn face_normal(polytope: &[SupportPoint], face: [usize; 3]) -> (Vec3, f32) {
+    let a = polytope[face[0]].v;
+    let b = polytope[face[1]].v;
+    let c = polytope[face[2]].v;
+    let n = (b - a).cross(c - a);
+    let length = n.length();
+    if length <= f32::EPSILON {
+        // a degenerate face is never the closest one
+        return (Vec3::unit_y(), f32::MAX);
+    }
+    let n = n / length;
+    (n, n.dot(a))
+}
+
+/// Returns the barycentric coordinates of `p` in the triangle `abc`.
+fn barycentric(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> (f32, f32, f32) {
+    let v0 = b - a;
+    let v1 = c - a;
+    let v2 = p - a;
+    let d00 = v0.dot(v0);
+    let d01 = v0.dot(v1);
+    let d11 = v1.dot(v1);
+    let d20 = v2.dot(v0);
+    let d21 = v2.dot(v1);
+    let denom = d00 * d11 - d01 * d01;
+    if denom.abs() <= f32::EPSILON {
+        return (1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0);
+    }
+    let v = (d11 * d20 - d01 * d21) / denom;
+    let w = (d00 * d21 - d01 * d20) / denom;
+    (1.0 - v - w, v, w)
+}
+
+/// Runs the expanding polytope algorithm on the tetrahedron found by `gjk`.
+///
+/// Returns the penetration normal, pointing from `pa` to `pb`, the penetration depth
+/// and the contact point, interpolated between the closest features of both shapes.
+fn epa(pa: &Primitive, pb: &Primitive, simplex: [SupportPoint; 4]) -> (Vec3, f32, Vec3) {
+    const MAX_ITERATIONS: usize = 64;
+    const TOLERANCE: f32 = 1.0e-4;
+
+    let mut polytope = simplex.to_vec();
+    let mut faces = vec![[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]];
+    let mut normals = Vec::with_capacity(faces.len());
+    for face in &mut faces {
+        let (n, distance) = face_normal(&polytope, *face);
+        if distance < 0.0 {
+            face.swap(1, 2);
+            normals.push((-n, -distance));
+        } else {
+            normals.push((n, distance));
+        }
+    }
+
+    let mut closest = 0;
+    for _ in 0..MAX_ITERATIONS {
+        closest = 0;
+        for i in 1..normals.len() {
+            if normals[i].1 < normals[closest].1 {
+                closest = i;
+            }
+        }
+        let (normal, distance) = normals[closest];
+
+        let support = minkowski_support(pa, pb, normal);
+        if normal.dot(support.v) - distance <= TOLERANCE {
+            break;
+        }
+
+        // remove all faces that can see the new point and keep the edges of the hole
+        let mut edges: Vec<(usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < faces.len() {
+            let face = faces[i];
+            if normals[i].0.dot(support.v - polytope[face[0]].v) > 0.0 {
+                for &(x, y) in &[(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+                    if let Some(position) = edges.iter().position(|&(ex, ey)| ex == y && ey == x) {
+                        edges.swap_remove(position);
+                    } else {
+                        edges.push((x, y));
+                    }
+                }
+                faces.swap_remove(i);
+                normals.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        if edges.is_empty() {
+            break;
+        }
+
+        let index = polytope.len();
+        polytope.push(support);
+        for (x, y) in edges {
+            let face = [x, y, index];
+            faces.push(face);
+            normals.push(face_normal(&polytope, face));
+        }
+    }
+
+    let face = faces[closest];
+    let (normal, distance) = normals[closest];
+    let a = polytope[face[0]];
+    let b = polytope[face[1]];
+    let c = polytope[face[2]];
+    let (u, v, w) = barycentric(normal * distance, a.v, b.v, c.v);
+    let contact_a = a.a * u + b.a * v + c.a * w;
+    let contact_b = a.b * u + b.b * v + c.b * w;
+
+    (normal, distance, (contact_a + contact_b) * 0.5)
+}
+
+/// Collides two shapes of any geometry using GJK and EPA on their support mappings.
+fn gjk_epa(a: &Obb, pa: &Primitive, b: &Obb, pb: &Primitive) -> Option<Manifold> {
+    let simplex = gjk(pa, pb)?;
+    let (normal, depth, position) = epa(pa, pb, simplex);
+    let penetration = -depth;
+
+    Some(Manifold {
+        body1: a.body,
+        body2: b.body,
+        shapes: smallvec![(a.shape, b.shape)],
+        normal,
+        penetration,
+        contacts: smallvec![Contact {
+            position,
+            penetration,
+            impulse: 0.0,
+        }],
+    })
+}
+
+/// Returns the world space triangles of a terrain collider that intersect the bounding
+/// box of world space `points`.
+fn terrain_triangles(a: &Obb, terrain: &Terrain, points: &[Vec3]) -> Vec<Solid> {
+    let tx = a.world();
+    let mut min = Vec3::splat(f32::INFINITY);
+    let mut max = Vec3::splat(f32::NEG_INFINITY);
+    for &point in points {
+        let l = tx.mult(point);
+        min = min.min(l);
+        max = max.max(l);
+    }
+    terrain
+        .triangles(min, max)
+        .into_iter()
+        .map(|[p, q, r]| Solid::Triangle {
+            vertices: [
+                tx.rotation * p + tx.translation,
+                tx.rotation * q + tx.translation,
+                tx.rotation * r + tx.translation,
+            ],
+        })
+        .collect()
+}
+
+/// Collides a static terrain with a collider of any other geometry.
+///
+/// Every triangle close to `b` is collided on its own.  The resulting manifold
+/// has the normal and penetration of the deepest triangle and the contacts of all of them.
+fn terrain_to_primitive(a: &Obb, terrain: &Terrain, b: &Obb) -> Option<Manifold> {
+    let corners = [
+        b.v0(),
+        b.v1(),
+        b.v2(),
+        b.v3(),
+        b.v4(),
+        b.v5(),
+        b.v6(),
+        b.v7(),
+    ];
+
+    let pb = primitive(b);
+    let mut result: Option<Manifold> = None;
+    for triangle in terrain_triangles(a, terrain, &corners) {
+        let manifold = match &pb {
+            Primitive::Round(round) => solid_to_round(a, triangle, b, *round),
+            Primitive::Solid(solid) => {
+                solid_to_solid(a, triangle, b, *solid).map(|mut manifold| {
+                    // the support of a triangle along its normal is any of its vertices,
+                    // so the contact is taken from the deepest point of the other solid
+                    let depth = -manifold.penetration;
+                    let position = solid.support(-manifold.normal) + manifold.normal * depth * 0.5;
+                    manifold.contacts = smallvec![Contact {
+                        position,
+                        penetration: manifold.penetration,
+                        impulse: 0.0,
+                    }];
+                    manifold
+                })
+            }
+            Primitive::Hull(_) => gjk_epa(a, &Primitive::Solid(triangle), b, &pb),
+        };
+        if let Some(manifold) = manifold {
+            result = Some(merge(result, manifold));
+        }
+    }
+    result
+}
+
+/// Merges a manifold into another one between the same bodies.
+///
+/// The merged manifold has the normal and penetration of the deeper one and the
+/// contacts and pairs of shapes of both, with the pairs of the deeper one first.
+pub fn merge(deepest: Option<Manifold>, manifold: Manifold) -> Manifold {
+    match deepest {
+        None => manifold,
+        Some(mut deepest) => {
+            let mut shapes = manifold.shapes;
+            if manifold.penetration < deepest.penetration {
+                deepest.normal = manifold.normal;
+                deepest.penetration = manifold.penetration;
+                // the pairs of shapes of the deeper manifold come first
+                mem::swap(&mut deepest.shapes, &mut shapes);
+            }
+            for pair in shapes {
+                if !deepest.shapes.contains(&pair) {
+                    deepest.shapes.push(pair);
+                }
+            }
+            deepest.contacts.extend(manifold.contacts);
+            deepest
+        }
+    }
+}
+
+/// Flips a manifold, so that the bodies and the normal are swapped.
+fn flip(manifold: Manifold) -> Manifold {
+    Manifold {
+        body1: manifold.body2,
+        body2: manifold.body1,
+        shapes: manifold.shapes.iter().map(|&(s1, s2)| (s2, s1)).collect(),
+        normal: -manifold.normal,
+        ..manifold
+    }
+}
+
+/// Collides two colliders of any geometry.
+///
+/// The normal of the resulting manifold always points from `a` to `b`.
+pub fn collide(a: &Obb, b: &Obb) -> Option<Manifold> {
+    match (&a.geometry, &b.geometry) {
+        (Geometry::Cuboid, Geometry::Cuboid) => return box_to_box(a, b),
+        (Geometry::Terrain(_), Geometry::Terrain(_)) => return None,
+        (Geometry::Terrain(terrain), _) => return terrain_to_primitive(a, terrain, b),
+        (_, Geometry::Terrain(terrain)) => return terrain_to_primitive(b, terrain, a).map(flip),
+        _ => {}
+    }
+
+    let pa = primitive(a);
+    let pb = primitive(b);
+    match (&pa, &pb) {
+        (Primitive::Hull(_), _) | (_, Primitive::Hull(_)) => gjk_epa(a, &pa, b, &pb),
+        (Primitive::Round(ra), Primitive::Round(rb)) => round_to_round(a, *ra, b, *rb),
+        (Primitive::Solid(sa), Primitive::Round(rb)) => solid_to_round(a, *sa, b, *rb),
+        (Primitive::Round(ra), Primitive::Solid(sb)) => solid_to_round(b, *sb, a, *ra).map(flip),
+        (Primitive::Solid(sa), Primitive::Solid(sb)) => solid_to_solid(a, *sa, b, *sb),
+    }
+}
+
+/// Tests whether two colliders overlap, without building a manifold.
+///
+/// Round shapes only compare the distance between their segments and all
+/// other primitives only run GJK, terrains run GJK on the triangles that touch
+/// the other collider.
+pub fn overlaps(a: &Obb, b: &Obb) -> bool {
+    if matches!(a.geometry, Geometry::Terrain(_)) || matches!(b.geometry, Geometry::Terrain(_)) {
+        return distance(a, (b.min(), b.max()), b, (a.min(), a.max())).is_none();
+    }
+
+    let pa = primitive(a);
+    let pb = primitive(b);
+    match (&pa, &pb) {
+        (Primitive::Round(ra), Primitive::Round(rb)) => {
+            let (ca, cb) = closest_points_segments(ra.p, ra.q, rb.p, rb.q);
+            let radius = ra.radius + rb.radius;
+            (cb - ca).length_squared() < radius * radius
+        }
+        _ => gjk(&pa, &pb).is_some(),
+    }
+}
+
+/// Returns half of the smallest side of the bounding boxes of two colliders, the relative
+/// motion up to which the discrete test keeps them from passing through each other.
+pub(super) fn sweep_step(a: &Obb, b: &Obb) -> f32 {
+    let size = (a.max() - a.min()).min(b.max() - b.min());
+    size.x().min(size.y()).min(size.z()) * 0.5
+}
+
+/// Splits a collider into convex primitives.
+///
+/// Terrains only return the triangles that intersect the world space box between `min` and `max`.
+fn convex_pieces(obb: &Obb, min: Vec3, max: Vec3) -> SmallVec<[Primitive<'_>; 4]> {
+    match obb.geometry {
+        Geometry::Terrain(ref terrain) => {
+            let corners = [
+                min,
+                Vec3::new(max.x(), min.y(), min.z()),
+                Vec3::new(min.x(), max.y(), min.z()),
+                Vec3::new(min.x(), min.y(), max.z()),
+                Vec3::new(max.x(), max.y(), min.z()),
+                Vec3::new(max.x(), min.y(), max.z()),
+                Vec3::new(min.x(), max.y(), max.z()),
+                max,
+            ];
+            terrain_triangles(obb, terrain, &corners)
+                .into_iter()
+                .map(Primitive::Solid)
+                .collect()
+        }
+        _ => smallvec![primitive(obb)],
+    }
+}
+
+/// Returns the point of the segment `pq` closest to the origin, along with the
+/// vertices of the segment needed to describe it.
+fn closest_to_origin_segment(p: Vec3, q: Vec3) -> (Vec3, SmallVec<[Vec3; 4]>) {
+    let d = q - p;
+    let l = d.length_squared();
+    let t = if l <= f32::EPSILON {
+        0.0
+    } else {
+        -p.dot(d) / l
+    };
+    if t <= 0.0 {
+        (p, smallvec![p])
+    } else if t >= 1.0 {
+        (q, smallvec![q])
+    } else {
+        (p + d * t, smallvec![p, q])
+    }
+}
+
+/// Returns the point of the triangle `abc` closest to the origin, along with the
+/// vertices of the triangle needed to describe it.
+fn closest_to_origin_triangle(a: Vec3, b: Vec3, c: Vec3) -> (Vec3, SmallVec<[Vec3; 4]>) {
+    let edges = [(a, b), (b, c), (c, a)];
+    let n = (b - a).cross(c - a);
+    if n.length_squared() > f32::EPSILON {
+        let p = n * (a.dot(n) / n.length_squared());
+        if edges
+            .iter()
+            .all(|&(u, v)| (v - u).cross(p - u).dot(n) >= 0.0)
+        {
+            return (p, smallvec![a, b, c]);
+        }
+    }
+    closest_to_origin_of(edges.iter().map(|&(u, v)| closest_to_origin_segment(u, v)))
+}
+
+/// Returns the candidate closest to the origin.
+fn closest_to_origin_of(
+    candidates: impl Iterator<Item = (Vec3, SmallVec<[Vec3; 4]>)>,
+) -> (Vec3, SmallVec<[Vec3; 4]>) {
+    candidates
+        .min_by(|(u, _), (v, _)| {
+            u.length_squared()
+                .partial_cmp(&v.length_squared())
+                .unwrap_or(Ordering::Equal)
+        })
+        .expect("a simplex has at least one feature")
+}
+
+/// Returns the point of a simplex closest to the origin, along with the vertices of the
+/// simplex needed to describe it, or `None` if the simplex contains the origin.
+fn closest_to_origin(simplex: &[Vec3]) -> Option<(Vec3, SmallVec<[Vec3; 4]>)> {
+    match *simplex {
+        [p] => Some((p, smallvec![p])),
+        [p, q] => Some(closest_to_origin_segment(p, q)),
+        [a, b, c] => Some(closest_to_origin_triangle(a, b, c)),
+        [a, b, c, d] => {
+            let faces = [(a, b, c, d), (a, c, d, b), (a, d, b, c), (b, d, c, a)];
+            let volume = (b - a).cross(c - a).dot(d - a);
+            if volume.abs() > f32::EPSILON
+                && faces.iter().all(|&(p, q, r, opposite)| {
+                    let n = (q - p).cross(r - p);
+                    n.dot(-p) * n.dot(opposite - p) >= 0.0
+                })
+            {
+                return None;
+            }
+            Some(closest_to_origin_of(
+                faces
+                    .iter()
+                    .map(|&(p, q, r, _)| closest_to_origin_triangle(p, q, r)),
+            ))
+        }
+        _ => unreachable!("a simplex in 3d has at most four vertices"),
+    }
+}
+
+/// Returns a lower bound of the distance between two convex primitives and the direction
+/// from `pa` to `pb` it is measured along, or `None` if they overlap.
+///
+/// Runs the GJK distance algorithm on the Minkowski difference of the primitives.  The
+/// bound is the separation along the current direction, so it never exceeds the actual
+/// distance, even if the algorithm stops before it converges.
+fn primitive_distance(pa: &Primitive, pb: &Primitive) -> Option<(f32, Vec3)> {
+    const MAX_ITERATIONS: usize = 32;
+    const TOLERANCE: f32 = 1.0e-6;
+
+    let mut closest = pb.center() - pa.center();
+    let mut simplex: SmallVec<[Vec3; 4]> = SmallVec::new();
+    let mut separation = (0.0, Vec3::zero());
+    for _ in 0..MAX_ITERATIONS {
+        let distance = closest.length_squared();
+        if distance <= TOLERANCE * TOLERANCE {
+            return None;
+        }
+        let w = pb.support(-closest) - pa.support(closest);
+        // the difference is at least this far from the origin along `closest`
+        let normal = closest / distance.sqrt();
+        separation = (normal.dot(w), normal);
+        // no point of the difference is closer to the origin than the simplex
+        if !simplex.is_empty() && distance - closest.dot(w) <= distance * TOLERANCE {
+            break;
+        }
+        simplex.push(w);
+        let (point, reduced) = closest_to_origin(&simplex)?;
+        if simplex.len() > 1 && point.length_squared() >= distance {
+            break;
+        }
+        closest = point;
+        simplex = reduced;
+    }
+
+    let (separation, normal) = separation;
+    if separation <= 0.0 {
+        return None;
+    }
+    Some((separation, normal))
+}
+
+/// Returns a lower bound of the distance between two colliders and the direction from `a`
+/// to `b` it is measured along, or `None` if they overlap.
+///
+/// Terrains only take their triangles in `a_box` and `b_box` into account, the distance
+/// is infinite if there are none.
+fn distance(a: &Obb, a_box: (Vec3, Vec3), b: &Obb, b_box: (Vec3, Vec3)) -> Option<(f32, Vec3)> {
+    let pieces_a = convex_pieces(a, a_box.0, a_box.1);
+    let pieces_b = convex_pieces(b, b_box.0, b_box.1);
+    let mut result = (f32::INFINITY, Vec3::zero());
+    for piece_a in &pieces_a {
+        for piece_b in &pieces_b {
+            let (distance, normal) = primitive_distance(piece_a, piece_b)?;
+            if distance < result.0 {
+                result = (distance, normal);
+            }
+        }
+    }
+    Some(result)
+}
+
+/// Finds the first time in `0.0..=1.0` at which `at` returns a manifold, if the
+/// colliders are apart at time zero.
+///
+/// `moving` returns the first collider at a time of the sweep, during which it translates
+/// by `linear` and none of its points moves further than `angular` because of rotation,
+/// while `other` stays in place.  `at` collides both colliders at a time.
+///
+/// Uses conservative advancement: each iteration moves to the earliest time at which
+/// the colliders could touch, given their distance and how fast they approach each other.
+/// The sweep is never sampled, so it can't step over thin colliders, however far they move.
+pub(super) fn sweep(
+    moving: impl Fn(f32) -> Obb,
+    other: &Obb,
+    linear: Vec3,
+    angular: f32,
+    at: impl Fn(f32) -> Option<Manifold>,
+) -> Option<(f32, Manifold)> {
+    // a guard against slow convergence, each iteration advances the sweep
+    const MAX_ITERATIONS: usize = 64;
+    // how close the colliders get before they are considered touching, relative to their size
+    const TOLERANCE: f32 = 0.01;
+
+    let start = moving(0.0);
+    let end = moving(1.0);
+    let tolerance = sweep_step(&start, other) * TOLERANCE;
+
+    // the terrain triangles that can be touched lie on the way of the other collider
+    let reach = Vec3::splat(angular);
+    let swept = (
+        start.min().min(end.min()) - reach,
+        start.max().max(end.max()) + reach,
+    );
+    let (min, max) = (other.min(), other.max());
+    let passed = (min.min(min - linear) - reach, max.max(max - linear) + reach);
+
+    let mut time = 0.0;
+    for _ in 0..MAX_ITERATIONS {
+        let (distance, normal) = match distance(&moving(time), passed, other, swept) {
+            Some(separation) => separation,
+            None => break,
+        };
+        if distance <= tolerance {
+            break;
+        }
+        let approach = linear.dot(normal) + angular;
+        if approach <= 0.0 {
+            return None;
+        }
+        time += distance / approach;
+        if time > 1.0 {
+            return None;
+        }
+    }
+
+    // the colliders touch at `time`, a little later they overlap enough for a manifold
+    let speed = linear.length() + angular;
+    let time = (time + 2.0 * tolerance / speed).min(1.0);
+    at(time).map(|manifold| (time, manifold))
+}
+
+/// Finds the first time during the last step at which two swept colliders touch.
+///
+/// Both colliders are moved back along their motion, only their positions are swept.
+/// Returns the time in `0.0..=1.0` and the manifold at that time, or `None` if the
+/// colliders already touched at the start of the step, never touched, or didn't approach
+/// each other by more than half of the smallest collider, which the discrete test handles.
+pub fn time_of_impact(a: &Obb, b: &Obb) -> Option<(f32, Manifold)> {
+    let relative = a.motion - b.motion;
+    let step = sweep_step(a, b);
+    if relative.length() <= step {
+        return None;
+    }
+
+    let (start_a, start_b) = (a.rewound(0.0), b.rewound(0.0));
+    if collide(&start_a, &start_b).is_some() {
+        return None;
+    }
+
+    // relative to `b`, `a` moves along the difference of their motions
+    let moving = |time: f32| {
+        let mut obb = start_a.clone();
+        obb.transform.translation += relative * time;
+        obb
+    };
+    let at = |time: f32| collide(&a.rewound(time), &b.rewound(time));
+    let (time, manifold) = sweep(moving, &start_b, relative, 0.0, at)?;
+    if relative.dot(manifold.normal) <= step {
+        return None;
+    }
+    Some((time, manifold))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collider(id: u32, shape: Shape, position: Vec3) -> Obb {
+        Obb::new(
+            Status::Static,
+            Entity::from_id(id),
+            Entity::from_id(id + 100),
+            Transform::from_matrix(shape.local),
+            Transform::from_translation(position),
+            shape.extent(),
+            shape.geometry(),
+            CollisionLayers::default(),
+        )
+    }
+
+    fn assert_manifold(a: Shape, b: Shape, offset: Vec3, normal: Vec3, penetration: f32) {
+        let a = collider(1, a, Vec3::zero());
+        let b = collider(2, b, offset);
+        let manifold = collide(&a, &b).expect("the shapes should collide");
+        assert_eq!(manifold.body1, a.body);
+        assert_eq!(manifold.body2, b.body);
+        assert_eq!(manifold.shapes[0], (a.shape, b.shape));
+        assert!(
+            (manifold.normal - normal).length() < 1.0e-3,
+            "normal {:?}, expected {:?}",
+            manifold.normal,
+            normal
+        );
+        assert!(
+            (manifold.penetration - penetration).abs() < 1.0e-3,
+            "penetration {}, expected {}",
+            manifold.penetration,
+            penetration
+        );
+        assert!(!manifold.contacts.is_empty());
+    }
+
+    fn cube() -> Shape {
+        Shape::new(Size3::new(2.0, 2.0, 2.0))
+    }
+
+    fn hull() -> Shape {
+        let mut points = Vec::new();
+        for &x in &[-1.0, 1.0] {
+            for &y in &[-1.0, 1.0] {
+                for &z in &[-1.0, 1.0] {
+                    points.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+        Shape::convex_hull(&points).unwrap()
+    }
+
+    #[test]
+    fn box_to_box() {
+        let offset = Vec3::new(1.5, 0.0, 0.0);
+        assert_manifold(cube(), cube(), offset, Vec3::unit_x(), -0.5);
+        assert_manifold(cube(), cube(), -offset, -Vec3::unit_x(), -0.5);
+        let a = collider(1, cube(), Vec3::zero());
+        let b = collider(2, cube(), Vec3::new(2.5, 0.0, 0.0));
+        assert!(collide(&a, &b).is_none());
+    }
+
+    #[test]
+    fn solid_to_round() {
+        let offset = Vec3::new(1.5, 0.0, 0.0);
+        let normal = Vec3::unit_x();
+        let sphere = Shape::sphere(1.0);
+        let capsule = Shape::capsule(2.0, 1.0);
+        assert_manifold(cube(), sphere.clone(), offset, normal, -0.5);
+        assert_manifold(sphere, cube(), offset, normal, -0.5);
+        assert_manifold(cube(), capsule.clone(), offset, normal, -0.5);
+        assert_manifold(capsule, cube(), offset, normal, -0.5);
+    }
+
+    #[test]
+    fn round_to_round() {
+        let offset = Vec3::new(1.5, 0.0, 0.0);
+        let normal = Vec3::unit_x();
+        let sphere = Shape::sphere(1.0);
+        let capsule = Shape::capsule(2.0, 1.0);
+        assert_manifold(sphere.clone(), sphere.clone(), offset, normal, -0.5);
+        assert_manifold(sphere.clone(), capsule.clone(), offset, normal, -0.5);
+        assert_manifold(capsule.clone(), sphere, offset, normal, -0.5);
+        assert_manifold(capsule.clone(), capsule, offset, normal, -0.5);
+    }
+
+    #[test]
+    fn solid_to_solid() {
+        let offset = Vec3::new(1.5, 0.0, 0.0);
+        let normal = Vec3::unit_x();
+        let cylinder = Shape::cylinder(2.0, 1.0);
+        assert_manifold(cube(), cylinder.clone(), offset, normal, -0.5);
+        assert_manifold(cylinder.clone(), cube(), offset, normal, -0.5);
+        assert_manifold(cylinder.clone(), cylinder, offset, normal, -0.5);
+    }
+
+    #[test]
+    fn hull_to_other() {
+        let offset = Vec3::new(1.5, 0.0, 0.0);
+        let normal = Vec3::unit_x();
+        assert_manifold(hull(), cube(), offset, normal, -0.5);
+        assert_manifold(cube(), hull(), offset, normal, -0.5);
+        assert_manifold(hull(), Shape::sphere(1.0), offset, normal, -0.5);
+        assert_manifold(hull(), hull(), offset, normal, -0.5);
+    }
+
+    #[test]
+    fn terrain_to_other() {
+        let offset = Vec3::new(0.3, 0.5, 0.2);
+        let normal = Vec3::unit_y();
+        let field = HeightField::new(vec![0.0; 9], 3, 3, Vec3::new(2.0, 1.0, 2.0)).unwrap();
+        let heightfield = Shape::heightfield(field);
+        assert_manifold(
+            heightfield.clone(),
+            Shape::sphere(1.0),
+            offset,
+            normal,
+            -0.5,
+        );
+        assert_manifold(Shape::sphere(1.0), heightfield, -offset, -normal, -0.5);
+
+        let vertices = vec![
+            Vec3::new(-2.0, 0.0, -2.0),
+            Vec3::new(2.0, 0.0, -2.0),
+            Vec3::new(2.0, 0.0, 2.0),
+            Vec3::new(-2.0, 0.0, 2.0),
+        ];
+        let mesh = TriMesh::new(vertices, vec![[0, 2, 1], [0, 3, 2]]).unwrap();
+        let trimesh = Shape::trimesh(mesh);
+        assert_manifold(trimesh.clone(), Shape::sphere(1.0), offset, normal, -0.5);
+        assert_manifold(
+            trimesh,
+            Shape::capsule(1.0, 1.0),
+            offset + Vec3::new(0.0, 0.5, 0.0),
+            normal,
+            -0.5,
+        );
+    }
+}
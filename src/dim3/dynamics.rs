@@ -0,0 +1,316 @@
+//! An iterative impulse solver for dynamic rigid bodies.
+//!
+//! Every manifold with a dynamic body becomes a contact constraint.  The
+//! velocities of all bodies in these constraints are solved together with
+//! accumulated impulses at every contact point, including restitution and
+//! friction, and the remaining penetration is corrected afterwards.
+
+use std::f32::consts::PI;
+
+use bevy::math::*;
+use bevy::prelude::*;
+use hashbrown::HashMap;
+use smallvec::SmallVec;
+
+use super::{Manifold, RigidBody, Up};
+use crate::common::Status;
+
+/// The number of velocity iterations per step.
+const ITERATIONS: usize = 10;
+/// The fraction of the penetration that is corrected every step.
+const CORRECTION: f32 = 0.8;
+/// The penetration that is left uncorrected to keep resting contacts stable.
+const SLOP: f32 = 0.01;
+
+struct Body {
+    entity: Entity,
+    linvel: Vec3,
+    /// The angular velocity in world space.
+    angvel: Vec3,
+    inv_mass: f32,
+    /// The inverse inertia tensor in world space.
+    inv_inertia: Mat3,
+    correction: Vec3,
+}
+
+impl Body {
+    fn new(entity: Entity, body: &RigidBody) -> Self {
+        // semikinematic bodies push with their mass, but never rotate
+        let (inv_mass, inv_inertia) = match body.status {
+            Status::Static | Status::Kinematic => (0.0, Mat3::zero()),
+            Status::Semikinematic => (body.inv_mass, Mat3::zero()),
+            Status::Dynamic => {
+                let rotation = Mat3::from_quat(body.rotation);
+                let inv_inertia = rotation * body.inv_inertia * rotation.transpose();
+                (body.inv_mass, inv_inertia)
+            }
+        };
+        // the angular velocity of a rigid body is a rotation in local space
+        let (axis, angle) = body.angvel.to_axis_angle();
+        Self {
+            entity,
+            linvel: body.linvel,
+            angvel: body.rotation * (axis * angle),
+            inv_mass,
+            inv_inertia,
+            correction: Vec3::zero(),
+        }
+    }
+
+    fn velocity_at(&self, r: Vec3) -> Vec3 {
+        self.linvel + self.angvel.cross(r)
+    }
+
+    fn apply_impulse(&mut self, r: Vec3, impulse: Vec3) {
+        self.linvel += impulse * self.inv_mass;
+        self.angvel += self.inv_inertia * r.cross(impulse);
+    }
+
+    fn effective_mass(&self, r: Vec3, axis: Vec3) -> f32 {
+        let rn = r.cross(axis);
+        self.inv_mass + (self.inv_inertia * rn).dot(rn)
+    }
+}
+
+struct Point {
+    r1: Vec3,
+    r2: Vec3,
+    normal_mass: f32,
+    tangent_mass: [f32; 2],
+    bounce: f32,
+    normal_impulse: f32,
+    tangent_impulse: [f32; 2],
+}
+
+struct Constraint {
+    manifold: usize,
+    body1: usize,
+    body2: usize,
+    normal: Vec3,
+    tangents: [Vec3; 2],
+    penetration: f32,
+    friction: f32,
+    points: SmallVec<[Point; 8]>,
+}
+
+fn pair(bodies: &mut [Body], a: usize, b: usize) -> (&mut Body, &mut Body) {
+    if a < b {
+        let (left, right) = bodies.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = bodies.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+/// Returns two unit vectors perpendicular to `normal` and to each other.
+fn tangents(normal: Vec3) -> [Vec3; 2] {
+    let axis = if normal.x().abs() < 0.57 {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_y()
+    };
+    let t1 = normal.cross(axis).normalize();
+    let t2 = normal.cross(t1);
+    [t1, t2]
+}
+
+/// Solves all manifolds that contain a dynamic body.
+///
+/// Contacts approaching slower than `resting` don't bounce.  The normal impulse
+/// applied at every point is written back into the contacts of the manifolds.
+pub(super) fn solve(
+    manifolds: &mut [Manifold],
+    query: &Query<(Mut<RigidBody>, Option<Mut<Up>>)>,
+    resting: f32,
+) {
+    if manifolds.is_empty() {
+        return;
+    }
+
+    let mut bodies = Vec::new();
+    let mut indices = HashMap::new();
+    let mut constraints = Vec::new();
+    for (index, manifold) in manifolds.iter().enumerate() {
+        let a = query.get::<RigidBody>(manifold.body1).unwrap();
+        let b = query.get::<RigidBody>(manifold.body2).unwrap();
+        let body1 = *indices.entry(manifold.body1).or_insert_with(|| {
+            bodies.push(Body::new(manifold.body1, &a));
+            bodies.len() - 1
+        });
+        let body2 = *indices.entry(manifold.body2).or_insert_with(|| {
+            bodies.push(Body::new(manifold.body2, &b));
+            bodies.len() - 1
+        });
+
+        let (b1, b2) = (&bodies[body1], &bodies[body2]);
+        if b1.inv_mass + b2.inv_mass == 0.0 {
+            continue;
+        }
+
+        let restitution = a.material.combine_restitution(&b.material);
+        let friction = a.material.combine_friction(&b.material);
+        let normal = manifold.normal;
+        let tangents = tangents(normal);
+        let points = manifold
+            .contacts
+            .iter()
+            .map(|contact| {
+                let r1 = contact.position - a.center_of_mass();
+                let r2 = contact.position - b.center_of_mass();
+                let normal_mass = b1.effective_mass(r1, normal) + b2.effective_mass(r2, normal);
+                let tangent_mass = |axis: Vec3| {
+                    (b1.effective_mass(r1, axis) + b2.effective_mass(r2, axis)).recip()
+                };
+                let vn = (b2.velocity_at(r2) - b1.velocity_at(r1)).dot(normal);
+                let bounce = if vn < -resting {
+                    -restitution * vn
+                } else {
+                    0.0
+                };
+                Point {
+                    r1,
+                    r2,
+                    normal_mass: normal_mass.recip(),
+                    tangent_mass: [tangent_mass(tangents[0]), tangent_mass(tangents[1])],
+                    bounce,
+                    normal_impulse: 0.0,
+                    tangent_impulse: [0.0; 2],
+                }
+            })
+            .collect();
+        constraints.push(Constraint {
+            manifold: index,
+            body1,
+            body2,
+            normal,
+            tangents,
+            // the penetration of 3d manifolds is negative
+            penetration: -manifold.penetration,
+            friction,
+            points,
+        });
+    }
+
+    for _ in 0..ITERATIONS {
+        for constraint in &mut constraints {
+            let (b1, b2) = pair(&mut bodies, constraint.body1, constraint.body2);
+            let normal = constraint.normal;
+            for point in &mut constraint.points {
+                let limit = constraint.friction * point.normal_impulse;
+                for (i, &tangent) in constraint.tangents.iter().enumerate() {
+                    let vt = (b2.velocity_at(point.r2) - b1.velocity_at(point.r1)).dot(tangent);
+                    let total = (point.tangent_impulse[i] - vt * point.tangent_mass[i])
+                        .max(-limit)
+                        .min(limit);
+                    let impulse = tangent * (total - point.tangent_impulse[i]);
+                    point.tangent_impulse[i] = total;
+                    b1.apply_impulse(point.r1, -impulse);
+                    b2.apply_impulse(point.r2, impulse);
+                }
+
+                let vn = (b2.velocity_at(point.r2) - b1.velocity_at(point.r1)).dot(normal);
+                let total =
+                    (point.normal_impulse + (point.bounce - vn) * point.normal_mass).max(0.0);
+                let impulse = normal * (total - point.normal_impulse);
+                point.normal_impulse = total;
+                b1.apply_impulse(point.r1, -impulse);
+                b2.apply_impulse(point.r2, impulse);
+            }
+        }
+    }
+
+    for constraint in &constraints {
+        let contacts = &mut manifolds[constraint.manifold].contacts;
+        for (contact, point) in contacts.iter_mut().zip(&constraint.points) {
+            contact.impulse = point.normal_impulse;
+        }
+
+        let (b1, b2) = pair(&mut bodies, constraint.body1, constraint.body2);
+        let depth = (constraint.penetration - SLOP).max(0.0) * CORRECTION;
+        let correction = constraint.normal * (depth / (b1.inv_mass + b2.inv_mass));
+        b1.correction -= correction * b1.inv_mass;
+        b2.correction += correction * b2.inv_mass;
+    }
+
+    for body in bodies {
+        let mut rigid = query.get_mut::<RigidBody>(body.entity).unwrap();
+        if matches!(rigid.status, Status::Static | Status::Kinematic) {
+            continue;
+        }
+        rigid.linvel = body.linvel;
+        rigid.position += body.correction;
+
+        if rigid.status == Status::Dynamic {
+            // back to a rotation in local space, which can't represent more than one turn per second
+            let angvel = rigid.rotation.conjugate() * body.angvel;
+            let angle = angvel.length();
+            rigid.angvel = if angle > f32::EPSILON {
+                Quat::from_axis_angle(angvel / angle, angle.min(2.0 * PI))
+            } else {
+                Quat::identity()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{app, body, spawn};
+    use super::super::{GlobalGravity, Shape, Size3};
+    use super::*;
+    use crate::common::Mass;
+
+    fn crate_(position: Vec3) -> RigidBody {
+        RigidBody::new(Mass::Real(1.0))
+            .with_status(Status::Dynamic)
+            .with_position(position)
+    }
+
+    fn cube() -> Shape {
+        Shape::new(Size3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn dropped_box_comes_to_rest() {
+        let mut app = app();
+        let floor = RigidBody::new(Mass::Infinite).with_status(Status::Static);
+        spawn(&mut app, 1, floor, Shape::new(Size3::new(10.0, 1.0, 10.0)));
+        let dropped = spawn(&mut app, 2, crate_(Vec3::new(0.0, 3.0, 0.0)), cube());
+
+        for _ in 0..180 {
+            app.update();
+        }
+        let dropped = body(&app, dropped);
+        assert!(
+            (dropped.position.y() - 1.0).abs() < 0.05,
+            "{:?}",
+            dropped.position
+        );
+        assert!(dropped.linvel.length() < 0.1, "{:?}", dropped.linvel);
+        let (_, angle) = dropped.angvel.to_axis_angle();
+        assert!(angle.abs() < 0.05, "{:?}", dropped.angvel);
+    }
+
+    #[test]
+    fn off_center_hit_turns_box() {
+        let mut app = app();
+        app.resources.insert(GlobalGravity(Vec3::zero()));
+        let target = spawn(&mut app, 1, crate_(Vec3::zero()), cube());
+        // a kinematic bat sweeps through the lower half of the box
+        let bat = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Kinematic)
+            .with_position(Vec3::new(-2.0, -0.6, 0.0))
+            .with_linear_velocity(Vec3::new(30.0, 0.0, 0.0));
+        spawn(&mut app, 2, bat, cube());
+
+        for _ in 0..10 {
+            app.update();
+        }
+        let target = body(&app, target);
+        assert!(target.linvel.x() > 0.0, "{:?}", target.linvel);
+        let (axis, angle) = target.angvel.to_axis_angle();
+        assert!(angle > 0.1, "{:?}", target.angvel);
+        assert!(axis.z() > 0.9, "{:?}", axis);
+    }
+}
@@ -0,0 +1,419 @@
+//! Convex hulls, computed once with quickhull.
+//!
+//! Only the vertices of the hull are kept, so points inside of it never slow
+//! down collision detection, and its faces are kept for raycasts.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use bevy::math::*;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+struct ConvexHullData {
+    points: Vec<Vec3>,
+    faces: Vec<[u32; 3]>,
+    planes: Vec<(Vec3, f32)>,
+    extent: Vec3,
+    volume: f32,
+    centroid: Vec3,
+    inertia: Mat3,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawConvexHull {
+    points: Vec<Vec3>,
+}
+
+/// The convex hull of a set of points in local space.
+///
+/// The hull is computed once, and cloning a `ConvexHull` is cheap, as the data
+/// is shared.  Faces are wound counter-clockwise when seen from the outside.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawConvexHull", into = "RawConvexHull")]
+pub struct ConvexHull {
+    data: Arc<ConvexHullData>,
+}
+
+impl ConvexHull {
+    /// Returns the convex hull of `points`.
+    ///
+    /// Returns `None` if the points don't span a volume, i.e. they are all coplanar.
+    pub fn new(points: &[Vec3]) -> Option<Self> {
+        let (points, faces) = quickhull(points)?;
+
+        let planes = faces
+            .iter()
+            .map(|face| {
+                let [a, b, c] = corners(&points, face);
+                let normal = (b - a).cross(c - a).normalize();
+                (normal, normal.dot(a))
+            })
+            .collect();
+        let extent = points
+            .iter()
+            .fold(Vec3::zero(), |extent, point| extent.max(point.abs()));
+
+        // the hull is split into tetrahedra with the local origin
+        let mut volume = 0.0;
+        let mut centroid = Vec3::zero();
+        let mut covariance = Mat3::zero();
+        let canonical = Mat3::from_cols(
+            Vec3::new(2.0, 1.0, 1.0),
+            Vec3::new(1.0, 2.0, 1.0),
+            Vec3::new(1.0, 1.0, 2.0),
+        );
+        for face in &faces {
+            let [a, b, c] = corners(&points, face);
+            let determinant = a.dot(b.cross(c));
+            volume += determinant / 6.0;
+            centroid += (a + b + c) * (determinant / 24.0);
+            let m = Mat3::from_cols(a, b, c);
+            covariance = covariance + m * canonical * m.transpose() * (determinant / 120.0);
+        }
+        centroid /= volume;
+        let covariance = covariance
+            - Mat3::from_cols(
+                centroid * centroid.x(),
+                centroid * centroid.y(),
+                centroid * centroid.z(),
+            ) * volume;
+        let trace = covariance.x_axis().x() + covariance.y_axis().y() + covariance.z_axis().z();
+        let inertia = Mat3::identity() * trace - covariance;
+
+        let data = ConvexHullData {
+            points,
+            faces,
+            planes,
+            extent,
+            volume,
+            centroid,
+            inertia,
+        };
+        Some(Self {
+            data: Arc::new(data),
+        })
+    }
+
+    /// Get the vertices of this hull.
+    pub fn points(&self) -> &[Vec3] {
+        &self.data.points
+    }
+
+    /// Get the triangles of this hull, as indices into `points`.
+    pub fn faces(&self) -> &[[u32; 3]] {
+        &self.data.faces
+    }
+
+    /// Returns the outward normal and the offset from the origin of every face.
+    pub(crate) fn planes(&self) -> &[(Vec3, f32)] {
+        &self.data.planes
+    }
+
+    /// Returns the half extents of the box bounding this hull around the local origin.
+    pub(crate) fn extent(&self) -> Vec3 {
+        self.data.extent
+    }
+
+    /// Returns the volume of this hull, its centroid and its inertia tensor per
+    /// unit of density, around the centroid.
+    pub(crate) fn mass_properties(&self) -> (f32, Vec3, Mat3) {
+        (self.data.volume, self.data.centroid, self.data.inertia)
+    }
+}
+
+impl fmt::Debug for ConvexHull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConvexHull")
+            .field("points", &self.data.points.len())
+            .field("faces", &self.data.faces.len())
+            .finish()
+    }
+}
+
+impl PartialEq for ConvexHull {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data) || self.data.points == other.data.points
+    }
+}
+
+impl std::convert::TryFrom<RawConvexHull> for ConvexHull {
+    type Error = &'static str;
+
+    fn try_from(raw: RawConvexHull) -> Result<Self, Self::Error> {
+        Self::new(&raw.points).ok_or("degenerate convex hull")
+    }
+}
+
+impl From<ConvexHull> for RawConvexHull {
+    fn from(hull: ConvexHull) -> Self {
+        Self {
+            points: hull.data.points.clone(),
+        }
+    }
+}
+
+fn corners(points: &[Vec3], face: &[u32; 3]) -> [Vec3; 3] {
+    [
+        points[face[0] as usize],
+        points[face[1] as usize],
+        points[face[2] as usize],
+    ]
+}
+
+/// A face of a hull under construction, with the points in front of it.
+struct Face {
+    indices: [usize; 3],
+    normal: Vec3,
+    offset: f32,
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl Face {
+    fn new(points: &[Vec3], indices: [usize; 3]) -> Self {
+        let [a, b, c] = [points[indices[0]], points[indices[1]], points[indices[2]]];
+        let normal = (b - a).cross(c - a);
+        let length = normal.length();
+        let normal = if length > 0.0 {
+            normal / length
+        } else {
+            Vec3::zero()
+        };
+        Self {
+            indices,
+            normal,
+            offset: normal.dot(a),
+            outside: Vec::new(),
+            alive: true,
+        }
+    }
+
+    fn distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.indices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+/// Returns the vertices and the faces of the convex hull of `points`.
+fn quickhull(points: &[Vec3]) -> Option<(Vec<Vec3>, Vec<[u32; 3]>)> {
+    if points.is_empty() {
+        return None;
+    }
+    let scale = points.iter().fold(0.0_f32, |scale, p| {
+        scale.max(p.x().abs()).max(p.y().abs()).max(p.z().abs())
+    });
+    let epsilon = scale * 1.0e-5;
+
+    // the initial tetrahedron starts with the two furthest extremes along the axes
+    let mut extremes = [0; 6];
+    for (i, point) in points.iter().enumerate() {
+        for axis in 0..3 {
+            if point[axis] < points[extremes[axis * 2]][axis] {
+                extremes[axis * 2] = i;
+            }
+            if point[axis] > points[extremes[axis * 2 + 1]][axis] {
+                extremes[axis * 2 + 1] = i;
+            }
+        }
+    }
+    let mut best = (0.0, 0, 0);
+    for &i in &extremes {
+        for &j in &extremes {
+            let distance = (points[i] - points[j]).length();
+            if distance > best.0 {
+                best = (distance, i, j);
+            }
+        }
+    }
+    let (distance, a, b) = best;
+    if distance <= epsilon {
+        return None;
+    }
+    let furthest = |distance: &dyn Fn(Vec3) -> f32| {
+        (0..points.len())
+            .map(|i| (distance(points[i]), i))
+            .max_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(Ordering::Equal))
+    };
+    let axis = (points[b] - points[a]).normalize();
+    let (distance, c) = furthest(&|p| (p - points[a]).cross(axis).length())?;
+    if distance <= epsilon {
+        return None;
+    }
+    let normal = (points[b] - points[a])
+        .cross(points[c] - points[a])
+        .normalize();
+    let (distance, d) = furthest(&|p| normal.dot(p - points[a]).abs())?;
+    if distance <= epsilon {
+        return None;
+    }
+
+    let mut faces = Vec::new();
+    for &(i, j, k, other) in &[(a, b, c, d), (a, d, b, c), (b, d, c, a), (c, d, a, b)] {
+        let mut face = Face::new(points, [i, j, k]);
+        if face.distance(points[other]) > 0.0 {
+            face = Face::new(points, [i, k, j]);
+        }
+        faces.push(face);
+    }
+    for (i, &point) in points.iter().enumerate() {
+        if [a, b, c, d].contains(&i) {
+            continue;
+        }
+        if let Some(face) = faces.iter_mut().find(|f| f.distance(point) > epsilon) {
+            face.outside.push(i);
+        }
+    }
+    let mut edges = HashMap::new();
+    for (index, face) in faces.iter().enumerate() {
+        for &edge in &face.edges() {
+            edges.insert(edge, index);
+        }
+    }
+
+    while let Some(start) = faces.iter().position(|f| f.alive && !f.outside.is_empty()) {
+        let face = &faces[start];
+        let eye = face
+            .outside
+            .iter()
+            .copied()
+            .max_by(|&i, &j| {
+                let (di, dj) = (face.distance(points[i]), face.distance(points[j]));
+                di.partial_cmp(&dj).unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+        let point = points[eye];
+
+        // the faces that see the eye point, and the edges around them
+        let mut visible = vec![start];
+        faces[start].alive = false;
+        let mut horizon = Vec::new();
+        let mut i = 0;
+        while i < visible.len() {
+            for &(from, to) in &faces[visible[i]].edges() {
+                let neighbour = edges[&(to, from)];
+                if !faces[neighbour].alive {
+                    continue;
+                }
+                if faces[neighbour].distance(point) > epsilon {
+                    faces[neighbour].alive = false;
+                    visible.push(neighbour);
+                } else {
+                    horizon.push((from, to));
+                }
+            }
+            i += 1;
+        }
+
+        let mut orphans = Vec::new();
+        for &index in &visible {
+            for &edge in &faces[index].edges() {
+                if edges.get(&edge) == Some(&index) {
+                    edges.remove(&edge);
+                }
+            }
+            orphans.append(&mut faces[index].outside);
+        }
+        let first = faces.len();
+        for &(from, to) in &horizon {
+            let face = Face::new(points, [from, to, eye]);
+            for &edge in &face.edges() {
+                edges.insert(edge, faces.len());
+            }
+            faces.push(face);
+        }
+        for orphan in orphans {
+            if orphan == eye {
+                continue;
+            }
+            let point = points[orphan];
+            if let Some(face) = faces[first..]
+                .iter_mut()
+                .find(|f| f.distance(point) > epsilon)
+            {
+                face.outside.push(orphan);
+            }
+        }
+    }
+
+    let mut vertices = Vec::new();
+    let mut remap = HashMap::new();
+    let indices = faces
+        .iter()
+        .filter(|face| face.alive)
+        .map(|face| {
+            let mut indices = [0; 3];
+            for (index, &vertex) in indices.iter_mut().zip(&face.indices) {
+                *index = *remap.entry(vertex).or_insert_with(|| {
+                    vertices.push(points[vertex]);
+                    vertices.len() as u32 - 1
+                });
+            }
+            indices
+        })
+        .collect();
+    Some((vertices, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> Vec<Vec3> {
+        let mut points = Vec::new();
+        for &x in &[-1.0, 1.0] {
+            for &y in &[-1.0, 1.0] {
+                for &z in &[-1.0, 1.0] {
+                    points.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn rejects_degenerate_points() {
+        assert!(ConvexHull::new(&[]).is_none());
+        assert!(ConvexHull::new(&[Vec3::zero(); 4]).is_none());
+        let collinear: Vec<_> = (0..4).map(|i| Vec3::unit_x() * i as f32).collect();
+        assert!(ConvexHull::new(&collinear).is_none());
+        let coplanar: Vec<_> = cube().into_iter().filter(|p| p.y() > 0.0).collect();
+        assert!(ConvexHull::new(&coplanar).is_none());
+    }
+
+    #[test]
+    fn builds_closed_hull() {
+        let mut points = cube();
+        points.push(Vec3::new(0.2, -0.3, 0.1));
+        let hull = ConvexHull::new(&points).unwrap();
+        assert_eq!(hull.points().len(), 8);
+        assert_eq!(hull.faces().len(), 12);
+        for &(normal, offset) in hull.planes() {
+            assert!((normal.length() - 1.0).abs() < 1.0e-4);
+            assert!((offset - 1.0).abs() < 1.0e-4);
+        }
+        assert_eq!(hull.extent(), Vec3::one());
+    }
+
+    #[test]
+    fn computes_mass_properties() {
+        let offset = Vec3::new(3.0, 0.0, 0.0);
+        let points: Vec<_> = cube().into_iter().map(|p| p + offset).collect();
+        let (volume, centroid, inertia) = ConvexHull::new(&points).unwrap().mass_properties();
+        assert!((volume - 8.0).abs() < 1.0e-3);
+        assert!((centroid - offset).length() < 1.0e-3);
+        let expected = 8.0 * (4.0 + 4.0) / 12.0;
+        let diagonal = Vec3::new(
+            inertia.x_axis().x(),
+            inertia.y_axis().y(),
+            inertia.z_axis().z(),
+        );
+        assert!((diagonal - Vec3::splat(expected)).length() < 1.0e-2);
+        assert!(inertia.x_axis().y().abs() < 1.0e-2);
+    }
+}
@@ -3,26 +3,66 @@
 //! For examples, see the root of the crate.
 
 use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::f32::consts::PI;
+use std::hash::{Hash, Hasher};
 use std::mem;
 
+use bevy::ecs::{IntoThreadLocalSystem, ParallelExecutor, Schedule};
 use bevy::math::*;
 use bevy::prelude::*;
-use hashbrown::HashSet;
+use bevy::render::mesh::VertexAttributeValues;
+use hashbrown::HashMap;
 use serde::{Deserialize, Serialize};
-use smallvec::SmallVec;
+use smallvec::{smallvec, SmallVec};
 
 use crate::broad::{self, BoundingBox, Collider};
 use crate::common::*;
 
 mod collision;
+mod dynamics;
+mod hull;
+mod query;
+mod terrain;
+
+pub use hull::ConvexHull;
+pub use query::{Overlap, RayHit, ShapeHit};
+use terrain::Terrain;
+pub use terrain::{HeightField, TriMesh};
 
 /// This is what you want to add to your `App` if you want to run 3d physics simulation.
+///
+/// For lockstep networking and replays, add `Physics3dPlugin::deterministic()` instead.
 pub struct Physics3dPlugin;
 
+impl Physics3dPlugin {
+    /// Returns a plugin that runs 3d physics simulation in deterministic mode.
+    ///
+    /// Deterministic mode takes exactly one step every frame, no matter how long
+    /// the frame took, so the simulation runs slower or faster along with the
+    /// frame rate.  Lockstep games advance their frames at a fixed rate of their
+    /// own, replays step through the recorded inputs one frame at a time.
+    pub fn deterministic() -> impl Plugin {
+        DeterministicPhysics3dPlugin
+    }
+}
+
+/// The plugin returned by `Physics3dPlugin::deterministic`.
+struct DeterministicPhysics3dPlugin;
+
+impl Plugin for DeterministicPhysics3dPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        Physics3dPlugin.build(app);
+        app.add_resource(FixedTimestep::default().with_deterministic(true));
+    }
+}
+
 pub mod stage {
     #[doc(hidden)]
     pub use bevy::prelude::stage::*;
 
+    pub const FIXED_TIMESTEP: &str = "fixed_timestep_3d";
+    pub const PHYSICS: &str = "physics_3d";
     pub const COLLIDING_JOINT: &str = "colliding_joint_3d";
     pub const PHYSICS_STEP: &str = "physics_step_3d";
     pub const BROAD_PHASE: &str = "broad_phase_3d";
@@ -39,21 +79,31 @@ impl Plugin for Physics3dPlugin {
             .add_resource(GlobalUp::default())
             .add_resource(GlobalStep::default())
             .add_resource(AngularTolerance::default())
+            .add_resource(RestingSpeed::default())
+            .add_resource(FixedTimestep::default())
+            .add_resource(StateHash::default())
+            .add_resource(BroadPhase::default())
+            .add_resource(Triggers::default())
+            .add_resource(Solved::default())
             .add_event::<Manifold>()
-            .add_stage_before(stage::UPDATE, stage::PHYSICS_STEP)
-            .add_stage_before(stage::PHYSICS_STEP, stage::COLLIDING_JOINT)
-            .add_stage_after(stage::PHYSICS_STEP, stage::BROAD_PHASE)
-            .add_stage_after(stage::BROAD_PHASE, stage::NARROW_PHASE)
-            .add_stage_after(stage::NARROW_PHASE, stage::PHYSICS_SOLVE)
-            .add_stage_after(stage::PHYSICS_SOLVE, stage::RIGID_JOINT)
-            .add_stage_after(stage::RIGID_JOINT, stage::SYNC_TRANSFORM);
+            .add_event::<CollisionStarted>()
+            .add_event::<CollisionOngoing>()
+            .add_event::<CollisionEnded>()
+            .add_event::<TriggerEntered>()
+            .add_event::<TriggerExited>()
+            .add_stage_before(stage::UPDATE, stage::PHYSICS)
+            .add_stage_before(stage::PHYSICS, stage::FIXED_TIMESTEP)
+            .add_stage_after(stage::PHYSICS, stage::SYNC_TRANSFORM);
+        let mut physics = PhysicsSchedule::default();
         let physics_step = PhysicsStep::default().system(app.resources_mut());
-        app.add_system_to_stage(stage::PHYSICS_STEP, physics_step)
-            .add_system_to_stage(stage::BROAD_PHASE, broad_phase_system.system())
-            .add_system_to_stage(stage::NARROW_PHASE, narrow_phase_system.system());
         let solver = Solver::default().system(app.resources_mut());
-        app.add_system_to_stage(stage::PHYSICS_SOLVE, solver)
-            .add_system_to_stage(stage::SYNC_TRANSFORM, sync_transform_system.system())
+        let tracker = ContactTracker::default().system(app.resources_mut());
+        physics
+            .add_system_to_stage(stage::PHYSICS_STEP, physics_step)
+            .add_system_to_stage(stage::BROAD_PHASE, broad_phase_system.system())
+            .add_system_to_stage(stage::NARROW_PHASE, narrow_phase_system.system())
+            .add_system_to_stage(stage::PHYSICS_SOLVE, solver)
+            .add_system_to_stage(stage::PHYSICS_SOLVE, tracker)
             .add_system_to_stage(
                 FixedJointBehaviour::STAGE,
                 joint_system::<FixedJointBehaviour>.system(),
@@ -66,11 +116,78 @@ impl Plugin for Physics3dPlugin {
                 SpringJointBehaviour::STAGE,
                 joint_system::<SpringJointBehaviour>.system(),
             );
+        app.add_resource(physics)
+            .add_system_to_stage(stage::FIXED_TIMESTEP, fixed_timestep_system.system())
+            .add_system_to_stage(stage::PHYSICS, physics_system())
+            .add_system_to_stage(stage::SYNC_TRANSFORM, sync_transform_system.system())
+            .add_system_to_stage(stage::SYNC_TRANSFORM, state_hash_system.system())
+            .add_system_to_stage(stage::LAST, broad_phase_changes_system.system());
     }
 }
 
 pub type BroadPhase = broad::BroadPhase<Obb>;
 
+/// The stages that run once for every fixed step, from `COLLIDING_JOINT` to `RIGID_JOINT`.
+///
+/// Systems that have to run on every step, like the `joint_system` of a custom
+/// `JointBehaviour`, are added here instead of to the `App`.
+pub struct PhysicsSchedule {
+    schedule: Schedule,
+}
+
+impl Default for PhysicsSchedule {
+    fn default() -> Self {
+        let mut schedule = Schedule::default();
+        schedule.add_stage(stage::COLLIDING_JOINT);
+        schedule.add_stage_after(stage::COLLIDING_JOINT, stage::PHYSICS_STEP);
+        schedule.add_stage_after(stage::PHYSICS_STEP, stage::BROAD_PHASE);
+        schedule.add_stage_after(stage::BROAD_PHASE, stage::NARROW_PHASE);
+        schedule.add_stage_after(stage::NARROW_PHASE, stage::PHYSICS_SOLVE);
+        schedule.add_stage_after(stage::PHYSICS_SOLVE, stage::RIGID_JOINT);
+        Self { schedule }
+    }
+}
+
+impl PhysicsSchedule {
+    /// Adds a system to one of the stages that run on every step.
+    pub fn add_system_to_stage(
+        &mut self,
+        stage_name: &'static str,
+        system: Box<dyn System>,
+    ) -> &mut Self {
+        self.schedule.add_system_to_stage(stage_name, system);
+        self
+    }
+}
+
+/// Returns the system that runs the `PhysicsSchedule` once for every step of the current frame.
+fn physics_system() -> Box<dyn System> {
+    // trackers are cleared by the app at the end of the frame, not after every step
+    let mut executor = ParallelExecutor::without_tracker_clears();
+    let run = move |world: &mut World, resources: &mut Resources| {
+        let steps = resources
+            .get::<FixedTimestep>()
+            .map_or(0, |timestep| timestep.steps());
+        if steps == 0 {
+            return;
+        }
+
+        // the schedule is taken out of the resources while it runs on them
+        let mut schedule = match resources.get_mut::<PhysicsSchedule>() {
+            Some(mut physics) => mem::take(&mut physics.schedule),
+            None => return,
+        };
+        for _ in 0..steps {
+            schedule.initialize(world, resources);
+            executor.run(&mut schedule, world, resources);
+        }
+        if let Some(mut physics) = resources.get_mut::<PhysicsSchedule>() {
+            physics.schedule = schedule;
+        }
+    };
+    run.thread_local_system()
+}
+
 /// The global gravity that affects every `RigidBody` with the `Semikinematic` status.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct GlobalGravity(pub Vec3);
@@ -95,6 +212,139 @@ impl Default for GlobalUp {
     }
 }
 
+/// The fixed timestep of the physics simulation, affects all bodies.
+///
+/// Every frame, the `PhysicsSchedule` runs once for every step that fits into
+/// the accumulated frame time, and every step simulates exactly one step
+/// duration.  The time left over is kept for the next frame and used to
+/// interpolate transforms.  At most `max_steps` steps are taken per frame, the
+/// time beyond that is dropped so that slow frames don't fall further behind.
+///
+/// In deterministic mode, exactly one step is taken every frame and the frame
+/// time is ignored, so the speed of the simulation depends on the frame rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+    steps: usize,
+    max_steps: usize,
+    deterministic: bool,
+}
+
+impl FixedTimestep {
+    /// Returns a new `FixedTimestep` with a number of steps per second.
+    pub fn from_rate(rate: f32) -> Self {
+        Self::from_step(rate.recip())
+    }
+
+    /// Returns a new `FixedTimestep` with the duration of a single step in seconds.
+    pub fn from_step(step: f32) -> Self {
+        Self {
+            step,
+            accumulator: 0.0,
+            steps: 0,
+            max_steps: 5,
+            deterministic: false,
+        }
+    }
+
+    /// Returns a `FixedTimestep` identical to this one, but with the maximum number of steps per frame set.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Returns a `FixedTimestep` identical to this one, but with the deterministic mode set.
+    ///
+    /// In deterministic mode, every frame takes exactly one fixed step no matter
+    /// how long the frame took, colliders and manifolds are processed in the
+    /// order of their entities, and the `StateHash` is updated after every step.
+    /// The simulation runs at real time speed only at one frame per step.
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Gets the duration of a single step in seconds.
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Gets the number of steps per second.
+    pub fn rate(&self) -> f32 {
+        self.step.recip()
+    }
+
+    /// Sets the number of steps per second.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.step = rate.recip();
+    }
+
+    /// Gets the maximum number of steps per frame.
+    pub fn max_steps(&self) -> usize {
+        self.max_steps
+    }
+
+    /// Sets the maximum number of steps per frame.
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    /// Returns true if the simulation runs in deterministic mode.
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Returns true if the physics stages are taking at least one step in the current frame.
+    pub fn is_stepping(&self) -> bool {
+        self.steps > 0
+    }
+
+    /// Gets the number of steps the physics stages take in the current frame.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Returns how far the current frame is between the last step and the next one, from 0 to 1.
+    ///
+    /// This is used to interpolate the transforms of rigid bodies.
+    pub fn alpha(&self) -> f32 {
+        if self.deterministic {
+            return 1.0;
+        }
+        (self.accumulator / self.step).max(0.0).min(1.0)
+    }
+
+    fn advance(&mut self, delta_time: f32) {
+        if self.deterministic {
+            self.steps = 1;
+            return;
+        }
+
+        self.accumulator += delta_time;
+        let steps = (self.accumulator / self.step).floor().max(0.0) as usize;
+        self.accumulator -= steps as f32 * self.step;
+        // the steps over the limit are dropped, only the remainder is kept
+        self.steps = steps.min(self.max_steps);
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::from_rate(60.0)
+    }
+}
+
+/// A hash over the state of all rigid bodies, only updated in deterministic mode.
+///
+/// Two runs of the same simulation with the same inputs have the same hash
+/// after every step, so comparing hashes detects when two runs diverge.  The
+/// entities are part of the hash, so both runs must spawn their bodies with the
+/// same ids, e.g. with `World::spawn_as_entity`.  Hashes are only comparable
+/// between binaries built with the same compiler.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateHash(pub u64);
+
 /// The global angular tolerance in radians, affects all semikinematic bodies.
 ///
 /// This is used for step calculation and for push dynamics.
@@ -107,6 +357,20 @@ impl Default for AngularTolerance {
     }
 }
 
+/// The global resting speed, affects all bodies.
+///
+/// Bodies hitting each other slower than this don't bounce, whatever their
+/// restitution, so that resting contacts stay at rest.  The speed is raised to
+/// twice the speed that gravity adds in one step, if that is higher.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RestingSpeed(pub f32);
+
+impl Default for RestingSpeed {
+    fn default() -> Self {
+        Self(0.5)
+    }
+}
+
 /// The local up vector, affects a single semikinematic body.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Properties)]
 pub struct Up(pub Vec3);
@@ -117,6 +381,106 @@ impl Default for Up {
     }
 }
 
+/// Tracks the ground under a body, maintained by the solver for every body with this component.
+///
+/// A body is grounded while it touches another one with a normal within
+/// `AngularTolerance` of its `Up` vector, or `GlobalUp` without one.  The coyote time keeps `can_jump` true
+/// for a short while after walking off a ledge.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Properties)]
+pub struct Grounded {
+    #[property(ignore)]
+    #[serde(skip)]
+    ground: Option<Entity>,
+    normal: Vec3,
+    velocity: Vec3,
+    airtime: f32,
+    coyote_time: f32,
+    coyote: bool,
+}
+
+impl Default for Grounded {
+    fn default() -> Self {
+        Self {
+            ground: None,
+            normal: Vec3::zero(),
+            velocity: Vec3::zero(),
+            airtime: f32::INFINITY,
+            coyote_time: 0.0,
+            coyote: false,
+        }
+    }
+}
+
+impl Grounded {
+    /// Returns a `Grounded` identical to this one, but with the coyote time set to a new one.
+    pub fn with_coyote_time(mut self, coyote_time: f32) -> Self {
+        self.coyote_time = coyote_time;
+        self
+    }
+
+    /// Gets the body this body stands on.
+    pub fn ground(&self) -> Option<Entity> {
+        self.ground
+    }
+
+    /// Returns true if this body stands on another one.
+    pub fn is_grounded(&self) -> bool {
+        self.ground.is_some()
+    }
+
+    /// Gets the normal of the current or last ground, pointing towards this body.
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// Gets the linear velocity of the current or last ground.
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    /// Gets the time since this body left the ground, zero while grounded.
+    pub fn airtime(&self) -> f32 {
+        self.airtime
+    }
+
+    /// Gets the coyote time.
+    pub fn coyote_time(&self) -> f32 {
+        self.coyote_time
+    }
+
+    /// Sets the coyote time.
+    pub fn set_coyote_time(&mut self, coyote_time: f32) {
+        self.coyote_time = coyote_time;
+    }
+
+    /// Returns true while grounded, and for up to the coyote time after leaving
+    /// the ground unless `jump` was called since.
+    pub fn can_jump(&self) -> bool {
+        self.is_grounded() || (self.coyote && self.airtime <= self.coyote_time)
+    }
+
+    /// Uses up the coyote time, so that a body that jumped can't jump again in mid-air.
+    pub fn jump(&mut self) {
+        self.coyote = false;
+    }
+
+    fn update(&mut self, ground: Option<(Entity, Vec3, Vec3)>, delta_time: f32) {
+        match ground {
+            Some((ground, normal, velocity)) => {
+                self.ground = Some(ground);
+                self.normal = normal;
+                self.velocity = velocity;
+                self.airtime = 0.0;
+                self.coyote = true;
+            }
+            None => {
+                self.ground = None;
+                self.airtime += delta_time;
+            }
+        }
+    }
+}
+
 /// The rotation, relative to the up vector.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Properties)]
 pub struct UpRotation(pub f32);
@@ -128,32 +492,82 @@ impl Default for UpRotation {
 }
 
 #[doc(hidden)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Cuboid,
+    Sphere { radius: f32 },
+    Capsule { half_height: f32, radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+    ConvexHull(ConvexHull),
+    Terrain(Terrain),
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Obb {
     status: Status,
     body: Entity,
+    shape: Entity,
     local: Transform,
     transform: Transform,
     extent: Vec3,
+    geometry: Geometry,
+    layers: CollisionLayers,
+    motion: Vec3,
+    sensor: bool,
 }
 
 impl Obb {
     fn new(
         status: Status,
         body: Entity,
+        shape: Entity,
         local: Transform,
         transform: Transform,
         extent: Vec3,
+        geometry: Geometry,
+        layers: CollisionLayers,
     ) -> Self {
         Self {
             status,
             body,
+            shape,
             local,
             transform,
             extent,
+            geometry,
+            layers,
+            motion: Vec3::zero(),
+            sensor: false,
         }
     }
 
+    /// Sweeps this collider backwards by `motion`, the distance it travelled this step.
+    fn with_motion(mut self, motion: Vec3) -> Self {
+        self.motion = motion;
+        self
+    }
+
+    /// Makes this collider a trigger, that is only tested for overlaps.
+    fn with_sensor(mut self, sensor: bool) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    /// Returns this collider moved back along its motion to a time during the
+    /// last step, from 0 at its start to 1 at its end, without a motion.
+    fn rewound(&self, time: f32) -> Self {
+        let mut obb = self.clone();
+        obb.transform.translation -= obb.motion * (1.0 - time);
+        obb.motion = Vec3::zero();
+        obb
+    }
+
+    /// Returns the world space transform of the shape, combining the body and the local transform.
+    pub fn world(&self) -> Transform {
+        Transform::from_matrix(self.transform.compute_matrix() * self.local.compute_matrix())
+    }
+
     pub fn v0(&self) -> Vec3 {
         let v = Vec3::new(-self.extent.x(), -self.extent.y(), -self.extent.z());
         self.transform
@@ -283,12 +697,25 @@ impl Collider for Obb {
     type Point = Vec3;
 
     fn bounding_box(&self) -> BoundingBox<Self::Point> {
-        BoundingBox::new(self.min(), self.max())
+        let (min, max) = (self.min(), self.max());
+        BoundingBox::new(min.min(min - self.motion), max.max(max - self.motion))
     }
 
     fn status(&self) -> Status {
         self.status
     }
+
+    fn body(&self) -> Entity {
+        self.body
+    }
+
+    fn shape(&self) -> Entity {
+        self.shape
+    }
+
+    fn layers(&self) -> CollisionLayers {
+        self.layers
+    }
 }
 
 /// The three dimensional size of a `Shape`
@@ -310,20 +737,146 @@ impl Size3 {
     }
 }
 
+/// The geometry of a `Shape`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Property)]
+pub enum ShapeKind {
+    /// A cuboid with a size, centered at the local origin.
+    Cuboid(Size3),
+    /// A sphere with a radius, centered at the local origin.
+    Sphere(f32),
+    /// A capsule, centered at the local origin.
+    ///
+    /// The height is the distance between the centers of the two caps
+    /// along the local y axis.
+    Capsule { height: f32, radius: f32 },
+    /// A cylinder with a height along the local y axis and a radius, centered at the local origin.
+    Cylinder { height: f32, radius: f32 },
+    /// The convex hull of a set of points in local space.
+    ConvexHull(ConvexHull),
+    /// A static triangle mesh in local space.
+    TriMesh(TriMesh),
+    /// A static heightfield, centered at the local origin.
+    HeightField(HeightField),
+}
+
 /// The shape of a rigid body.
 ///
 /// Contains a rotation/translation offset and a size.
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Properties)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Properties)]
 pub struct Shape {
     local: Mat4,
-    size: Size3,
+    kind: ShapeKind,
+    layers: CollisionLayers,
+    sensor: bool,
 }
 
 impl Shape {
     /// Return a new `Shape` with a zero offset and a size.
     pub fn new(size: Size3) -> Self {
         let local = Mat4::identity();
-        Self { local, size }
+        let kind = ShapeKind::Cuboid(size);
+        Self {
+            local,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new spherical `Shape` with a zero offset and a radius.
+    pub fn sphere(radius: f32) -> Self {
+        let local = Mat4::identity();
+        let kind = ShapeKind::Sphere(radius);
+        Self {
+            local,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new capsule `Shape` with a zero offset, a height and a radius.
+    pub fn capsule(height: f32, radius: f32) -> Self {
+        let local = Mat4::identity();
+        let kind = ShapeKind::Capsule { height, radius };
+        Self {
+            local,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new cylindrical `Shape` with a zero offset, a height and a radius.
+    pub fn cylinder(height: f32, radius: f32) -> Self {
+        let local = Mat4::identity();
+        let kind = ShapeKind::Cylinder { height, radius };
+        Self {
+            local,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new `Shape` with a zero offset, shaped like the convex hull of `points`.
+    ///
+    /// Returns `None` if the points don't span a volume, i.e. they are all coplanar.
+    pub fn convex_hull(points: &[Vec3]) -> Option<Self> {
+        ConvexHull::new(points).map(Self::hull)
+    }
+
+    /// Return a new `Shape` with a zero offset, shaped like a `ConvexHull`.
+    pub fn hull(hull: ConvexHull) -> Self {
+        let local = Mat4::identity();
+        let kind = ShapeKind::ConvexHull(hull);
+        Self {
+            local,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new `Shape` with a zero offset, shaped like the convex hull of the vertices of a `Mesh`.
+    ///
+    /// Returns `None` if the mesh has no positions or if they don't span a volume.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+            VertexAttributeValues::Float3(positions) => {
+                let points: Vec<Vec3> = positions.iter().map(|&p| Vec3::from(p)).collect();
+                Self::convex_hull(&points)
+            }
+            _ => None,
+        }
+    }
+
+    /// Return a new `Shape` with a zero offset, shaped like a triangle mesh.
+    ///
+    /// Triangle meshes should only be attached to `Static` bodies.
+    pub fn trimesh(mesh: TriMesh) -> Self {
+        let local = Mat4::identity();
+        let kind = ShapeKind::TriMesh(mesh);
+        Self {
+            local,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
+    }
+
+    /// Return a new `Shape` with a zero offset, shaped like a heightfield.
+    ///
+    /// Heightfields should only be attached to `Static` bodies.
+    pub fn heightfield(field: HeightField) -> Self {
+        let local = Mat4::identity();
+        let kind = ShapeKind::HeightField(field);
+        Self {
+            local,
+            kind,
+            layers: CollisionLayers::default(),
+            sensor: false,
+        }
     }
 
     /// Return a new `Shape` with an offset and a size.
@@ -332,15 +885,208 @@ impl Shape {
         self
     }
 
+    /// Return a new `Shape` on some collision layers.
+    pub fn with_layers(mut self, layers: CollisionLayers) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Return a new `Shape` that is a trigger if `sensor` is set.
+    ///
+    /// Triggers never push other bodies and never show up in `Manifold`s, they
+    /// only send `TriggerEntered` and `TriggerExited` events.
+    pub fn with_sensor(mut self, sensor: bool) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    /// Get the geometry of this `Shape`.
+    pub fn kind(&self) -> &ShapeKind {
+        &self.kind
+    }
+
+    /// Get the collision layers of this `Shape`.
+    pub fn layers(&self) -> CollisionLayers {
+        self.layers
+    }
+
+    /// Set the collision layers of this `Shape`.
+    pub fn set_layers(&mut self, layers: CollisionLayers) {
+        self.layers = layers;
+    }
+
+    /// Get the sensor flag of this `Shape`.
+    pub fn is_sensor(&self) -> bool {
+        self.sensor
+    }
+
+    /// Set the sensor flag of this `Shape`.
+    pub fn set_sensor(&mut self, sensor: bool) {
+        self.sensor = sensor;
+    }
+
+    /// Returns the half extents of the box bounding this `Shape` in local space.
     pub fn extent(&self) -> Vec3 {
-        Vec3::new(
-            self.size.width * 0.5,
-            self.size.height * 0.5,
-            self.size.depth * 0.5,
+        match self.kind {
+            ShapeKind::Cuboid(size) => {
+                Vec3::new(size.width * 0.5, size.height * 0.5, size.depth * 0.5)
+            }
+            ShapeKind::Sphere(radius) => Vec3::splat(radius),
+            ShapeKind::Capsule { height, radius } => {
+                Vec3::new(radius, height * 0.5 + radius, radius)
+            }
+            ShapeKind::Cylinder { height, radius } => Vec3::new(radius, height * 0.5, radius),
+            ShapeKind::ConvexHull(ref hull) => hull.extent(),
+            ShapeKind::TriMesh(ref mesh) => mesh.extent(),
+            ShapeKind::HeightField(ref field) => field.extent(),
+        }
+    }
+
+    fn geometry(&self) -> Geometry {
+        match self.kind {
+            ShapeKind::Cuboid(_) => Geometry::Cuboid,
+            ShapeKind::Sphere(radius) => Geometry::Sphere { radius },
+            ShapeKind::Capsule { height, radius } => Geometry::Capsule {
+                half_height: height * 0.5,
+                radius,
+            },
+            ShapeKind::Cylinder { height, radius } => Geometry::Cylinder {
+                half_height: height * 0.5,
+                radius,
+            },
+            ShapeKind::ConvexHull(ref hull) => Geometry::ConvexHull(hull.clone()),
+            ShapeKind::TriMesh(ref mesh) => Geometry::Terrain(Terrain::TriMesh(mesh.clone())),
+            ShapeKind::HeightField(ref field) => {
+                Geometry::Terrain(Terrain::HeightField(field.clone()))
+            }
+        }
+    }
+
+    /// Returns the lowest point of this shape along `up`, relative to the body.
+    fn lowest_point(&self, rotation: Quat, up: Vec3) -> Vec3 {
+        let e = self.extent();
+        let corners = || -> SmallVec<[Vec3; 8]> {
+            smallvec![
+                Vec3::new(-e.x(), -e.y(), -e.z()),
+                Vec3::new(e.x(), -e.y(), -e.z()),
+                Vec3::new(e.x(), e.y(), -e.z()),
+                Vec3::new(-e.x(), e.y(), -e.z()),
+                Vec3::new(-e.x(), -e.y(), e.z()),
+                Vec3::new(e.x(), -e.y(), e.z()),
+                Vec3::new(e.x(), e.y(), e.z()),
+                Vec3::new(-e.x(), e.y(), e.z()),
+            ]
+        };
+        let (points, radius): (SmallVec<[Vec3; 8]>, f32) = match self.kind {
+            ShapeKind::Sphere(radius) => (smallvec![Vec3::zero()], radius),
+            ShapeKind::Capsule { height, radius } => {
+                let half = Vec3::new(0.0, height * 0.5, 0.0);
+                (smallvec![-half, half], radius)
+            }
+            ShapeKind::ConvexHull(ref hull) => (hull.points().iter().copied().collect(), 0.0),
+            _ => (corners(), 0.0),
+        };
+        let points: SmallVec<[Vec3; 8]> = points
+            .into_iter()
+            .map(|v| rotation * self.local.transform_point3(v))
+            .collect();
+        let s: SmallVec<[f32; 8]> = points.iter().map(|v| v.dot(up)).collect();
+        let min = s.iter().copied().fold(f32::INFINITY, f32::min);
+        let mut lowest_point = Vec3::zero();
+        let mut count = 0;
+        for (&v, &s) in points.iter().zip(&s) {
+            if (s - min).abs() < f32::EPSILON {
+                lowest_point += v;
+                count += 1;
+            }
+        }
+        lowest_point /= count as f32;
+        if radius > 0.0 && up.length_squared() != 0.0 {
+            lowest_point -= up.normalize() * radius;
+        }
+        lowest_point
+    }
+
+    /// Returns the volume of this shape, its centroid and its inertia tensor per
+    /// unit of density, around the origin of the body.
+    fn mass_properties(&self) -> (f32, Vec3, Mat3) {
+        let cuboid = |e: Vec3| {
+            let (x, y, z) = (e.x() * 2.0, e.y() * 2.0, e.z() * 2.0);
+            let volume = x * y * z;
+            let inertia = Vec3::new(y * y + z * z, x * x + z * z, x * x + y * y) * (volume / 12.0);
+            (volume, inertia)
+        };
+        let (volume, inertia) = match self.kind {
+            ShapeKind::ConvexHull(ref hull) => {
+                let (volume, centroid, inertia) = hull.mass_properties();
+                return self.offset_mass_properties(volume, centroid, inertia);
+            }
+            ShapeKind::Cuboid(_) => cuboid(self.extent()),
+            ShapeKind::Sphere(radius) => {
+                let volume = 4.0 / 3.0 * PI * radius * radius * radius;
+                (volume, Vec3::splat(0.4 * volume * radius * radius))
+            }
+            ShapeKind::Capsule { height, radius } => {
+                // a cylinder between the centers of the caps, plus both caps as one sphere
+                let r2 = radius * radius;
+                let cylinder = PI * r2 * height;
+                let sphere = 4.0 / 3.0 * PI * r2 * radius;
+                let x = cylinder * (3.0 * r2 + height * height) / 12.0
+                    + sphere * (0.4 * r2 + height * height * 0.25 + 0.375 * height * radius);
+                let y = cylinder * r2 * 0.5 + sphere * 0.4 * r2;
+                (cylinder + sphere, Vec3::new(x, y, x))
+            }
+            ShapeKind::Cylinder { height, radius } => {
+                let r2 = radius * radius;
+                let volume = PI * r2 * height;
+                let x = volume * (3.0 * r2 + height * height) / 12.0;
+                (volume, Vec3::new(x, volume * r2 * 0.5, x))
+            }
+            ShapeKind::TriMesh(_) | ShapeKind::HeightField(_) => {
+                return (0.0, Vec3::zero(), Mat3::zero())
+            }
+        };
+
+        let inertia = Mat3::from_cols(
+            Vec3::new(inertia.x(), 0.0, 0.0),
+            Vec3::new(0.0, inertia.y(), 0.0),
+            Vec3::new(0.0, 0.0, inertia.z()),
+        );
+        self.offset_mass_properties(volume, Vec3::zero(), inertia)
+    }
+
+    /// Moves an inertia tensor around a local centroid to the origin of the body.
+    fn offset_mass_properties(
+        &self,
+        volume: f32,
+        centroid: Vec3,
+        inertia: Mat3,
+    ) -> (f32, Vec3, Mat3) {
+        let rotation = Mat3::from_cols(
+            self.local.x_axis().truncate().into(),
+            self.local.y_axis().truncate().into(),
+            self.local.z_axis().truncate().into(),
+        );
+        // parallel axis theorem for the offset of the shape
+        let centroid = self.local.transform_point3(centroid);
+        (
+            volume,
+            centroid,
+            rotation * inertia * rotation.transpose() + parallel_axis(centroid) * volume,
         )
     }
 }
 
+/// Returns the inertia tensor of a unit mass at `offset`.
+fn parallel_axis(offset: Vec3) -> Mat3 {
+    Mat3::identity() * offset.length_squared()
+        - Mat3::from_cols(
+            offset * offset.x(),
+            offset * offset.y(),
+            offset * offset.z(),
+        )
+}
+
 impl From<Size3> for Shape {
     fn from(size: Size3) -> Self {
         Self::new(size)
@@ -640,11 +1386,14 @@ impl<B: JointBehaviour> Joint<B> {
 pub struct RigidBody {
     /// Current position of this rigid body.
     pub position: Vec3,
+    prev_position: Vec3,
     lowest_position: Vec3,
     /// Current rotation of this rigid body.
     ///
-    /// NOTE: collisions checks may or may not be broken if this is not a multiple of 90 degrees.
+    /// NOTE: the semikinematic collision response assumes this is a multiple of 90 degrees,
+    /// dynamic bodies may rotate freely.
     pub rotation: Quat,
+    prev_rotation: Quat,
     /// Current linear velocity of this rigid body.
     pub linvel: Vec3,
     prev_linvel: Vec3,
@@ -661,14 +1410,29 @@ pub struct RigidBody {
     ///
     /// Defaults to `f32::INFINITY`.
     pub ang_term: f32,
-    /// The status, i.e. static or semikinematic.
+    /// The physics material, i.e. friction and restitution.
+    pub material: PhysicsMaterial,
+    /// The status, i.e. static, semikinematic or dynamic.
     ///
     /// Affects how forces and collisions affect this rigid body.
     pub status: Status,
     mass: f32,
     inv_mass: f32,
+    inv_inertia: Mat3,
+    center: Vec3,
     active: bool,
     sensor: bool,
+    ccd: bool,
+    #[property(ignore)]
+    #[serde(skip)]
+    target: Option<(Vec3, Quat)>,
+    targeted: bool,
+    #[property(ignore)]
+    #[serde(skip)]
+    carrier: Option<Entity>,
+    #[property(ignore)]
+    #[serde(skip)]
+    surface: Option<f32>,
 }
 
 impl RigidBody {
@@ -676,8 +1440,10 @@ impl RigidBody {
     pub fn new(mass: Mass) -> Self {
         Self {
             position: Vec3::zero(),
+            prev_position: Vec3::zero(),
             lowest_position: Vec3::zero(),
             rotation: Quat::identity(),
+            prev_rotation: Quat::identity(),
             linvel: Vec3::zero(),
             prev_linvel: Vec3::zero(),
             terminal: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
@@ -686,23 +1452,33 @@ impl RigidBody {
             angvel: Quat::identity(),
             prev_angvel: Quat::identity(),
             ang_term: f32::INFINITY,
+            material: PhysicsMaterial::default(),
             status: Status::Semikinematic,
             mass: mass.scalar(),
             inv_mass: mass.inverse(),
+            inv_inertia: Mat3::zero(),
+            center: Vec3::zero(),
             active: true,
             sensor: false,
+            ccd: false,
+            target: None,
+            targeted: false,
+            carrier: None,
+            surface: None,
         }
     }
 
     /// Returns a `RigidBody` identical to this one, but with the position set to a new one.
     pub fn with_position(mut self, position: Vec3) -> Self {
         self.position = position;
+        self.prev_position = position;
         self
     }
 
     /// Returns a `RigidBody` identical to this one, but with the rotation set to a new one.
     pub fn with_rotation(mut self, rotation: Quat) -> Self {
         self.rotation = rotation;
+        self.prev_rotation = rotation;
         self
     }
 
@@ -736,6 +1512,24 @@ impl RigidBody {
         self
     }
 
+    /// Returns a `RigidBody` identical to this one, but with the material set to a new one.
+    pub fn with_material(mut self, material: PhysicsMaterial) -> Self {
+        self.material = material;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the restitution of its material set to a new one.
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.material.restitution = restitution;
+        self
+    }
+
+    /// Returns a `RigidBody` identical to this one, but with the friction of its material set to a new one.
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.material.friction = friction;
+        self
+    }
+
     /// Returns a `RigidBody` identical to this one, but with the status set to a new one.
     pub fn with_status(mut self, status: Status) -> Self {
         self.status = status;
@@ -749,11 +1543,20 @@ impl RigidBody {
     }
 
     /// Returns a `RigidBody` identical to this one, but with the sensor flag set to a new one.
+    ///
+    /// All the shapes of a sensor body are triggers, see `Shape::with_sensor`.
     pub fn with_sensor(mut self, sensor: bool) -> Self {
         self.sensor = sensor;
         self
     }
 
+    /// Returns a `RigidBody` identical to this one, but with continuous collision detection
+    /// enabled or disabled.
+    pub fn with_ccd(mut self, ccd: bool) -> Self {
+        self.ccd = ccd;
+        self
+    }
+
     /// Applies an impulse to the `RigidBody`s linear velocity.
     pub fn apply_linear_impulse(&mut self, impulse: Vec3) {
         self.linvel += impulse * self.inv_mass;
@@ -766,6 +1569,14 @@ impl RigidBody {
         self.angvel *= Quat::from_axis_angle(axis, angle);
     }
 
+    /// Moves a kinematic `RigidBody` to a target position and rotation over the next step.
+    ///
+    /// The velocities are set to reach the target, so that pushed and carried
+    /// bodies move along smoothly, and reset afterwards unless a new target is set.
+    pub fn move_to(&mut self, position: Vec3, rotation: Quat) {
+        self.target = Some((position, rotation));
+    }
+
     /// Applies a force to the `RigidBody`s acceleration accumulator.
     pub fn apply_force(&mut self, force: Vec3) {
         self.accumulator += force * self.inv_mass;
@@ -781,6 +1592,11 @@ impl RigidBody {
         self.sensor
     }
 
+    /// Returns true if continuous collision detection is enabled.
+    pub fn is_ccd(&self) -> bool {
+        self.ccd
+    }
+
     /// Gets the mass
     pub fn mass(&self) -> f32 {
         self.mass
@@ -791,6 +1607,20 @@ impl RigidBody {
         self.inv_mass
     }
 
+    /// Gets the inverse inertia tensor in local space, derived from the `Shape`s of a dynamic body.
+    ///
+    /// It is zero for static and semikinematic bodies, which never rotate from collisions.
+    pub fn inverse_inertia(&self) -> Mat3 {
+        self.inv_inertia
+    }
+
+    /// Gets the center of mass in world space, derived from the `Shape`s of a dynamic body.
+    ///
+    /// It is the position for static and semikinematic bodies.
+    pub fn center_of_mass(&self) -> Vec3 {
+        self.position + self.rotation * self.center
+    }
+
     /// Sets the active flag.
     pub fn set_active(&mut self, active: bool) {
         self.active = active;
@@ -801,6 +1631,15 @@ impl RigidBody {
         self.sensor = sensor;
     }
 
+    /// Enables or disables continuous collision detection.
+    ///
+    /// A body with continuous collision detection is swept from its previous to its
+    /// current position, so that it stops at the first shape in its way instead of
+    /// passing through it.  The rotation of the body is not swept.
+    pub fn set_ccd(&mut self, ccd: bool) {
+        self.ccd = ccd;
+    }
+
     /// Sets the mass.
     pub fn set_mass(&mut self, mass: Mass) {
         self.mass = mass.scalar();
@@ -824,91 +1663,483 @@ impl RigidBody {
         let angvel = Quat::from_axis_angle(axis, angle);
         prev_angvel * angvel.conjugate()
     }
+
+    fn hash_state<H: Hasher>(&self, state: &mut H) {
+        let vectors = [
+            self.position,
+            self.prev_position,
+            self.lowest_position,
+            self.linvel,
+            self.prev_linvel,
+            self.terminal,
+            self.accumulator,
+            self.dynamic_acc,
+            self.center,
+        ];
+        for vector in vectors.iter() {
+            state.write_u32(vector.x().to_bits());
+            state.write_u32(vector.y().to_bits());
+            state.write_u32(vector.z().to_bits());
+        }
+        let quats = [
+            self.rotation,
+            self.prev_rotation,
+            self.angvel,
+            self.prev_angvel,
+        ];
+        for quat in quats.iter() {
+            let quat: [f32; 4] = (*quat).into();
+            for scalar in quat.iter() {
+                state.write_u32(scalar.to_bits());
+            }
+        }
+        let scalars = [
+            self.ang_term,
+            self.material.friction,
+            self.material.restitution,
+            self.mass,
+            self.inv_mass,
+        ];
+        for scalar in scalars.iter().chain(&self.inv_inertia.to_cols_array()) {
+            state.write_u32(scalar.to_bits());
+        }
+        self.material.friction_combine.hash(state);
+        self.material.restitution_combine.hash(state);
+        self.status.hash(state);
+        self.active.hash(state);
+        self.sensor.hash(state);
+        self.ccd.hash(state);
+        self.target
+            .map(|(position, rotation)| {
+                let rotation: [f32; 4] = rotation.into();
+                [
+                    position.x().to_bits(),
+                    position.y().to_bits(),
+                    position.z().to_bits(),
+                    rotation[0].to_bits(),
+                    rotation[1].to_bits(),
+                    rotation[2].to_bits(),
+                    rotation[3].to_bits(),
+                ]
+            })
+            .hash(state);
+        self.targeted.hash(state);
+        self.carrier.hash(state);
+        self.surface.map(f32::to_bits).hash(state);
+    }
 }
 
+/// A contact point of a `Manifold`.
 #[derive(Debug, Clone, Copy)]
 pub struct Contact {
+    /// The position in world space.
     pub position: Vec3,
+    /// How deep the shapes overlap at this point, negative like the penetration of the manifold.
     pub penetration: f32,
+    /// The impulse the solver applied along the normal at this point.
+    ///
+    /// Only contacts with dynamic bodies are solved with impulses, semikinematic
+    /// bodies are pushed apart and report zero.  The `Manifold` events are sent before
+    /// the solver runs, so only the manifolds of the `CollisionStarted` and
+    /// `CollisionOngoing` events carry impulses.
+    pub impulse: f32,
 }
 
 /// The manifold, representing detailed data on a collision between two `RigidBody`s.
 ///
-/// Usable as an event.
+/// Usable as an event.  The `Manifold` events are sent by the narrow phase, before
+/// the solver runs, so the impulses of their contacts are always zero.
 #[derive(Debug, Clone)]
 pub struct Manifold {
     /// The first entity.
     pub body1: Entity,
     /// The second entity.
     pub body2: Entity,
+    /// Every pair of shapes in contact, as the `Shape` of the first entity and the
+    /// `Shape` of the second one, e.g. to tell which hitbox hit which hurtbox.
+    ///
+    /// The deepest pair, which the normal and the penetration belong to, comes first.
+    pub shapes: SmallVec<[(Entity, Entity); 1]>,
     /// The normals, relative to the second entity.
     pub normal: Vec3,
+    /// The penetration along the normal, negative while the shapes overlap.
+    ///
+    /// Unlike in 2d, where it is positive, the depth of the overlap is `-penetration`.
     pub penetration: f32,
+    /// The contact points of this manifold.
     pub contacts: SmallVec<[Contact; 8]>,
 }
 
+/// Builds the collider of the shape `e` of the body `entity`.
+fn shape_collider(
+    entity: Entity,
+    body: &RigidBody,
+    e: Entity,
+    shape: &Shape,
+    geometry: Geometry,
+) -> Obb {
+    let mut transform = Transform::from_translation(body.position);
+    transform.rotation = body.rotation;
+    let collider = Obb::new(
+        body.status,
+        entity,
+        e,
+        Transform::from_matrix(shape.local),
+        transform,
+        shape.extent(),
+        geometry,
+        shape.layers,
+    )
+    .with_sensor(shape.sensor || body.sensor);
+    if body.ccd {
+        collider.with_motion(body.position - body.prev_position)
+    } else {
+        collider
+    }
+}
+
+/// Marks the bodies and shapes that were added, changed or removed in the current frame,
+/// so that the broad phase refreshes their colliders.
+fn mark_changes(
+    broad: &mut BroadPhase,
+    bodies: &mut Query<(Entity, Changed<RigidBody>)>,
+    children: &mut Query<(Entity, &RigidBody, Changed<Children>)>,
+    shapes: &mut Query<(Entity, Changed<Shape>, &Parent)>,
+) {
+    for (entity, _) in &mut bodies.iter() {
+        broad.mark_changed(entity);
+    }
+    for (entity, _, _) in &mut children.iter() {
+        broad.mark_changed(entity);
+    }
+    for (entity, _, parent) in &mut shapes.iter() {
+        broad.mark_reshaped(parent.0, entity);
+    }
+    for &entity in bodies.removed::<RigidBody>() {
+        broad.mark_removed(entity);
+    }
+    for &entity in shapes.removed::<Shape>() {
+        broad.mark_removed(entity);
+    }
+}
+
+/// Keeps the changes that happen after the physics stages for the next step, since
+/// change detection forgets them at the end of the frame.
+fn broad_phase_changes_system(
+    mut broad: ResMut<BroadPhase>,
+    mut bodies: Query<(Entity, Changed<RigidBody>)>,
+    mut children: Query<(Entity, &RigidBody, Changed<Children>)>,
+    mut shapes: Query<(Entity, Changed<Shape>, &Parent)>,
+) {
+    mark_changes(&mut broad, &mut bodies, &mut children, &mut shapes);
+}
+
 pub fn broad_phase_system(
-    mut commands: Commands,
+    mut broad: ResMut<BroadPhase>,
+    timestep: Res<FixedTimestep>,
     mut query: Query<(Entity, &RigidBody, &Children)>,
-    query2: Query<&Shape>,
+    mut changed_bodies: Query<(Entity, Changed<RigidBody>)>,
+    mut changed_children: Query<(Entity, &RigidBody, Changed<Children>)>,
+    mut changed_shapes: Query<(Entity, Changed<Shape>, &Parent)>,
+    shapes: Query<&Shape>,
 ) {
-    let mut colliders = Vec::new();
-    for (entity, body, children) in &mut query.iter() {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    mark_changes(
+        &mut broad,
+        &mut changed_bodies,
+        &mut changed_children,
+        &mut changed_shapes,
+    );
+    let changes = broad.take_changes();
+
+    // an empty broad phase is filled with every collider at once
+    if broad.is_empty() {
+        let mut colliders = Vec::new();
+        for (entity, body, children) in &mut query.iter() {
+            for &e in children.iter() {
+                if let Ok(shape) = shapes.get::<Shape>(e) {
+                    colliders.push(shape_collider(entity, body, e, &shape, shape.geometry()));
+                }
+            }
+        }
+        // keep the trees independent from the order of the query
+        colliders.sort_by_key(|collider| collider.shape);
+        *broad = BroadPhase::with_colliders(colliders);
+        return;
+    }
+
+    // keep the trees independent from the order of the hash sets
+    let mut removed: Vec<Entity> = changes.removed.into_iter().collect();
+    removed.sort();
+    for entity in removed {
+        broad.remove(entity);
+        broad.remove_body(entity);
+    }
+
+    let mut bodies: Vec<Entity> = changes.bodies.into_iter().collect();
+    bodies.sort();
+    for entity in bodies {
+        let mut old = broad.remove_body(entity);
+        let (body, children) = match (
+            query.get::<RigidBody>(entity),
+            query.get::<Children>(entity),
+        ) {
+            (Ok(body), Ok(children)) => (body, children),
+            _ => continue,
+        };
         for &e in children.iter() {
-            if let Ok(shape) = query2.get::<Shape>(e) {
-                let mut transform = Transform::from_translation(body.position);
-                transform.rotation = body.rotation;
-                let collider = Obb::new(
-                    body.status,
-                    entity,
-                    Transform::from_matrix(shape.local),
-                    transform,
-                    shape.extent(),
-                );
-                colliders.push(collider);
+            if let Ok(shape) = shapes.get::<Shape>(e) {
+                // the geometry is only cloned again if the shape changed
+                let geometry = match old.iter().position(|collider| collider.shape == e) {
+                    Some(index) if !changes.shapes.contains(&e) => old.swap_remove(index).geometry,
+                    _ => shape.geometry(),
+                };
+                broad.insert(shape_collider(entity, &body, e, &shape, geometry));
             }
         }
     }
-    let broad = BroadPhase::with_colliders(colliders);
-    commands.insert_resource(broad);
 }
 
+fn narrow_phase_system(
+    mut manifolds: ResMut<Events<Manifold>>,
+    timestep: Res<FixedTimestep>,
+    broad: Res<BroadPhase>,
+    mut triggers: ResMut<Triggers>,
+    mut entered: ResMut<Events<TriggerEntered>>,
+    mut exited: ResMut<Events<TriggerExited>>,
+    bodies: Query<Mut<RigidBody>>,
+) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
+    // the first impact of every swept body, over all of its pairs
+    let groups = broad.groups();
+    let mut impacts = HashMap::new();
+    for &(collider1, collider2) in groups.iter().flatten() {
+        if collider1.sensor || collider2.sensor {
+            continue;
+        }
+        if let Some((time, _)) = collision::time_of_impact(collider1, collider2) {
+            for collider in &[collider1, collider2] {
+                if collider.motion != Vec3::zero() {
+                    let first = impacts.entry(collider.body).or_insert(time);
+                    *first = time.min(*first);
+                }
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    let mut inside = Vec::new();
+    for group in &groups {
+        let mut result = None;
+        for &(collider1, collider2) in group {
+            // triggers are only tested for overlaps and never reach the solver
+            if collider1.sensor || collider2.sensor {
+                if collision::overlaps(collider1, collider2) {
+                    for &(trigger, other) in &[(collider1, collider2), (collider2, collider1)] {
+                        if trigger.sensor {
+                            inside.push(TriggerContact {
+                                trigger: trigger.shape,
+                                trigger_body: trigger.body,
+                                body: other.body,
+                            });
+                        }
+                    }
+                }
+                continue;
+            }
+            // swept bodies are moved back to their first impact, so every pair of
+            // them collides where they end up, not where they were swept to
+            let manifold = match (impacts.get(&collider1.body), impacts.get(&collider2.body)) {
+                (None, None) => collision::collide(collider1, collider2),
+                (time1, time2) => collision::collide(
+                    &collider1.rewound(time1.copied().unwrap_or(1.0)),
+                    &collider2.rewound(time2.copied().unwrap_or(1.0)),
+                ),
+            };
+            if let Some(manifold) = manifold {
+                result = Some(collision::merge(result, manifold));
+            }
+        }
+        if let Some(manifold) = result {
+            results.push(manifold);
+        }
+    }
+    for (body, time) in impacts {
+        if let Ok(mut body) = bodies.get_mut::<RigidBody>(body) {
+            let rewind = (body.position - body.prev_position) * (1.0 - time);
+            body.position -= rewind;
+            body.lowest_position -= rewind;
+        }
+    }
+
+    if timestep.is_deterministic() {
+        results.sort_by_key(|manifold| (manifold.body1, manifold.body2));
+    }
+    for manifold in results {
+        manifolds.send(manifold);
+    }
+
+    let (started, stopped) = triggers.update(inside);
+    for contact in started {
+        entered.send(TriggerEntered(contact));
+    }
+    for contact in stopped {
+        exited.send(TriggerExited(contact));
+    }
+}
+
+/// A `RigidBody` inside a trigger, i.e. a `Shape` with the sensor flag set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TriggerContact {
+    /// The `Shape` of the trigger.
+    pub trigger: Entity,
+    /// The `RigidBody` the trigger belongs to.
+    pub trigger_body: Entity,
+    /// The `RigidBody` inside the trigger.
+    pub body: Entity,
+}
+
+/// Sent when a `RigidBody` enters a trigger.
+#[derive(Debug, Clone, Copy)]
+pub struct TriggerEntered(pub TriggerContact);
+
+/// Sent when a `RigidBody` leaves a trigger.
+#[derive(Debug, Clone, Copy)]
+pub struct TriggerExited(pub TriggerContact);
+
+/// The triggers every `RigidBody` is currently inside, as of the last narrow phase.
+#[derive(Debug, Default)]
+pub struct Triggers {
+    /// Sorted, to look up and compare contacts between steps.
+    contacts: Vec<TriggerContact>,
+    inside: HashMap<Entity, SmallVec<[Entity; 4]>>,
+}
+
+impl Triggers {
+    /// Returns the triggers `body` is inside.
+    pub fn inside(&self, body: Entity) -> &[Entity] {
+        self.inside
+            .get(&body)
+            .map_or(&[][..], |triggers| &triggers[..])
+    }
+
+    /// Returns whether `body` is inside `trigger`.
+    pub fn is_inside(&self, body: Entity, trigger: Entity) -> bool {
+        self.inside(body).contains(&trigger)
+    }
+
+    /// Iterates over all the bodies inside a trigger.
+    pub fn iter(&self) -> impl Iterator<Item = &TriggerContact> {
+        self.contacts.iter()
+    }
+
+    /// Replaces the contacts of the last step, returning the ones that started and stopped.
+    fn update(
+        &mut self,
+        mut contacts: Vec<TriggerContact>,
+    ) -> (Vec<TriggerContact>, Vec<TriggerContact>) {
+        // a body can overlap a trigger with more than one of its shapes
+        contacts.sort();
+        contacts.dedup();
+        let started = contacts
+            .iter()
+            .filter(|contact| self.contacts.binary_search(contact).is_err())
+            .copied()
+            .collect();
+        let stopped = self
+            .contacts
+            .iter()
+            .filter(|contact| contacts.binary_search(contact).is_err())
+            .copied()
+            .collect();
+
+        self.inside.clear();
+        for contact in &contacts {
+            self.inside
+                .entry(contact.body)
+                .or_insert_with(SmallVec::new)
+                .push(contact.trigger);
+        }
+        self.contacts = contacts;
+        (started, stopped)
+    }
+}
+
+/// Sent when two `RigidBody`s start touching, with their first manifold.
+#[derive(Debug, Clone)]
+pub struct CollisionStarted(pub Manifold);
+
+/// Sent for every step in which two `RigidBody`s keep touching, with their current manifold.
+#[derive(Debug, Clone)]
+pub struct CollisionOngoing(pub Manifold);
+
+/// Sent when two `RigidBody`s stop touching, with their last manifold.
+#[derive(Debug, Clone)]
+pub struct CollisionEnded(pub Manifold);
+
+/// Keeps track of the pairs of `RigidBody`s in contact across steps, to turn the
+/// manifolds of every step into `CollisionStarted`, `CollisionOngoing` and
+/// `CollisionEnded` events.
 #[derive(Default)]
-pub struct NarrowPhase {
-    set: HashSet<[Entity; 2]>,
+pub struct ContactTracker {
+    contacts: HashMap<(Entity, Entity), Manifold>,
 }
 
-impl NarrowPhase {
+impl ContactTracker {
     pub fn system(self, res: &mut Resources) -> Box<dyn System> {
-        let system = narrow_phase_system.system();
+        let system = contact_tracker_system.system();
         res.insert_local(system.id(), self);
         system
     }
 }
 
-fn narrow_phase_system(
-    mut state: Local<NarrowPhase>,
-    mut manifolds: ResMut<Events<Manifold>>,
-    broad: Res<BroadPhase>,
+fn contact_tracker_system(
+    mut tracker: Local<ContactTracker>,
+    timestep: Res<FixedTimestep>,
+    solved: Res<Solved>,
+    mut started: ResMut<Events<CollisionStarted>>,
+    mut ongoing: ResMut<Events<CollisionOngoing>>,
+    mut ended: ResMut<Events<CollisionEnded>>,
 ) {
-    state.set.clear();
-    let narrow = broad.iter();
-    for (collider1, collider2) in narrow {
-        if collider1.body == collider2.body {
-            continue;
-        }
+    if !timestep.is_stepping() {
+        return;
+    }
 
-        if state.set.contains(&[collider2.body, collider1.body]) {
-            continue;
+    let tracker = &mut *tracker;
+    let mut contacts = HashMap::new();
+    for manifold in &solved.0 {
+        let pair = if manifold.body1 < manifold.body2 {
+            (manifold.body1, manifold.body2)
+        } else {
+            (manifold.body2, manifold.body1)
+        };
+        if tracker.contacts.remove(&pair).is_some() {
+            ongoing.send(CollisionOngoing(manifold.clone()));
+        } else {
+            started.send(CollisionStarted(manifold.clone()));
         }
-        state.set.insert([collider1.body, collider2.body]);
+        contacts.insert(pair, manifold.clone());
+    }
 
-        if let Some(manifold) = collision::box_to_box(&collider1, &collider2) {
-            manifolds.send(manifold);
-        }
+    // the pairs left over didn't touch in this step, sorted to not depend on the hash map
+    let mut left: Vec<_> = tracker.contacts.drain().collect();
+    left.sort_by_key(|(pair, _)| *pair);
+    for (_, manifold) in left {
+        ended.send(CollisionEnded(manifold));
     }
+    tracker.contacts = contacts;
 }
 
+/// The manifolds of the last step, with the impulses applied by the solver.
+#[derive(Default)]
+struct Solved(Vec<Manifold>);
+
 #[derive(Default)]
 pub struct Solver {
     reader: EventReader<Manifold>,
@@ -924,20 +2155,51 @@ impl Solver {
 
 fn solve_system(
     mut solver: Local<Solver>,
-    time: Res<Time>,
+    timestep: Res<FixedTimestep>,
     manifolds: Res<Events<Manifold>>,
+    mut solved: ResMut<Solved>,
+    gravity: Res<GlobalGravity>,
     up: Res<GlobalUp>,
     step: Res<GlobalStep>,
     ang_tol: Res<AngularTolerance>,
+    resting: Res<RestingSpeed>,
     query: Query<(Mut<RigidBody>, Option<Mut<Up>>)>,
+    mut grounded: Query<(Entity, Mut<Grounded>)>,
 ) {
-    let delta_time = time.delta.as_secs_f32();
+    if !timestep.is_stepping() {
+        return;
+    }
 
+    let delta_time = timestep.step();
+    // contacts slower than this rest, bodies resting on each other gain about
+    // half of the speed from gravity every step
+    let resting = resting.0.max(gravity.0.length() * delta_time * 2.0);
+
+    let mut results = Vec::new();
+    let mut contacts = Vec::new();
+    let mut grounds = HashMap::new();
     for manifold in solver.reader.iter(&manifolds) {
         let a = query.get::<RigidBody>(manifold.body1).unwrap();
         let b = query.get::<RigidBody>(manifold.body2).unwrap();
 
-        if a.sensor || b.sensor {
+        // bodies resting on top of another one are grounded on it
+        if grounded.get::<Grounded>(manifold.body1).is_ok() {
+            let up = query.get::<Up>(manifold.body1).map_or(up.0, |up| up.0);
+            if rests_on(up, -manifold.normal, ang_tol.0) {
+                let ground = (manifold.body2, -manifold.normal, b.linvel);
+                touch_ground(&mut grounds, manifold.body1, ground, up);
+            }
+        }
+        if grounded.get::<Grounded>(manifold.body2).is_ok() {
+            let up = query.get::<Up>(manifold.body2).map_or(up.0, |up| up.0);
+            if rests_on(up, manifold.normal, ang_tol.0) {
+                let ground = (manifold.body1, manifold.normal, a.linvel);
+                touch_ground(&mut grounds, manifold.body2, ground, up);
+            }
+        }
+
+        if a.status == Status::Dynamic || b.status == Status::Dynamic {
+            contacts.push(manifold.clone());
             continue;
         }
 
@@ -960,13 +2222,28 @@ fn solve_system(
         } else {
             None
         };
+
+        let friction = a.material.combine_friction(&b.material);
+        let restitution = a.material.combine_restitution(&b.material);
+        let approach = (a.linvel - b.linvel).dot(manifold.normal);
+
+        // semikinematic bodies resting on top of kinematic bodies are carried along
+        let carried = (
+            b.status == Status::Kinematic && rests_on(up.0, -manifold.normal, ang_tol.0),
+            a.status == Status::Kinematic && rests_on(up.0, manifold.normal, ang_tol.0),
+        );
         mem::drop(a);
         mem::drop(b);
 
         let mut a = query.get_mut::<RigidBody>(manifold.body1).unwrap();
         match a.status {
-            Status::Static => {}
+            Status::Static | Status::Dynamic | Status::Kinematic => {}
             Status::Semikinematic => {
+                if carried.0 {
+                    a.carrier = Some(manifold.body2);
+                }
+                a.surface = Some(a.surface.map_or(friction, |surface| surface.max(friction)));
+
                 if let Ok(mut local_up) = query.get_mut::<Up>(manifold.body1) {
                     let angle = up.0.dot(manifold.normal).acos();
                     if angle >= 0.0 && angle < ang_tol.0 {
@@ -982,8 +2259,8 @@ fn solve_system(
                     if v.signum() == d.signum() {
                         // nothing
                     } else {
-                        let c = Vec3::splat(1.0) - manifold.normal.abs();
-                        a.linvel *= c;
+                        a.linvel =
+                            bounce(a.linvel, manifold.normal, approach, restitution, resting);
                         a.position += d;
                     }
                 } else {
@@ -1011,8 +2288,8 @@ fn solve_system(
                         if v.signum() == d.signum() {
                             // nothing
                         } else {
-                            let c = Vec3::splat(1.0) - manifold.normal.abs();
-                            a.linvel *= c;
+                            a.linvel =
+                                bounce(a.linvel, manifold.normal, approach, restitution, resting);
                             a.position += d;
                         }
                     }
@@ -1023,8 +2300,13 @@ fn solve_system(
 
         let mut b = query.get_mut::<RigidBody>(manifold.body2).unwrap();
         match b.status {
-            Status::Static => {}
+            Status::Static | Status::Dynamic | Status::Kinematic => {}
             Status::Semikinematic => {
+                if carried.1 {
+                    b.carrier = Some(manifold.body1);
+                }
+                b.surface = Some(b.surface.map_or(friction, |surface| surface.max(friction)));
+
                 if let Ok(mut local_up) = query.get_mut::<Up>(manifold.body2) {
                     let angle = up.0.dot(manifold.normal).acos();
                     if angle >= 0.0 && angle < ang_tol.0 {
@@ -1040,8 +2322,8 @@ fn solve_system(
                     if v.signum() == d.signum() {
                         // nothing
                     } else {
-                        let c = Vec3::splat(1.0) - manifold.normal.abs();
-                        b.linvel *= c;
+                        b.linvel =
+                            bounce(b.linvel, -manifold.normal, approach, restitution, resting);
                         b.position += d;
                     }
                 } else {
@@ -1069,8 +2351,8 @@ fn solve_system(
                         if v.signum() == d.signum() {
                             // nothing
                         } else {
-                            let c = Vec3::splat(1.0) - manifold.normal.abs();
-                            b.linvel *= c;
+                            b.linvel =
+                                bounce(b.linvel, -manifold.normal, approach, restitution, resting);
                             b.position += d;
                         }
                     }
@@ -1078,9 +2360,60 @@ fn solve_system(
             }
         }
         mem::drop(b);
+
+        results.push(manifold.clone());
+    }
+
+    for (entity, mut ground) in &mut grounded.iter() {
+        ground.update(grounds.remove(&entity), delta_time);
+    }
+
+    dynamics::solve(&mut contacts, &query, resting);
+    results.extend(contacts);
+    solved.0 = results;
+}
+
+/// Returns the velocity of a semikinematic body after hitting a surface.
+///
+/// The velocity along the normal, which points into the surface, is removed.
+/// If the body approached the surface faster than `resting`, it bounces off
+/// with its approach speed scaled by the restitution.
+fn bounce(linvel: Vec3, normal: Vec3, approach: f32, restitution: f32, resting: f32) -> Vec3 {
+    let linvel = linvel * (Vec3::splat(1.0) - normal.abs());
+    if approach > resting {
+        linvel - normal * (approach * restitution)
+    } else {
+        linvel
     }
 }
 
+/// Keeps the ground of `body` with the normal closest to `up`.
+fn touch_ground(
+    grounds: &mut HashMap<Entity, (Entity, Vec3, Vec3)>,
+    body: Entity,
+    ground: (Entity, Vec3, Vec3),
+    up: Vec3,
+) {
+    let flatter = grounds
+        .get(&body)
+        .map_or(true, |&(_, normal, _)| ground.1.dot(up) > normal.dot(up));
+    if flatter {
+        grounds.insert(body, ground);
+    }
+}
+
+/// Returns true if a body rests on top of another one.
+///
+/// The normal points from the other body to the resting body.
+fn rests_on(up: Vec3, normal: Vec3, ang_tol: f32) -> bool {
+    if up.length_squared() == 0.0 {
+        return false;
+    }
+    let up = up.normalize();
+    let angle = up.dot(normal).max(-1.0).min(1.0).acos();
+    angle < ang_tol
+}
+
 pub struct PhysicsStep {
     skip: usize,
 }
@@ -1101,35 +2434,82 @@ impl Default for PhysicsStep {
 
 fn physics_step_system(
     mut state: Local<PhysicsStep>,
-    time: Res<Time>,
+    timestep: Res<FixedTimestep>,
     friction: Res<GlobalFriction>,
     gravity: Res<GlobalGravity>,
     global_up: Res<GlobalUp>,
-    mut query: Query<(Mut<RigidBody>, Option<(&Up, Mut<UpRotation>)>)>,
+    mut query: Query<(
+        Entity,
+        Mut<RigidBody>,
+        Option<(&Up, Mut<UpRotation>)>,
+        Option<&Children>,
+    )>,
+    shapes: Query<&Shape>,
 ) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
     if state.skip > 0 {
         state.skip -= 1;
         return;
     }
 
-    let delta_time = time.delta.as_secs_f32();
+    let delta_time = timestep.step();
+
+    let mut carriers = HashMap::new();
+    for (entity, mut body, local_up, children) in &mut query.iter() {
+        // resting static bodies are left untouched, so that the broad phase skips them
+        if body.status == Status::Static
+            && body.position == body.prev_position
+            && body.rotation == body.prev_rotation
+            && body.linvel == Vec3::zero()
+            && body.angvel == Quat::identity()
+            && body.accumulator == Vec3::zero()
+            && body.dynamic_acc == Vec3::zero()
+        {
+            continue;
+        }
+
+        body.prev_position = body.position;
+        body.prev_rotation = body.rotation;
 
-    for (mut body, local_up) in &mut query.iter() {
         if !body.active {
             continue;
         }
 
-        if !matches!(body.status, Status::Static) {
+        if matches!(body.status, Status::Semikinematic | Status::Dynamic) {
             body.accumulator += gravity.0;
         }
 
+        let mut target = None;
+        if body.status == Status::Kinematic {
+            if let Some((position, rotation)) = body.target.take() {
+                body.linvel = (position - body.position) / delta_time;
+                let mut delta = body.rotation.conjugate() * rotation;
+                if delta.w() < 0.0 {
+                    delta = -delta;
+                }
+                // the velocities only push and carry other bodies, the target pose is
+                // applied directly, as a rotation can't hold more than one turn per second
+                let (axis, angle) = delta.to_axis_angle();
+                body.angvel = Quat::from_axis_angle(axis, (angle / delta_time).min(2.0 * PI));
+                body.targeted = true;
+                target = Some((position, rotation));
+            } else if body.targeted {
+                body.linvel = Vec3::zero();
+                body.angvel = Quat::identity();
+                body.targeted = false;
+            }
+        }
+
         let linvel = body.linvel + body.accumulator * delta_time;
         let linvel = linvel + body.dynamic_acc;
         body.linvel = linvel;
         body.accumulator = Vec3::zero();
         body.dynamic_acc = Vec3::zero();
 
-        if matches!(body.status, Status::Semikinematic) {
+        if matches!(body.status, Status::Semikinematic | Status::Dynamic) {
             let vel = body.linvel;
             let limit = body.terminal;
             match vel.x().partial_cmp(&0.0) {
@@ -1167,6 +2547,36 @@ fn physics_step_system(
             }
         }
 
+        // the mass of dynamic bodies is spread evenly over the volume of all shapes
+        body.center = Vec3::zero();
+        body.inv_inertia = Mat3::zero();
+        if let (Status::Dynamic, Some(children)) = (body.status, children) {
+            let mut volume = 0.0;
+            let mut center = Vec3::zero();
+            let mut inertia = Mat3::zero();
+            for &child in children.iter() {
+                if let Ok(shape) = shapes.get::<Shape>(child) {
+                    let (v, c, i) = shape.mass_properties();
+                    volume += v;
+                    center += c * v;
+                    inertia = inertia + i;
+                }
+            }
+            if volume > 0.0 && body.inv_mass > 0.0 {
+                let center = center / volume;
+                // parallel axis theorem, from the origin of the body to its center of mass
+                let inertia = (inertia - parallel_axis(center) * volume) * (body.mass / volume);
+                body.center = center;
+                body.inv_inertia = if inertia.determinant() > 0.0 {
+                    inertia.inverse()
+                } else {
+                    Mat3::zero()
+                };
+            }
+        }
+
+        // dynamic bodies turn around their center of mass, which moves with the linear velocity
+        let center = body.rotation * body.center;
         let position = body.position + body.linvel * delta_time;
         body.position = position;
 
@@ -1185,36 +2595,92 @@ fn physics_step_system(
             let rotation = body.rotation * Quat::from_axis_angle(axis, angle);
             body.rotation = rotation.normalize();
         }
+        let turned = body.rotation * body.center;
+        body.position += center - turned;
+        if let Some((position, rotation)) = target {
+            body.position = position;
+            body.rotation = rotation;
+        }
 
+        if body.status == Status::Kinematic {
+            let rotation = body.rotation * body.prev_rotation.conjugate();
+            carriers.insert(entity, (body.prev_position, body.position, rotation));
+        }
+
+        // touching surfaces slow semikinematic bodies down more or less than the default material
+        let damping = match body.surface.take() {
+            Some(surface) => {
+                let scale = surface / PhysicsMaterial::default().friction;
+                friction.0.powf(scale)
+            }
+            None => friction.0,
+        };
         match body.status {
             Status::Semikinematic => {
                 if body.linvel.x().abs() <= body.prev_linvel.x().abs() {
-                    *body.linvel.x_mut() *= friction.0;
+                    *body.linvel.x_mut() *= damping;
                 }
                 if body.linvel.y().abs() <= body.prev_linvel.y().abs() {
-                    *body.linvel.y_mut() *= friction.0;
+                    *body.linvel.y_mut() *= damping;
                 }
                 if body.linvel.z().abs() <= body.prev_linvel.z().abs() {
-                    *body.linvel.z_mut() *= friction.0;
+                    *body.linvel.z_mut() *= damping;
                 }
                 if body.angvel.w().abs() <= body.prev_angvel.w().abs() {
                     let (axis, mut angle) = body.angvel.to_axis_angle();
-                    angle *= friction.0;
+                    angle *= damping;
                     body.angvel = Quat::from_axis_angle(axis, angle);
                 }
             }
-            Status::Static => {}
+            Status::Static | Status::Dynamic | Status::Kinematic => {}
         }
         body.prev_linvel = body.linvel;
         body.prev_angvel = body.angvel;
+
+        // the lowest point along the up vector over all shapes
+        if let Some(children) = children {
+            let rotation = body.rotation;
+            let lowest = children
+                .iter()
+                .filter_map(|&child| shapes.get::<Shape>(child).ok())
+                .map(|shape| shape.lowest_point(rotation, global_up.0))
+                .min_by(|a, b| {
+                    let a = a.dot(global_up.0);
+                    let b = b.dot(global_up.0);
+                    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+                });
+            if let Some(lowest) = lowest {
+                body.lowest_position = body.position + lowest;
+            }
+        }
+    }
+
+    for (_, mut body, _, _) in &mut query.iter() {
+        // only bodies on a carrier are changed
+        let carrier = match body.carrier {
+            Some(carrier) => carrier,
+            None => continue,
+        };
+        body.carrier = None;
+        if let Some(&(from, to, rotation)) = carriers.get(&carrier) {
+            let position = to + rotation * (body.position - from);
+            let delta = position - body.position;
+            body.position += delta;
+            body.lowest_position += delta;
+        }
     }
 }
 
 pub fn joint_system<B: JointBehaviour>(
     mut commands: Commands,
+    timestep: Res<FixedTimestep>,
     mut query: Query<(Entity, Mut<Joint<B>>)>,
     bodies: Query<Mut<RigidBody>>,
 ) {
+    if !timestep.is_stepping() {
+        return;
+    }
+
     for (e, mut joint) in &mut query.iter() {
         let anchor = if let Ok(anchor) = bodies.get::<RigidBody>(joint.inner.body1) {
             anchor
@@ -1268,9 +2734,389 @@ pub fn joint_system<B: JointBehaviour>(
     }
 }
 
-pub fn sync_transform_system(mut query: Query<(&RigidBody, Mut<Transform>)>) {
+pub fn fixed_timestep_system(time: Res<Time>, mut timestep: ResMut<FixedTimestep>) {
+    let delta_time = time.delta.as_secs_f32();
+    timestep.advance(delta_time);
+}
+
+pub fn state_hash_system(
+    timestep: Res<FixedTimestep>,
+    mut hash: ResMut<StateHash>,
+    mut query: Query<(Entity, &RigidBody)>,
+) {
+    if !timestep.is_deterministic() || !timestep.is_stepping() {
+        return;
+    }
+
+    let mut bodies = Vec::new();
+    for (entity, body) in &mut query.iter() {
+        bodies.push((entity, *body));
+    }
+    bodies.sort_by_key(|(entity, _)| *entity);
+
+    // the default hasher always uses the same keys
+    let mut hasher = DefaultHasher::new();
+    for (entity, body) in bodies {
+        entity.hash(&mut hasher);
+        body.hash_state(&mut hasher);
+    }
+    hash.0 = hasher.finish();
+}
+
+pub fn sync_transform_system(
+    timestep: Res<FixedTimestep>,
+    mut query: Query<(&RigidBody, Mut<Transform>)>,
+) {
+    let alpha = timestep.alpha();
     for (body, mut transform) in &mut query.iter() {
-        transform.translation = body.position;
-        transform.rotation = body.rotation;
+        // interpolate between the last two steps
+        transform.translation = body.prev_position + (body.position - body.prev_position) * alpha;
+        transform.rotation = body.prev_rotation.lerp(body.rotation, alpha).normalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_timestep_accumulates() {
+        let mut timestep = FixedTimestep::from_step(0.25);
+        timestep.advance(0.6);
+        assert_eq!(timestep.steps(), 2);
+        assert!((timestep.alpha() - 0.4).abs() < 1.0e-4);
+        timestep.advance(0.2);
+        assert_eq!(timestep.steps(), 1);
+        timestep.advance(0.1);
+        assert_eq!(timestep.steps(), 0);
+        assert!(!timestep.is_stepping());
+    }
+
+    #[test]
+    fn fixed_timestep_drops_extra_steps() {
+        let mut timestep = FixedTimestep::from_step(0.25).with_max_steps(2);
+        timestep.advance(2.1);
+        assert_eq!(timestep.steps(), 2);
+        timestep.advance(0.0);
+        assert_eq!(timestep.steps(), 0);
+    }
+
+    #[test]
+    fn fixed_timestep_deterministic() {
+        let mut timestep = FixedTimestep::from_step(0.25).with_deterministic(true);
+        timestep.advance(0.0);
+        assert_eq!(timestep.steps(), 1);
+        timestep.advance(10.0);
+        assert_eq!(timestep.steps(), 1);
+        assert_eq!(timestep.alpha(), 1.0);
+    }
+
+    /// Returns an app that runs exactly one physics step per update.
+    pub(super) fn app() -> App {
+        let mut builder = App::build();
+        builder
+            .add_plugin(Physics3dPlugin::deterministic())
+            .add_resource(Time::default());
+        builder.app
+    }
+
+    /// Spawns a body with a single shape.
+    ///
+    /// Entity ids are random, so bodies are spawned with fixed ones to keep runs comparable.
+    pub(super) fn spawn(app: &mut App, id: u32, body: RigidBody, shape: Shape) -> Entity {
+        let entity = Entity::from_id(id);
+        let child = Entity::from_id(id + 1000);
+        app.world.spawn_as_entity(child, (shape, Parent(entity)));
+        app.world
+            .spawn_as_entity(entity, (body, Children::with(&[child])));
+        entity
+    }
+
+    pub(super) fn body(app: &App, entity: Entity) -> RigidBody {
+        *app.world.get::<RigidBody>(entity).unwrap()
+    }
+
+    /// Runs a small scene and returns the hash after every frame.
+    fn simulate(frames: usize) -> Vec<u64> {
+        let mut app = app();
+        let floor = RigidBody::new(Mass::Infinite).with_status(Status::Static);
+        spawn(
+            &mut app,
+            1,
+            floor,
+            Shape::new(Size3::new(100.0, 10.0, 100.0)),
+        );
+        let crate_ = RigidBody::new(Mass::Real(1.0))
+            .with_position(Vec3::new(0.0, 20.0, 0.0))
+            .with_rotation(Quat::from_rotation_z(0.3))
+            .with_linear_velocity(Vec3::new(10.0, 0.0, 0.0));
+        spawn(&mut app, 2, crate_, Shape::new(Size3::new(8.0, 8.0, 8.0)));
+        let ball = RigidBody::new(Mass::Real(2.0))
+            .with_position(Vec3::new(3.0, 40.0, 1.0))
+            .with_angular_velocity(Quat::from_rotation_y(1.0));
+        spawn(&mut app, 3, ball, Shape::sphere(4.0));
+
+        let mut hashes = Vec::new();
+        for _ in 0..frames {
+            app.update();
+            hashes.push(app.resources.get::<StateHash>().unwrap().0);
+        }
+        hashes
+    }
+
+    #[test]
+    fn deterministic_runs_have_equal_hashes() {
+        let first = simulate(120);
+        let second = simulate(120);
+        assert_eq!(first, second);
+        assert_ne!(first[0], first[first.len() - 1]);
+    }
+
+    #[test]
+    fn kinematic_body_reaches_target_rotation() {
+        let mut app = app();
+        let platform = RigidBody::new(Mass::Infinite).with_status(Status::Kinematic);
+        let platform = spawn(&mut app, 1, platform, Shape::new(Size3::new(4.0, 1.0, 4.0)));
+        // a quarter turn in a single step, far beyond one turn per second
+        let position = Vec3::new(1.0, 0.0, 0.0);
+        let rotation = Quat::from_rotation_y(PI * 0.5);
+        app.world
+            .get_mut::<RigidBody>(platform)
+            .unwrap()
+            .move_to(position, rotation);
+
+        // the first steps are skipped
+        for _ in 0..4 {
+            app.update();
+        }
+        let moved = body(&app, platform);
+        assert!((moved.position - position).length() < 1.0e-5);
+        assert!(
+            moved.rotation.dot(rotation).abs() > 0.9999,
+            "{:?}",
+            moved.rotation
+        );
+
+        // without a new target, the body stops where it is
+        app.update();
+        let stopped = body(&app, platform);
+        assert!((stopped.position - position).length() < 1.0e-5);
+        assert!(stopped.rotation.dot(rotation).abs() > 0.9999);
+        assert_eq!(stopped.linvel, Vec3::zero());
+    }
+
+    /// Shoots a small ball at a thin wall, returns where it ends up.
+    fn shoot(ccd: bool) -> f32 {
+        let mut app = app();
+        let wall = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Static)
+            .with_position(Vec3::new(10.0, 0.0, 0.0));
+        spawn(&mut app, 1, wall, Shape::new(Size3::new(0.1, 10.0, 10.0)));
+        // the ball travels about 5 units per step, more than ten times its size
+        let bullet = RigidBody::new(Mass::Real(1.0))
+            .with_position(Vec3::new(-3.0, 0.0, 0.0))
+            .with_linear_velocity(Vec3::new(300.0, 0.0, 0.0))
+            .with_ccd(ccd);
+        let bullet = spawn(&mut app, 2, bullet, Shape::sphere(0.2));
+        for _ in 0..20 {
+            app.update();
+        }
+        body(&app, bullet).position.x()
+    }
+
+    #[test]
+    fn ccd_stops_fast_bodies_at_thin_walls() {
+        assert!(shoot(false) > 10.05);
+        let x = shoot(true);
+        assert!(x < 10.0 && x > 9.0, "{}", x);
+    }
+
+    #[test]
+    fn body_rests_on_heightfield() {
+        let mut app = app();
+        let field = HeightField::new(vec![1.0; 25], 5, 5, Vec3::new(2.0, 1.0, 2.0)).unwrap();
+        let terrain = RigidBody::new(Mass::Infinite).with_status(Status::Static);
+        spawn(&mut app, 1, terrain, Shape::heightfield(field));
+        let ball = RigidBody::new(Mass::Real(1.0)).with_position(Vec3::new(0.5, 3.0, -0.3));
+        let ball = spawn(&mut app, 2, ball, Shape::sphere(0.5));
+
+        for _ in 0..120 {
+            app.update();
+        }
+        let ball = body(&app, ball);
+        assert!(
+            (ball.position.y() - 1.5).abs() < 0.05,
+            "{:?}",
+            ball.position
+        );
+        assert!((ball.position.x() - 0.5).abs() < 1.0e-3);
+        assert!(ball.linvel.length() < 0.2, "{:?}", ball.linvel);
+    }
+
+    fn count<T: Send + Sync + 'static>(app: &App, reader: &mut EventReader<T>) -> usize {
+        let events = app.resources.get::<Events<T>>().unwrap();
+        reader.iter(&events).count()
+    }
+
+    #[test]
+    fn collision_events_follow_contacts() {
+        let mut app = app();
+        let floor = RigidBody::new(Mass::Infinite).with_status(Status::Static);
+        spawn(
+            &mut app,
+            1,
+            floor,
+            Shape::new(Size3::new(100.0, 10.0, 100.0)),
+        );
+        let ball = RigidBody::new(Mass::Real(1.0)).with_position(Vec3::new(0.0, 6.5, 0.0));
+        let ball = spawn(&mut app, 2, ball, Shape::sphere(0.5));
+        let mut started = app
+            .resources
+            .get::<Events<CollisionStarted>>()
+            .unwrap()
+            .get_reader();
+        let mut ongoing = app
+            .resources
+            .get::<Events<CollisionOngoing>>()
+            .unwrap()
+            .get_reader();
+        let mut ended = app
+            .resources
+            .get::<Events<CollisionEnded>>()
+            .unwrap()
+            .get_reader();
+        let mut update = |app: &mut App| {
+            app.update();
+            (
+                count(app, &mut started),
+                count(app, &mut ongoing),
+                count(app, &mut ended),
+            )
+        };
+
+        let mut events = Vec::new();
+        for _ in 0..90 {
+            events.push(update(&mut app));
+        }
+        let landing = events
+            .iter()
+            .position(|&counts| counts != (0, 0, 0))
+            .expect("the body should land");
+        assert_eq!(events[landing], (1, 0, 0));
+        for &counts in &events[landing + 1..] {
+            assert_eq!(counts, (0, 1, 0));
+        }
+
+        // teleported away, the body stops touching the floor at once
+        app.world.get_mut::<RigidBody>(ball).unwrap().position = Vec3::new(0.0, 50.0, 0.0);
+        assert_eq!(update(&mut app), (0, 0, 1));
+        for _ in 0..5 {
+            assert_eq!(update(&mut app), (0, 0, 0));
+        }
+    }
+
+    #[test]
+    fn triggers_are_entered_and_exited_once() {
+        let mut app = app();
+        let trigger = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Static)
+            .with_position(Vec3::new(10.0, 0.0, 0.0));
+        let shape = Shape::new(Size3::new(2.0, 2.0, 2.0)).with_sensor(true);
+        let trigger = spawn(&mut app, 1, trigger, shape);
+        // the bullet passes through the trigger in a few steps
+        let bullet = RigidBody::new(Mass::Infinite)
+            .with_status(Status::Kinematic)
+            .with_linear_velocity(Vec3::new(60.0, 0.0, 0.0));
+        let bullet = spawn(&mut app, 2, bullet, Shape::sphere(0.5));
+
+        let mut entered_reader = app
+            .resources
+            .get::<Events<TriggerEntered>>()
+            .unwrap()
+            .get_reader();
+        let mut exited_reader = app
+            .resources
+            .get::<Events<TriggerExited>>()
+            .unwrap()
+            .get_reader();
+        let mut entered = Vec::new();
+        let mut exited = Vec::new();
+        let mut steps_inside = 0;
+        for _ in 0..40 {
+            app.update();
+            let events = app.resources.get::<Events<TriggerEntered>>().unwrap();
+            entered.extend(entered_reader.iter(&events).map(|event| event.0));
+            let events = app.resources.get::<Events<TriggerExited>>().unwrap();
+            exited.extend(exited_reader.iter(&events).map(|event| event.0));
+            let triggers = app.resources.get::<Triggers>().unwrap();
+            if triggers.inside(bullet) == [Entity::from_id(1001)] {
+                steps_inside += 1;
+            }
+        }
+
+        let contact = TriggerContact {
+            trigger: Entity::from_id(1001),
+            trigger_body: trigger,
+            body: bullet,
+        };
+        assert_eq!(entered, vec![contact]);
+        assert_eq!(exited, vec![contact]);
+        assert!(steps_inside > 1, "{}", steps_inside);
+        assert!(body(&app, bullet).position.x() > 11.5);
+    }
+
+    #[test]
+    fn grounded_bodies_can_jump_for_the_coyote_time() {
+        let mut app = app();
+        let floor = RigidBody::new(Mass::Infinite).with_status(Status::Static);
+        let floor = spawn(
+            &mut app,
+            1,
+            floor,
+            Shape::new(Size3::new(100.0, 10.0, 100.0)),
+        );
+        let ball = RigidBody::new(Mass::Real(1.0)).with_position(Vec3::new(0.0, 5.5, 0.0));
+        let body = spawn(&mut app, 2, ball, Shape::sphere(0.5));
+        app.world
+            .insert_one(body, Grounded::default().with_coyote_time(0.1))
+            .unwrap();
+        let grounded = |app: &App| *app.world.get::<Grounded>(body).unwrap();
+        let teleport = |app: &mut App, position| {
+            app.world.get_mut::<RigidBody>(body).unwrap().position = position;
+        };
+
+        for _ in 0..20 {
+            app.update();
+        }
+        let standing = grounded(&app);
+        assert_eq!(standing.ground(), Some(floor));
+        assert!((standing.normal() - Vec3::unit_y()).length() < 1.0e-3);
+        assert_eq!(standing.airtime(), 0.0);
+        assert!(standing.can_jump());
+
+        // teleported into the air, the body can still jump for a while
+        teleport(&mut app, Vec3::new(0.0, 50.0, 0.0));
+        app.update();
+        let falling = grounded(&app);
+        assert!(!falling.is_grounded());
+        assert!(falling.airtime() > 0.0);
+        assert!(falling.can_jump());
+        for _ in 0..6 {
+            app.update();
+        }
+        assert!(!grounded(&app).can_jump());
+
+        // back on the ground, jumping uses up the coyote time
+        teleport(&mut app, Vec3::new(0.0, 5.5, 0.0));
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(grounded(&app).is_grounded());
+        teleport(&mut app, Vec3::new(0.0, 50.0, 0.0));
+        app.update();
+        let mut jumping = app.world.get_mut::<Grounded>(body).unwrap();
+        assert!(jumping.can_jump());
+        jumping.jump();
+        assert!(!jumping.can_jump());
     }
 }
@@ -0,0 +1,852 @@
+//! Spatial queries against the colliders of the `BroadPhase`.
+//!
+//! The queries see the colliders as they were in the last broad phase, which
+//! runs after the physics step and before the solver of every step.  Sensors
+//! are only hit by queries with a `QueryFilter` that allows them.
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+
+use bevy::math::*;
+use bevy::prelude::*;
+
+use super::collision::closest_point_segment;
+use super::*;
+
+/// A hit of a ray with a `Shape`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// The entity of the `RigidBody` that was hit.
+    pub body: Entity,
+    /// The entity of the `Shape` that was hit.
+    pub shape: Entity,
+    /// The point where the ray entered the shape.
+    pub point: Vec3,
+    /// The normal of the shape at the point.
+    ///
+    /// Rays that start inside of a shape hit it at their origin with a normal opposite to their direction.
+    pub normal: Vec3,
+    /// The distance from the origin of the ray to the point.
+    pub distance: f32,
+}
+
+/// A hit of a swept `Shape` with another `Shape`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeHit {
+    /// The entity of the `RigidBody` that was hit.
+    pub body: Entity,
+    /// The entity of the `Shape` that was hit.
+    pub shape: Entity,
+    /// The time of impact, from `0.0` at the start to `1.0` at the end of the sweep.
+    pub time: f32,
+    /// The point where the shapes touch.
+    pub point: Vec3,
+    /// The normal of the hit shape at the point, pointing towards the swept shape.
+    pub normal: Vec3,
+}
+
+/// A `Shape` that contains a point or overlaps another shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Overlap {
+    /// The entity of the `RigidBody` of the shape.
+    pub body: Entity,
+    /// The entity of the shape.
+    pub shape: Entity,
+}
+
+impl BroadPhase {
+    /// Returns the first shape hit by a ray within `max_distance`.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+        self.raycast_filtered(origin, direction, max_distance, &QueryFilter::default())
+    }
+
+    /// Returns the first shape allowed by `filter` hit by a ray within `max_distance`.
+    pub fn raycast_filtered(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        self.ray_hits(origin, direction, max_distance, filter)
+            .min_by(|a, b| compare_hits(a, b))
+    }
+
+    /// Returns all shapes hit by a ray within `max_distance`, sorted by distance.
+    pub fn raycast_all(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Vec<RayHit> {
+        self.raycast_all_filtered(origin, direction, max_distance, &QueryFilter::default())
+    }
+
+    /// Returns all shapes allowed by `filter` hit by a ray within `max_distance`, sorted by distance.
+    pub fn raycast_all_filtered(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Vec<RayHit> {
+        let mut hits: Vec<_> = self
+            .ray_hits(origin, direction, max_distance, filter)
+            .collect();
+        hits.sort_by(compare_hits);
+        hits
+    }
+
+    fn ray_hits<'a>(
+        &'a self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        filter: &'a QueryFilter,
+    ) -> impl Iterator<Item = RayHit> + 'a {
+        let length = direction.length();
+        let direction = if length > f32::EPSILON {
+            direction / length
+        } else {
+            Vec3::zero()
+        };
+        let max_distance = if length > f32::EPSILON {
+            max_distance.max(0.0)
+        } else {
+            0.0
+        };
+        let end = origin + direction * max_distance;
+        let bounding_box = BoundingBox::new(origin.min(end), origin.max(end));
+        self.intersecting(&bounding_box)
+            .filter(move |collider| filter.allows(collider.body, collider.layers, collider.sensor))
+            .filter_map(move |collider| {
+                let (distance, normal) = raycast(collider, origin, direction, max_distance)?;
+                Some(RayHit {
+                    body: collider.body,
+                    shape: collider.shape,
+                    point: origin + direction * distance,
+                    normal,
+                    distance,
+                })
+            })
+    }
+
+    /// Sweeps a shape from one position and rotation to another and returns the first shape it hits.
+    ///
+    /// The rotation is interpolated linearly.  A shape that already overlaps another
+    /// shape at the start of the sweep hits it at time zero.
+    pub fn shape_cast(
+        &self,
+        shape: &Shape,
+        from: (Vec3, Quat),
+        to: (Vec3, Quat),
+    ) -> Option<ShapeHit> {
+        self.shape_cast_filtered(shape, from, to, &QueryFilter::default())
+    }
+
+    /// Sweeps a shape from one position and rotation to another and returns the first shape
+    /// allowed by `filter` it hits.
+    pub fn shape_cast_filtered(
+        &self,
+        shape: &Shape,
+        from: (Vec3, Quat),
+        to: (Vec3, Quat),
+        filter: &QueryFilter,
+    ) -> Option<ShapeHit> {
+        let local = Transform::from_matrix(shape.local);
+        let extent = shape.extent();
+        let geometry = shape.geometry();
+        let reach = Vec3::splat(local.translation.length() + extent.length());
+        let bounding_box = BoundingBox::new(from.0.min(to.0) - reach, from.0.max(to.0) + reach);
+        let linear = to.0 - from.0;
+        // a normalized lerp turns up to 4/π times faster than on average, halfway
+        let angle = 2.0 * from.1.dot(to.1).abs().min(1.0).acos();
+        let angular = reach.x() * angle * 4.0 / PI;
+
+        let mut best: Option<ShapeHit> = None;
+        for collider in self.intersecting(&bounding_box) {
+            if !filter.allows(collider.body, collider.layers, collider.sensor) {
+                continue;
+            }
+            // the swept collider borrows the entities of the other one, they are never read
+            let swept = |time: f32| {
+                let mut transform = Transform::from_translation(from.0 + (to.0 - from.0) * time);
+                transform.rotation = from.1.lerp(to.1, time).normalize();
+                Obb::new(
+                    Status::Semikinematic,
+                    collider.body,
+                    collider.shape,
+                    local,
+                    transform,
+                    extent,
+                    geometry.clone(),
+                    shape.layers,
+                )
+            };
+            let at = |time: f32| collision::collide(collider, &swept(time));
+            let (time, manifold) = match at(0.0) {
+                Some(manifold) => (0.0, manifold),
+                None => match collision::sweep(&swept, collider, linear, angular, at) {
+                    Some(hit) => hit,
+                    None => continue,
+                },
+            };
+            if best.map_or(false, |best| {
+                (best.time, best.shape) <= (time, collider.shape)
+            }) {
+                continue;
+            }
+            let mut point = Vec3::zero();
+            for contact in &manifold.contacts {
+                point += contact.position;
+            }
+            best = Some(ShapeHit {
+                body: collider.body,
+                shape: collider.shape,
+                time,
+                point: point / manifold.contacts.len().max(1) as f32,
+                normal: manifold.normal,
+            });
+        }
+        best
+    }
+
+    /// Returns all shapes that contain a point.
+    pub fn point_query(&self, point: Vec3) -> Vec<Overlap> {
+        self.point_query_filtered(point, &QueryFilter::default())
+    }
+
+    /// Returns all shapes allowed by `filter` that contain a point.
+    pub fn point_query_filtered(&self, point: Vec3, filter: &QueryFilter) -> Vec<Overlap> {
+        let bounding_box = BoundingBox::new(point, point);
+        self.intersecting(&bounding_box)
+            .filter(|collider| filter.allows(collider.body, collider.layers, collider.sensor))
+            // a ray without length only hits the shapes it starts in
+            .filter(|collider| raycast(collider, point, Vec3::zero(), 0.0).is_some())
+            .map(|collider| Overlap {
+                body: collider.body,
+                shape: collider.shape,
+            })
+            .collect()
+    }
+
+    /// Returns all shapes that overlap a shape at a position and rotation.
+    pub fn overlap(&self, shape: &Shape, position: Vec3, rotation: Quat) -> Vec<Overlap> {
+        self.overlap_filtered(shape, position, rotation, &QueryFilter::default())
+    }
+
+    /// Returns all shapes allowed by `filter` that overlap a shape at a position and rotation.
+    pub fn overlap_filtered(
+        &self,
+        shape: &Shape,
+        position: Vec3,
+        rotation: Quat,
+        filter: &QueryFilter,
+    ) -> Vec<Overlap> {
+        let local = Transform::from_matrix(shape.local);
+        let extent = shape.extent();
+        let geometry = shape.geometry();
+        let reach = Vec3::splat(local.translation.length() + extent.length());
+        let bounding_box = BoundingBox::new(position - reach, position + reach);
+
+        let mut overlaps = Vec::new();
+        for collider in self.intersecting(&bounding_box) {
+            if !filter.allows(collider.body, collider.layers, collider.sensor) {
+                continue;
+            }
+            // the collider borrows the entities of the other one, they are never read
+            let mut transform = Transform::from_translation(position);
+            transform.rotation = rotation;
+            let obb = Obb::new(
+                Status::Semikinematic,
+                collider.body,
+                collider.shape,
+                local,
+                transform,
+                extent,
+                geometry.clone(),
+                shape.layers,
+            );
+            if collision::overlaps(collider, &obb) {
+                overlaps.push(Overlap {
+                    body: collider.body,
+                    shape: collider.shape,
+                });
+            }
+        }
+        overlaps
+    }
+}
+
+/// Orders hits by distance, and hits at the same distance by their shape.
+fn compare_hits(a: &RayHit, b: &RayHit) -> Ordering {
+    a.distance
+        .partial_cmp(&b.distance)
+        .unwrap_or(Ordering::Equal)
+        .then(a.shape.cmp(&b.shape))
+}
+
+/// Keeps the earlier of two hits.
+fn earliest(best: Option<(f32, Vec3)>, hit: Option<(f32, Vec3)>) -> Option<(f32, Vec3)> {
+    match (best, hit) {
+        (Some(best), Some(hit)) if hit.0 < best.0 => Some(hit),
+        (None, hit) => hit,
+        (best, _) => best,
+    }
+}
+
+/// Casts a ray with a normalized direction against a collider.
+///
+/// Returns the distance to the hit and the world space normal at the hit.
+fn raycast(obb: &Obb, origin: Vec3, direction: Vec3, max: f32) -> Option<(f32, Vec3)> {
+    let tx = obb.world();
+    let o = tx.rotation.conjugate() * (origin - tx.translation);
+    let d = tx.rotation.conjugate() * direction;
+    let (distance, normal) = match obb.geometry {
+        Geometry::Cuboid => ray_box(-obb.extent, obb.extent, o, d, max)?,
+        Geometry::Sphere { radius } => ray_capsule(Vec3::zero(), Vec3::zero(), radius, o, d, max)?,
+        Geometry::Capsule {
+            half_height,
+            radius,
+        } => {
+            let axis = Vec3::unit_y() * half_height;
+            ray_capsule(-axis, axis, radius, o, d, max)?
+        }
+        Geometry::Cylinder {
+            half_height,
+            radius,
+        } => ray_cylinder(half_height, radius, o, d, max)?,
+        Geometry::ConvexHull(ref hull) => ray_hull(hull, o, d, max)?,
+        Geometry::Terrain(ref terrain) => ray_terrain(terrain, o, d, max)?,
+    };
+    Some((distance, tx.rotation * normal))
+}
+
+/// Casts a ray against an axis aligned box.
+fn ray_box(min: Vec3, max: Vec3, o: Vec3, d: Vec3, max_distance: f32) -> Option<(f32, Vec3)> {
+    let mut near = 0.0;
+    let mut far = max_distance;
+    let mut normal = -d;
+    let axes = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+    for (i, &axis) in axes.iter().enumerate() {
+        if d[i].abs() <= f32::EPSILON {
+            if o[i] < min[i] || o[i] > max[i] {
+                return None;
+            }
+            continue;
+        }
+        let inverse = d[i].recip();
+        let mut enter = (min[i] - o[i]) * inverse;
+        let mut exit = (max[i] - o[i]) * inverse;
+        let mut n = -axis;
+        if enter > exit {
+            std::mem::swap(&mut enter, &mut exit);
+            n = axis;
+        }
+        if enter > near {
+            near = enter;
+            normal = n;
+        }
+        far = exit.min(far);
+        if near > far {
+            return None;
+        }
+    }
+    Some((near, normal))
+}
+
+/// Casts a ray against a sphere.
+fn ray_sphere(center: Vec3, radius: f32, o: Vec3, d: Vec3, max: f32) -> Option<(f32, Vec3)> {
+    let m = o - center;
+    let b = m.dot(d);
+    let c = m.dot(m) - radius * radius;
+    if b > 0.0 && c > 0.0 {
+        return None;
+    }
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time = -b - discriminant.sqrt();
+    if time > max {
+        return None;
+    }
+    Some((time, (m + d * time) / radius))
+}
+
+/// Casts a ray against the side of an infinite cylinder around the axis through `p`,
+/// limited to `0.0..=length` along the axis.
+fn ray_tube(
+    p: Vec3,
+    axis: Vec3,
+    length: f32,
+    radius: f32,
+    o: Vec3,
+    d: Vec3,
+    max: f32,
+) -> Option<(f32, Vec3)> {
+    let m = o - p;
+    let md = m - axis * m.dot(axis);
+    let dd = d - axis * d.dot(axis);
+    let a = dd.dot(dd);
+    if a <= f32::EPSILON {
+        return None;
+    }
+    let b = md.dot(dd);
+    let c = md.dot(md) - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / a;
+    let along = (m + d * time).dot(axis);
+    if time < 0.0 || time > max || along < 0.0 || along > length {
+        return None;
+    }
+    Some((time, (md + dd * time) / radius))
+}
+
+/// Casts a ray against a capsule, a sphere being a capsule with a single point.
+fn ray_capsule(p: Vec3, q: Vec3, radius: f32, o: Vec3, d: Vec3, max: f32) -> Option<(f32, Vec3)> {
+    if (o - closest_point_segment(p, q, o)).length_squared() <= radius * radius {
+        return Some((0.0, -d));
+    }
+
+    let mut best = ray_sphere(p, radius, o, d, max);
+    best = earliest(best, ray_sphere(q, radius, o, d, max));
+    let axis = q - p;
+    let length = axis.length();
+    if length > f32::EPSILON {
+        best = earliest(best, ray_tube(p, axis / length, length, radius, o, d, max));
+    }
+    best
+}
+
+/// Casts a ray against a cylinder along the y axis.
+fn ray_cylinder(half_height: f32, radius: f32, o: Vec3, d: Vec3, max: f32) -> Option<(f32, Vec3)> {
+    let radial = |v: Vec3| Vec3::new(v.x(), 0.0, v.z());
+    if o.y().abs() <= half_height && radial(o).length_squared() <= radius * radius {
+        return Some((0.0, -d));
+    }
+
+    let axis = Vec3::unit_y();
+    let p = -axis * half_height;
+    let mut best = ray_tube(p, axis, half_height * 2.0, radius, o, d, max);
+    for &n in &[axis, -axis] {
+        let speed = n.dot(d);
+        if speed >= 0.0 {
+            continue;
+        }
+        let time = (half_height - n.dot(o)) / speed;
+        let point = o + d * time;
+        if time >= 0.0 && time <= max && radial(point).length_squared() <= radius * radius {
+            best = earliest(best, Some((time, n)));
+        }
+    }
+    best
+}
+
+/// Casts a ray against a convex hull, clipped by the planes of its faces.
+fn ray_hull(hull: &ConvexHull, o: Vec3, d: Vec3, max: f32) -> Option<(f32, Vec3)> {
+    let mut near = 0.0;
+    let mut far = max;
+    let mut normal = -d;
+    for &(n, offset) in hull.planes() {
+        let distance = offset - n.dot(o);
+        let speed = n.dot(d);
+        if speed == 0.0 {
+            if distance < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let time = distance / speed;
+        if speed < 0.0 {
+            if time > near {
+                near = time;
+                normal = n;
+            }
+        } else {
+            far = time.min(far);
+        }
+        if near > far {
+            return None;
+        }
+    }
+    Some((near, normal))
+}
+
+/// Casts a ray against a triangle from both sides.
+fn ray_triangle([a, b, c]: [Vec3; 3], o: Vec3, d: Vec3, max: f32) -> Option<(f32, Vec3)> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = d.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant.abs() <= f32::EPSILON {
+        return None;
+    }
+    let inverse = determinant.recip();
+    let t = o - a;
+    let u = t.dot(p) * inverse;
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+    let q = t.cross(ab);
+    let v = d.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let time = ac.dot(q) * inverse;
+    if time < 0.0 || time > max {
+        return None;
+    }
+    let normal = ab.cross(ac).normalize();
+    if normal.dot(d) > 0.0 {
+        Some((time, -normal))
+    } else {
+        Some((time, normal))
+    }
+}
+
+/// Casts a ray against the triangles of a terrain.
+fn ray_terrain(terrain: &Terrain, o: Vec3, d: Vec3, max: f32) -> Option<(f32, Vec3)> {
+    let end = o + d * max;
+    let mut best = None;
+    for triangle in terrain.triangles(o.min(end), o.max(end)) {
+        best = earliest(best, ray_triangle(triangle, o, d, max));
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collider(id: u32, shape: Shape, position: Vec3) -> Obb {
+        Obb::new(
+            Status::Static,
+            Entity::from_id(id),
+            Entity::from_id(id + 100),
+            Transform::from_matrix(shape.local),
+            Transform::from_translation(position),
+            shape.extent(),
+            shape.geometry(),
+            CollisionLayers::default(),
+        )
+    }
+
+    fn assert_hit(hit: Option<RayHit>, id: u32, distance: f32, normal: Vec3) {
+        let hit = hit.expect("the ray should hit");
+        assert_eq!(hit.body, Entity::from_id(id));
+        assert_eq!(hit.shape, Entity::from_id(id + 100));
+        assert!(
+            (hit.distance - distance).abs() < 1.0e-3,
+            "distance {}, expected {}",
+            hit.distance,
+            distance
+        );
+        assert!(
+            (hit.normal - normal).length() < 1.0e-3,
+            "normal {:?}, expected {:?}",
+            hit.normal,
+            normal
+        );
+    }
+
+    fn cube() -> Shape {
+        Shape::new(Size3::new(2.0, 2.0, 2.0))
+    }
+
+    fn hull() -> Shape {
+        let mut points = Vec::new();
+        for &x in &[-1.0, 1.0] {
+            for &y in &[-1.0, 1.0] {
+                for &z in &[-1.0, 1.0] {
+                    points.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+        Shape::convex_hull(&points).unwrap()
+    }
+
+    fn terrains() -> Vec<Shape> {
+        let field = HeightField::new(vec![0.0; 9], 3, 3, Vec3::new(2.0, 1.0, 2.0)).unwrap();
+        let vertices = vec![
+            Vec3::new(-2.0, 0.0, -2.0),
+            Vec3::new(2.0, 0.0, -2.0),
+            Vec3::new(2.0, 0.0, 2.0),
+            Vec3::new(-2.0, 0.0, 2.0),
+        ];
+        let mesh = TriMesh::new(vertices, vec![[0, 2, 1], [0, 3, 2]]).unwrap();
+        vec![Shape::heightfield(field), Shape::trimesh(mesh)]
+    }
+
+    #[test]
+    fn raycast_hits_every_shape() {
+        let shapes = vec![
+            cube(),
+            Shape::sphere(1.0),
+            Shape::capsule(2.0, 1.0),
+            Shape::cylinder(2.0, 1.0),
+            hull(),
+        ];
+        let offset = Vec3::new(5.0, 0.0, 0.0);
+        let left = -Vec3::unit_x();
+        for shape in shapes {
+            let broad = BroadPhase::with_colliders(vec![collider(1, shape, offset)]);
+            assert_hit(
+                broad.raycast(Vec3::zero(), Vec3::unit_x(), 10.0),
+                1,
+                4.0,
+                left,
+            );
+            assert_eq!(broad.raycast(Vec3::zero(), Vec3::unit_x(), 3.0), None);
+            assert_eq!(broad.raycast(Vec3::zero(), -Vec3::unit_x(), 10.0), None);
+            assert_eq!(
+                broad.raycast(Vec3::new(0.0, 3.0, 0.0), Vec3::unit_x(), 10.0),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn raycast_hits_caps_and_tops() {
+        let origin = Vec3::new(0.0, 10.0, 0.0);
+        let down = -Vec3::unit_y();
+        let up = Vec3::unit_y();
+        let capsule =
+            BroadPhase::with_colliders(vec![collider(1, Shape::capsule(2.0, 1.0), Vec3::zero())]);
+        assert_hit(capsule.raycast(origin, down, 20.0), 1, 8.0, up);
+        let cylinder =
+            BroadPhase::with_colliders(vec![collider(1, Shape::cylinder(2.0, 1.0), Vec3::zero())]);
+        assert_hit(cylinder.raycast(origin, down, 20.0), 1, 9.0, up);
+    }
+
+    #[test]
+    fn raycast_hits_terrain_from_both_sides() {
+        for terrain in terrains() {
+            let broad = BroadPhase::with_colliders(vec![collider(1, terrain, Vec3::zero())]);
+            let above = Vec3::new(0.3, 5.0, 0.2);
+            let below = Vec3::new(0.3, -5.0, 0.2);
+            let up = Vec3::unit_y();
+            assert_hit(broad.raycast(above, -up, 10.0), 1, 5.0, up);
+            assert_hit(broad.raycast(below, up, 10.0), 1, 5.0, -up);
+            assert_eq!(broad.raycast(above, -up, 4.0), None);
+            assert_eq!(broad.raycast(Vec3::new(3.0, 5.0, 0.2), -up, 10.0), None);
+        }
+    }
+
+    #[test]
+    fn raycast_from_inside_hits_at_origin() {
+        let origin = Vec3::new(5.0, 0.0, 0.0);
+        let broad = BroadPhase::with_colliders(vec![collider(1, cube(), origin)]);
+        let hit = broad.raycast(origin, Vec3::unit_x(), 10.0);
+        assert_hit(hit, 1, 0.0, -Vec3::unit_x());
+        assert_eq!(hit.unwrap().point, origin);
+    }
+
+    #[test]
+    fn raycast_all_sorts_by_distance() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, cube(), Vec3::new(10.0, 0.0, 0.0)),
+            collider(2, cube(), Vec3::new(5.0, 0.0, 0.0)),
+        ]);
+        let hits = broad.raycast_all(Vec3::zero(), Vec3::unit_x(), 20.0);
+        assert_eq!(hits.len(), 2);
+        assert_hit(Some(hits[0]), 2, 4.0, -Vec3::unit_x());
+        assert_hit(Some(hits[1]), 1, 9.0, -Vec3::unit_x());
+        assert_eq!(hits[0].point, Vec3::new(4.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn raycast_skips_filtered_bodies() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, cube(), Vec3::new(10.0, 0.0, 0.0)),
+            collider(2, cube(), Vec3::new(5.0, 0.0, 0.0)),
+        ]);
+        let excluded = QueryFilter::default().with_excluded(Entity::from_id(2));
+        let hit = broad.raycast_filtered(Vec3::zero(), Vec3::unit_x(), 20.0, &excluded);
+        assert_hit(hit, 1, 9.0, -Vec3::unit_x());
+        let hits = broad.raycast_all_filtered(Vec3::zero(), Vec3::unit_x(), 20.0, &excluded);
+        assert_eq!(hits.len(), 1);
+        let masked = QueryFilter::new(0);
+        assert_eq!(
+            broad.raycast_filtered(Vec3::zero(), Vec3::unit_x(), 20.0, &masked),
+            None
+        );
+    }
+
+    fn assert_cast(hit: Option<ShapeHit>, id: u32, time: f32, normal: Vec3) {
+        let hit = hit.expect("the shape should hit");
+        assert_eq!(hit.body, Entity::from_id(id));
+        assert_eq!(hit.shape, Entity::from_id(id + 100));
+        assert!(
+            (hit.time - time).abs() < 1.0e-2,
+            "time {}, expected {}",
+            hit.time,
+            time
+        );
+        assert!(
+            (hit.normal - normal).length() < 1.0e-3,
+            "normal {:?}, expected {:?}",
+            hit.normal,
+            normal
+        );
+    }
+
+    #[test]
+    fn shape_cast_finds_time_of_impact() {
+        let broad = BroadPhase::with_colliders(vec![collider(1, cube(), Vec3::new(6.0, 0.0, 0.0))]);
+        let from = (Vec3::zero(), Quat::identity());
+        let to = (Vec3::new(20.0, 0.0, 0.0), Quat::identity());
+        let left = -Vec3::unit_x();
+        assert_cast(broad.shape_cast(&cube(), from, to), 1, 0.2, left);
+        assert_cast(
+            broad.shape_cast(&Shape::sphere(1.0), from, to),
+            1,
+            0.2,
+            left,
+        );
+        let capsule = Shape::capsule(2.0, 1.0);
+        assert_cast(broad.shape_cast(&capsule, from, to), 1, 0.2, left);
+
+        let away = (Vec3::new(30.0, 0.0, 0.0), Quat::identity());
+        assert_eq!(broad.shape_cast(&cube(), to, away), None);
+        let above = (Vec3::new(0.0, 3.0, 0.0), Quat::identity());
+        let over = (Vec3::new(20.0, 3.0, 0.0), Quat::identity());
+        assert_eq!(broad.shape_cast(&cube(), above, over), None);
+        let inside = (Vec3::new(5.5, 0.0, 0.0), Quat::identity());
+        assert_eq!(broad.shape_cast(&cube(), inside, to).unwrap().time, 0.0);
+    }
+
+    #[test]
+    fn shape_cast_lands_on_terrain() {
+        for terrain in terrains() {
+            let broad = BroadPhase::with_colliders(vec![collider(1, terrain, Vec3::zero())]);
+            let from = (Vec3::new(0.3, 5.0, 0.2), Quat::identity());
+            let to = (Vec3::new(0.3, -5.0, 0.2), Quat::identity());
+            let hit = broad.shape_cast(&Shape::sphere(0.5), from, to);
+            assert_cast(hit, 1, 0.45, Vec3::unit_y());
+        }
+    }
+
+    #[test]
+    fn shape_cast_skips_filtered_bodies() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, cube(), Vec3::new(12.0, 0.0, 0.0)),
+            collider(2, cube(), Vec3::new(6.0, 0.0, 0.0)),
+        ]);
+        let from = (Vec3::zero(), Quat::identity());
+        let to = (Vec3::new(20.0, 0.0, 0.0), Quat::identity());
+        let left = -Vec3::unit_x();
+        assert_cast(broad.shape_cast(&cube(), from, to), 2, 0.2, left);
+        let excluded = QueryFilter::default().with_excluded(Entity::from_id(2));
+        let hit = broad.shape_cast_filtered(&cube(), from, to, &excluded);
+        assert_cast(hit, 1, 0.5, left);
+    }
+
+    fn overlapping(overlaps: Vec<Overlap>) -> Vec<u32> {
+        let mut ids: Vec<_> = overlaps.iter().map(|overlap| overlap.body.id()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn point_query_finds_containing_shapes() {
+        let shapes = vec![
+            cube(),
+            Shape::sphere(1.0),
+            Shape::capsule(2.0, 1.0),
+            Shape::cylinder(2.0, 1.0),
+            hull(),
+        ];
+        for shape in shapes {
+            let broad =
+                BroadPhase::with_colliders(vec![collider(1, shape, Vec3::new(5.0, 0.0, 0.0))]);
+            let overlaps = broad.point_query(Vec3::new(5.0, 0.0, 0.0));
+            assert_eq!(
+                overlaps,
+                vec![Overlap {
+                    body: Entity::from_id(1),
+                    shape: Entity::from_id(101),
+                }]
+            );
+            assert!(broad.point_query(Vec3::new(5.0, 3.0, 0.0)).is_empty());
+            assert!(broad.point_query(Vec3::new(7.5, 0.0, 0.0)).is_empty());
+        }
+    }
+
+    #[test]
+    fn overlap_finds_touched_shapes() {
+        let shapes = vec![
+            cube(),
+            Shape::sphere(1.0),
+            Shape::capsule(2.0, 1.0),
+            Shape::cylinder(2.0, 1.0),
+            hull(),
+        ];
+        let rotation = Quat::identity();
+        for shape in shapes {
+            let broad =
+                BroadPhase::with_colliders(vec![collider(1, shape, Vec3::new(5.0, 0.0, 0.0))]);
+            for query in &[cube(), Shape::sphere(1.0)] {
+                let overlaps = broad.overlap(query, Vec3::new(6.5, 0.0, 0.0), rotation);
+                assert_eq!(overlapping(overlaps), vec![1]);
+                let apart = broad.overlap(query, Vec3::new(8.5, 0.0, 0.0), rotation);
+                assert!(apart.is_empty());
+            }
+        }
+        for terrain in terrains() {
+            let broad = BroadPhase::with_colliders(vec![collider(1, terrain, Vec3::zero())]);
+            let sphere = Shape::sphere(0.5);
+            let overlaps = broad.overlap(&sphere, Vec3::new(0.3, 0.25, 0.2), rotation);
+            assert_eq!(overlapping(overlaps), vec![1]);
+            let apart = broad.overlap(&sphere, Vec3::new(0.3, 1.0, 0.2), rotation);
+            assert!(apart.is_empty());
+        }
+    }
+
+    #[test]
+    fn point_and_overlap_queries_skip_filtered_bodies() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, cube(), Vec3::new(5.0, 0.0, 0.0)),
+            collider(2, Shape::sphere(1.0), Vec3::new(5.0, 0.0, 0.0)),
+        ]);
+        let point = Vec3::new(5.0, 0.0, 0.0);
+        let rotation = Quat::identity();
+        assert_eq!(overlapping(broad.point_query(point)), vec![1, 2]);
+        assert_eq!(
+            overlapping(broad.overlap(&cube(), point, rotation)),
+            vec![1, 2]
+        );
+
+        let excluded = QueryFilter::default().with_excluded(Entity::from_id(2));
+        let overlaps = broad.point_query_filtered(point, &excluded);
+        assert_eq!(overlapping(overlaps), vec![1]);
+        let overlaps = broad.overlap_filtered(&cube(), point, rotation, &excluded);
+        assert_eq!(overlapping(overlaps), vec![1]);
+    }
+
+    #[test]
+    fn queries_only_hit_sensors_when_allowed() {
+        let broad = BroadPhase::with_colliders(vec![
+            collider(1, cube(), Vec3::new(10.0, 0.0, 0.0)),
+            collider(2, cube(), Vec3::new(5.0, 0.0, 0.0)).with_sensor(true),
+        ]);
+        let sensors = QueryFilter::default().with_sensors(true);
+        let left = -Vec3::unit_x();
+        let hit = broad.raycast(Vec3::zero(), Vec3::unit_x(), 20.0);
+        assert_hit(hit, 1, 9.0, left);
+        let hit = broad.raycast_filtered(Vec3::zero(), Vec3::unit_x(), 20.0, &sensors);
+        assert_hit(hit, 2, 4.0, left);
+
+        let from = (Vec3::new(-4.0, 0.0, 0.0), Quat::identity());
+        let to = (Vec3::new(16.0, 0.0, 0.0), Quat::identity());
+        assert_cast(broad.shape_cast(&cube(), from, to), 1, 0.6, left);
+        let hit = broad.shape_cast_filtered(&cube(), from, to, &sensors);
+        assert_cast(hit, 2, 0.3, left);
+
+        let point = Vec3::new(5.0, 0.0, 0.0);
+        let rotation = Quat::identity();
+        assert!(broad.point_query(point).is_empty());
+        let overlaps = broad.point_query_filtered(point, &sensors);
+        assert_eq!(overlapping(overlaps), vec![2]);
+        assert!(broad.overlap(&cube(), point, rotation).is_empty());
+        let overlaps = broad.overlap_filtered(&cube(), point, rotation, &sensors);
+        assert_eq!(overlapping(overlaps), vec![2]);
+    }
+}
@@ -0,0 +1,387 @@
+//! Static terrain colliders, i.e. triangle meshes and heightfields.
+//!
+//! Both of them are only ever collided triangle by triangle, so only the
+//! triangles close to the other collider are tested.
+
+use std::fmt;
+use std::sync::Arc;
+
+use bevy::math::*;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use rstar::{RTree, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+
+use crate::broad::NPoint;
+
+/// A triangle of a `TriMesh`, stored in its R*-tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Triangle {
+    index: usize,
+    min: Vec3,
+    max: Vec3,
+}
+
+impl RTreeObject for Triangle {
+    type Envelope = AABB<NPoint<Vec3>>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_corners(NPoint::from(self.min), NPoint::from(self.max))
+    }
+}
+
+struct TriMeshData {
+    vertices: Vec<Vec3>,
+    indices: Vec<[u32; 3]>,
+    tree: RTree<Triangle>,
+    min: Vec3,
+    max: Vec3,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawTriMesh {
+    vertices: Vec<Vec3>,
+    indices: Vec<[u32; 3]>,
+}
+
+/// A static triangle mesh, for level geometry.
+///
+/// The triangles are kept in an R*-tree, so that large meshes are cheap to
+/// collide with.  Cloning a `TriMesh` is cheap, as the data is shared.
+///
+/// Triangles are one-sided with respect to their winding: the front face is
+/// the one whose vertices appear counter-clockwise.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawTriMesh", into = "RawTriMesh")]
+pub struct TriMesh {
+    data: Arc<TriMeshData>,
+}
+
+impl TriMesh {
+    /// Returns a new `TriMesh` from a list of vertices and a list of triangles indexing them.
+    ///
+    /// Returns `None` if there are no triangles or an index is out of bounds.
+    pub fn new(vertices: Vec<Vec3>, indices: Vec<[u32; 3]>) -> Option<Self> {
+        if indices.is_empty()
+            || indices
+                .iter()
+                .flatten()
+                .any(|&index| index as usize >= vertices.len())
+        {
+            return None;
+        }
+
+        let triangles = indices
+            .iter()
+            .enumerate()
+            .map(|(index, triangle)| {
+                let [a, b, c] = [
+                    vertices[triangle[0] as usize],
+                    vertices[triangle[1] as usize],
+                    vertices[triangle[2] as usize],
+                ];
+                Triangle {
+                    index,
+                    min: a.min(b).min(c),
+                    max: a.max(b).max(c),
+                }
+            })
+            .collect::<Vec<_>>();
+        let min = triangles
+            .iter()
+            .fold(Vec3::splat(f32::INFINITY), |min, t| min.min(t.min));
+        let max = triangles
+            .iter()
+            .fold(Vec3::splat(f32::NEG_INFINITY), |max, t| max.max(t.max));
+
+        let data = TriMeshData {
+            vertices,
+            indices,
+            tree: RTree::bulk_load(triangles),
+            min,
+            max,
+        };
+        Some(Self {
+            data: Arc::new(data),
+        })
+    }
+
+    /// Returns a new `TriMesh` with the triangles of a `Mesh`.
+    ///
+    /// Meshes without indices are treated as a list of triangles.
+    /// Returns `None` if the mesh has no positions or no triangles.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        let vertices: Vec<Vec3> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+            VertexAttributeValues::Float3(positions) => {
+                positions.iter().map(|&p| Vec3::from(p)).collect()
+            }
+            _ => return None,
+        };
+        let flat: Vec<u32> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as u32).collect(),
+            Some(Indices::U32(indices)) => indices.clone(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        let indices = flat
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        Self::new(vertices, indices)
+    }
+
+    /// Get the vertices of this mesh.
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.data.vertices
+    }
+
+    /// Get the triangles of this mesh, as indices into `vertices`.
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.data.indices
+    }
+
+    /// Returns the triangles that intersect the box between `min` and `max`, in local space.
+    pub(crate) fn triangles(&self, min: Vec3, max: Vec3) -> Vec<[Vec3; 3]> {
+        let envelope = AABB::from_corners(NPoint::from(min), NPoint::from(max));
+        self.data
+            .tree
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|triangle| {
+                let indices = self.data.indices[triangle.index];
+                [
+                    self.data.vertices[indices[0] as usize],
+                    self.data.vertices[indices[1] as usize],
+                    self.data.vertices[indices[2] as usize],
+                ]
+            })
+            .collect()
+    }
+
+    /// Returns the half extents of the box bounding this mesh around the local origin.
+    pub(crate) fn extent(&self) -> Vec3 {
+        self.data.min.abs().max(self.data.max.abs())
+    }
+}
+
+impl fmt::Debug for TriMesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TriMesh")
+            .field("vertices", &self.data.vertices.len())
+            .field("triangles", &self.data.indices.len())
+            .finish()
+    }
+}
+
+impl PartialEq for TriMesh {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+            || (self.data.vertices == other.data.vertices
+                && self.data.indices == other.data.indices)
+    }
+}
+
+impl std::convert::TryFrom<RawTriMesh> for TriMesh {
+    type Error = &'static str;
+
+    fn try_from(raw: RawTriMesh) -> Result<Self, Self::Error> {
+        Self::new(raw.vertices, raw.indices).ok_or("invalid triangle mesh")
+    }
+}
+
+impl From<TriMesh> for RawTriMesh {
+    fn from(mesh: TriMesh) -> Self {
+        Self {
+            vertices: mesh.data.vertices.clone(),
+            indices: mesh.data.indices.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawHeightField {
+    heights: Vec<f32>,
+    columns: usize,
+    rows: usize,
+    scale: Vec3,
+}
+
+/// A static heightfield, for terrain.
+///
+/// The heights are laid out in a grid of `columns` along the x axis and
+/// `rows` along the z axis, stored row by row, and the grid is centered at the
+/// local origin.  `scale` is the size of a single cell along x and z, and the
+/// factor applied to the heights along y.  Cloning a `HeightField` is cheap,
+/// as the heights are shared.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawHeightField", into = "RawHeightField")]
+pub struct HeightField {
+    heights: Arc<Vec<f32>>,
+    columns: usize,
+    rows: usize,
+    scale: Vec3,
+    min: f32,
+    max: f32,
+}
+
+impl HeightField {
+    /// Returns a new `HeightField` from a grid of heights.
+    ///
+    /// Returns `None` if the grid has fewer than two columns or rows, or if the
+    /// number of heights doesn't match the size of the grid.
+    pub fn new(heights: Vec<f32>, columns: usize, rows: usize, scale: Vec3) -> Option<Self> {
+        if columns < 2 || rows < 2 || heights.len() != columns * rows {
+            return None;
+        }
+
+        let min = heights.iter().copied().fold(f32::INFINITY, f32::min) * scale.y();
+        let max = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max) * scale.y();
+        Some(Self {
+            heights: Arc::new(heights),
+            columns,
+            rows,
+            scale,
+            min: min.min(max),
+            max: max.max(min),
+        })
+    }
+
+    /// Get the number of columns, along the x axis.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Get the number of rows, along the z axis.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Get the scale of the cells and the heights.
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    /// Get the unscaled height at a column and a row.
+    pub fn height(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.columns + column]
+    }
+
+    fn origin(&self) -> Vec3 {
+        Vec3::new(
+            (self.columns - 1) as f32 * self.scale.x() * -0.5,
+            0.0,
+            (self.rows - 1) as f32 * self.scale.z() * -0.5,
+        )
+    }
+
+    fn vertex(&self, column: usize, row: usize) -> Vec3 {
+        self.origin()
+            + Vec3::new(
+                column as f32 * self.scale.x(),
+                self.height(column, row) * self.scale.y(),
+                row as f32 * self.scale.z(),
+            )
+    }
+
+    /// Returns the triangles that intersect the box between `min` and `max`, in local space.
+    pub(crate) fn triangles(&self, min: Vec3, max: Vec3) -> Vec<[Vec3; 3]> {
+        let mut triangles = Vec::new();
+        if max.y() < self.min || min.y() > self.max {
+            return triangles;
+        }
+
+        let origin = self.origin();
+        let cell = |x: f32, origin: f32, scale: f32, count: usize| {
+            let c = ((x - origin) / scale).floor();
+            c.max(0.0).min((count - 1) as f32) as usize
+        };
+        let c0 = cell(min.x(), origin.x(), self.scale.x(), self.columns - 1);
+        let c1 = cell(max.x(), origin.x(), self.scale.x(), self.columns - 1);
+        let r0 = cell(min.z(), origin.z(), self.scale.z(), self.rows - 1);
+        let r1 = cell(max.z(), origin.z(), self.scale.z(), self.rows - 1);
+
+        for row in r0..=r1 {
+            for column in c0..=c1 {
+                let v00 = self.vertex(column, row);
+                let v10 = self.vertex(column + 1, row);
+                let v01 = self.vertex(column, row + 1);
+                let v11 = self.vertex(column + 1, row + 1);
+
+                let low = v00.y().min(v10.y()).min(v01.y()).min(v11.y());
+                let high = v00.y().max(v10.y()).max(v01.y()).max(v11.y());
+                if max.y() < low || min.y() > high {
+                    continue;
+                }
+
+                // wound counter-clockwise when seen from above
+                triangles.push([v00, v01, v10]);
+                triangles.push([v10, v01, v11]);
+            }
+        }
+        triangles
+    }
+
+    /// Returns the half extents of the box bounding this heightfield around the local origin.
+    pub(crate) fn extent(&self) -> Vec3 {
+        let origin = self.origin();
+        Vec3::new(
+            origin.x().abs(),
+            self.min.abs().max(self.max.abs()),
+            origin.z().abs(),
+        )
+    }
+}
+
+impl fmt::Debug for HeightField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeightField")
+            .field("columns", &self.columns)
+            .field("rows", &self.rows)
+            .field("scale", &self.scale)
+            .finish()
+    }
+}
+
+impl PartialEq for HeightField {
+    fn eq(&self, other: &Self) -> bool {
+        self.columns == other.columns
+            && self.rows == other.rows
+            && self.scale == other.scale
+            && (Arc::ptr_eq(&self.heights, &other.heights) || self.heights == other.heights)
+    }
+}
+
+impl std::convert::TryFrom<RawHeightField> for HeightField {
+    type Error = &'static str;
+
+    fn try_from(raw: RawHeightField) -> Result<Self, Self::Error> {
+        Self::new(raw.heights, raw.columns, raw.rows, raw.scale).ok_or("invalid heightfield")
+    }
+}
+
+impl From<HeightField> for RawHeightField {
+    fn from(field: HeightField) -> Self {
+        Self {
+            heights: field.heights.to_vec(),
+            columns: field.columns,
+            rows: field.rows,
+            scale: field.scale,
+        }
+    }
+}
+
+/// A static terrain collider.
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terrain {
+    TriMesh(TriMesh),
+    HeightField(HeightField),
+}
+
+impl Terrain {
+    /// Returns the triangles that intersect the box between `min` and `max`, in local space.
+    pub(crate) fn triangles(&self, min: Vec3, max: Vec3) -> Vec<[Vec3; 3]> {
+        match self {
+            Terrain::TriMesh(mesh) => mesh.triangles(min, max),
+            Terrain::HeightField(field) => field.triangles(min, max),
+        }
+    }
+}
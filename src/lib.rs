@@ -97,23 +97,32 @@ pub mod dim3;
 pub mod prelude2d {
     //! This module re-exports all the things you might need for 2d physics
     //! simulation.
-    pub use crate::common::{GlobalFriction, Mass, Status};
+    pub use crate::common::{
+        CollisionLayers, CombineRule, GlobalFriction, Mass, PhysicsMaterial, QueryFilter, Status,
+    };
     pub use crate::dim2::{
-        AngularTolerance, BroadPhase, FixedJoint, FixedJointBehaviour, GlobalGravity, GlobalStep,
-        GlobalUp, JointBehaviour, Manifold, MechanicalJoint, MechanicalJointBehaviour,
-        Physics2dPlugin, RigidBody, RotationMode, Shape, Size2, SpringJoint, SpringJointBehaviour,
-        TranslationMode,
+        AngularTolerance, BroadPhase, CollisionEnded, CollisionOngoing, CollisionStarted, Contact,
+        FixedJoint, FixedJointBehaviour, FixedTimestep, GlobalGravity, GlobalStep, GlobalUp,
+        Grounded, JointBehaviour, Manifold, MechanicalJoint, MechanicalJointBehaviour, OneWay,
+        Overlap, Physics2dPlugin, PhysicsSchedule, RayHit, RestingSpeed, RigidBody, RotationMode,
+        Shape, ShapeHit, ShapeKind, Size2, SpringJoint, SpringJointBehaviour, StateHash, TileMap,
+        TranslationMode, TriggerContact, TriggerEntered, TriggerExited, Triggers,
     };
 }
 
 pub mod prelude3d {
     //! This module re-exports all the things you might need for 3d physics
     //! simulation.
-    pub use crate::common::{GlobalFriction, Mass, Status, Vec3Ext};
+    pub use crate::common::{
+        CollisionLayers, CombineRule, GlobalFriction, Mass, PhysicsMaterial, QueryFilter, Status,
+        Vec3Ext,
+    };
     pub use crate::dim3::{
-        AngularTolerance, BroadPhase, FixedJoint, FixedJointBehaviour, GlobalGravity, GlobalStep,
-        GlobalUp, Joint, JointBehaviour, Manifold, MechanicalJoint, MechanicalJointBehaviour,
-        Physics3dPlugin, RigidBody, Shape, Size3, SpringJoint, SpringJointBehaviour, Up,
-        UpRotation,
+        AngularTolerance, BroadPhase, CollisionEnded, CollisionOngoing, CollisionStarted, Contact,
+        ConvexHull, FixedJoint, FixedJointBehaviour, FixedTimestep, GlobalGravity, GlobalStep,
+        GlobalUp, Grounded, HeightField, Joint, JointBehaviour, Manifold, MechanicalJoint,
+        MechanicalJointBehaviour, Overlap, Physics3dPlugin, PhysicsSchedule, RayHit, RestingSpeed,
+        RigidBody, Shape, ShapeHit, ShapeKind, Size3, SpringJoint, SpringJointBehaviour, StateHash,
+        TriMesh, TriggerContact, TriggerEntered, TriggerExited, Triggers, Up, UpRotation,
     };
 }